@@ -0,0 +1,231 @@
+use super::config;
+use crate::errors::AppError;
+use crate::models::{FileChunks, FilePart};
+use crate::utils::config::{BudgetPolicy, EditFormat, ResolvedStage, Stage};
+
+/// Rough number of characters per token for code and English text.
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Estimates the number of tokens `text` will use.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Estimates the number of tokens `file_chunks` use once serialized into the prompt.
+pub fn estimate_file_tokens(file_chunks: &[FileChunks]) -> usize {
+    file_chunks.iter().map(estimate_file_chunks_tokens).sum()
+}
+
+fn estimate_file_chunks_tokens(file_chunks: &FileChunks) -> usize {
+    estimate_tokens(&serde_json::to_string(file_chunks).unwrap_or_default())
+}
+
+fn estimate_part_tokens(file_path: &str, part: &FilePart) -> usize {
+    estimate_tokens(file_path) + estimate_tokens(&serde_json::to_string(part).unwrap_or_default())
+}
+
+/// Tokens left for file content in a request of `stage`, once the prompts
+/// and the reserved response tokens are accounted for.
+pub fn available_file_tokens(
+    stage: Stage,
+    edit_format: EditFormat,
+    resolved: &ResolvedStage,
+    user_system_prompt: &str,
+    user_prompt: &str,
+) -> usize {
+    let (system_prompt, important_text) = config::stage_prompts(stage, edit_format);
+    let overhead = estimate_tokens(system_prompt)
+        + estimate_tokens(important_text)
+        + estimate_tokens(user_system_prompt)
+        + estimate_tokens(user_prompt);
+
+    resolved
+        .context_budget
+        .saturating_sub(overhead + resolved.max_tokens as usize)
+}
+
+/// Checks `file_chunks` against the `available` token budget and returns the
+/// batches to send, one request each.
+///
+/// Within budget, or with `BudgetPolicy::Warn`, everything goes in a single
+/// batch. `BudgetPolicy::Fail` refuses to send an oversized prompt, and
+/// `BudgetPolicy::Split` packs whole files (or, for files that don't fit on
+/// their own, runs of parts) into as many batches as needed.
+pub fn plan_batches(
+    file_chunks: &[FileChunks],
+    available: usize,
+    policy: BudgetPolicy,
+) -> Result<Vec<Vec<FileChunks>>, AppError> {
+    let estimated = estimate_file_tokens(file_chunks);
+    if estimated <= available || policy == BudgetPolicy::Warn {
+        return Ok(vec![file_chunks.to_vec()]);
+    }
+    if policy == BudgetPolicy::Fail {
+        return Err(budget_exceeded(estimated, available));
+    }
+
+    let mut batches = Vec::new();
+    let mut current: Vec<FileChunks> = Vec::new();
+    let mut current_tokens = 0;
+
+    for file in file_chunks {
+        let file_tokens = estimate_file_chunks_tokens(file);
+        if file_tokens <= available {
+            if current_tokens + file_tokens > available && !current.is_empty() {
+                batches.push(std::mem::take(&mut current));
+                current_tokens = 0;
+            }
+            current.push(file.clone());
+            current_tokens += file_tokens;
+            continue;
+        }
+
+        // The file doesn't fit on its own, so spread its parts over batches
+        for part in &file.parts {
+            let part_tokens = estimate_part_tokens(&file.file_path, part);
+            if part_tokens > available {
+                return Err(AppError::ContextBudgetExceeded(format!(
+                    "part {} of {} alone needs ~{} tokens but only {} are available; \
+                     lower the chunk size or raise the context budget",
+                    part.part_id, file.file_path, part_tokens, available
+                )));
+            }
+            if current_tokens + part_tokens > available && !current.is_empty() {
+                batches.push(std::mem::take(&mut current));
+                current_tokens = 0;
+            }
+            match current.last_mut() {
+                Some(last) if last.file_path == file.file_path => last.parts.push(part.clone()),
+                _ => current.push(FileChunks {
+                    file_path: file.file_path.clone(),
+                    parts: vec![part.clone()],
+                }),
+            }
+            current_tokens += part_tokens;
+        }
+    }
+
+    if !current.is_empty() {
+        batches.push(current);
+    }
+
+    Ok(batches)
+}
+
+/// Error for a prompt of `estimated` tokens that doesn't fit in `available`.
+pub fn budget_exceeded(estimated: usize, available: usize) -> AppError {
+    AppError::ContextBudgetExceeded(format!(
+        "the files need ~{} tokens but only {} are available",
+        estimated, available
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, part_sizes: &[usize]) -> FileChunks {
+        FileChunks {
+            file_path: path.to_string(),
+            parts: part_sizes
+                .iter()
+                .enumerate()
+                .map(|(index, size)| FilePart {
+                    part_id: index + 1,
+                    content: "x".repeat(*size),
+                })
+                .collect(),
+        }
+    }
+
+    fn part_ids(batch: &[FileChunks]) -> Vec<(String, Vec<usize>)> {
+        batch
+            .iter()
+            .map(|file| {
+                (
+                    file.file_path.clone(),
+                    file.parts.iter().map(|part| part.part_id).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn estimates_four_characters_per_token() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        assert_eq!(estimate_tokens("éééé"), 1);
+    }
+
+    #[test]
+    fn sends_everything_at_once_within_budget() {
+        let files = [file("a.rs", &[40]), file("b.rs", &[40])];
+        let batches = plan_batches(&files, 1000, BudgetPolicy::Fail).unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].len(), 2);
+    }
+
+    #[test]
+    fn warn_sends_an_oversized_prompt_anyway() {
+        let files = [file("a.rs", &[4000])];
+        let batches = plan_batches(&files, 10, BudgetPolicy::Warn).unwrap();
+        assert_eq!(batches.len(), 1);
+    }
+
+    #[test]
+    fn fail_refuses_an_oversized_prompt() {
+        let files = [file("a.rs", &[4000])];
+        assert!(matches!(
+            plan_batches(&files, 10, BudgetPolicy::Fail),
+            Err(AppError::ContextBudgetExceeded(_))
+        ));
+    }
+
+    #[test]
+    fn split_packs_whole_files_into_batches() {
+        let files = [
+            file("a.rs", &[300]),
+            file("b.rs", &[300]),
+            file("c.rs", &[300]),
+        ];
+        let one_file = estimate_file_tokens(&files[..1]);
+        let batches = plan_batches(&files, one_file * 2, BudgetPolicy::Split).unwrap();
+        assert_eq!(
+            batches
+                .iter()
+                .map(|batch| part_ids(batch))
+                .collect::<Vec<_>>(),
+            vec![
+                vec![("a.rs".to_string(), vec![1]), ("b.rs".to_string(), vec![1])],
+                vec![("c.rs".to_string(), vec![1])],
+            ]
+        );
+    }
+
+    #[test]
+    fn split_spreads_the_parts_of_a_file_too_big_on_its_own() {
+        let files = [file("big.rs", &[300, 300, 300])];
+        let one_part = estimate_part_tokens("big.rs", &files[0].parts[0]);
+        let batches = plan_batches(&files, one_part * 2, BudgetPolicy::Split).unwrap();
+        assert_eq!(
+            batches
+                .iter()
+                .map(|batch| part_ids(batch))
+                .collect::<Vec<_>>(),
+            vec![
+                vec![("big.rs".to_string(), vec![1, 2])],
+                vec![("big.rs".to_string(), vec![3])],
+            ]
+        );
+    }
+
+    #[test]
+    fn split_fails_when_a_single_part_is_too_big() {
+        let files = [file("big.rs", &[4000])];
+        assert!(matches!(
+            plan_batches(&files, 10, BudgetPolicy::Split),
+            Err(AppError::ContextBudgetExceeded(message)) if message.contains("part 1 of big.rs")
+        ));
+    }
+}
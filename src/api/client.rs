@@ -1,14 +1,38 @@
-use super::{config, errors::DeepSeekError};
-use crate::models::FileChunks; // Import the FileChunks type
+use super::{
+    config,
+    errors::DeepSeekError,
+    provider::{CompletionOptions, LlmProvider, ProgressFn},
+    stream::StreamAssembler,
+};
+use async_trait::async_trait;
 use reqwest::Client;
 use serde_json::{json, Value};
 use std::io::Write;
+use std::time::Duration;
+
+/// Connect and total timeouts applied to every API request.
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    pub connect: Duration,
+    pub total: Duration,
+}
+
+impl Timeouts {
+    /// Builds an HTTP client that gives up on hung connections.
+    fn build_client(&self) -> Result<Client, DeepSeekError> {
+        Ok(Client::builder()
+            .connect_timeout(self.connect)
+            .timeout(self.total)
+            .build()?)
+    }
+}
 
 /// API client for interacting with the DeepSeek API.
 pub struct DeepSeekApi {
     client: Client,
     api_key: String,
     base_url: String,
+    model: String,
 }
 
 impl DeepSeekApi {
@@ -18,164 +42,246 @@ impl DeepSeekApi {
             client: Client::new(),
             api_key,
             base_url: config::BASE_URL.to_string(),
+            model: config::DEFAULT_MODEL.to_string(),
         }
     }
 
-    /// Calls the DeepSeek preprocessor API.
-    pub async fn call_deepseek_preprocessor(
+    /// Overrides the base URL, e.g. to go through a proxy.
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url;
+        self
+    }
+
+    /// Overrides the model name (defaults to `deepseek-chat`).
+    pub fn with_model(mut self, model: String) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Applies connect and total request timeouts.
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Result<Self, DeepSeekError> {
+        self.client = timeouts.build_client()?;
+        Ok(self)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for DeepSeekApi {
+    fn name(&self) -> &str {
+        "DeepSeek"
+    }
+
+    async fn complete(
         &self,
-        user_system_prompt: &str,
-        user_prompt: &str,
-        file_chunks: &Vec<FileChunks>,
-        temperature: f32,
-        output_directory: String,
+        messages: &[Value],
+        options: CompletionOptions,
+        on_progress: Option<&ProgressFn>,
     ) -> Result<String, DeepSeekError> {
-        log::debug!("Calling DeepSeek preprocessor API");
-
-        // Serialize FileChunks to JSON
-        let file_content = serde_json::to_string(&file_chunks)?;
+        send_chat_completion(
+            &self.client,
+            &self.base_url,
+            &self.model,
+            Some((config::DEFAULT_AUTH_HEADER, &self.api_key)),
+            messages,
+            options,
+            on_progress,
+        )
+        .await
+    }
+}
 
-        let final_prompt =
-            format!(
-            "<code_files>{}</code_files> <user_prompt>{}</user_prompt> <important>{}</important>",
-            file_content, user_prompt, config::PREPROCESSOR_IMPORTANT_TEXT,
-        );
+/// API client for any OpenAI-compatible chat-completions endpoint,
+/// such as a self-hosted gateway or a local llama.cpp/vLLM server.
+pub struct OpenAiCompatibleApi {
+    client: Client,
+    api_key: Option<String>,
+    base_url: String,
+    model: String,
+    auth_header: String,
+}
 
-        let final_system_prompt = format!(
-            "<system_prompt>{}</system_prompt> <user_system_prompt>{}</user_system_prompt>",
-            config::PREPROCESSOR_SYSTEM_PROMPT,
-            user_system_prompt
-        );
+impl OpenAiCompatibleApi {
+    /// Creates a new `OpenAiCompatibleApi` instance.
+    pub fn new(
+        base_url: String,
+        model: String,
+        api_key: Option<String>,
+        auth_header: String,
+    ) -> Self {
+        Self {
+            client: Client::new(),
+            api_key,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model,
+            auth_header,
+        }
+    }
 
-        let messages = vec![
-            json!({"role": "system", "content": final_system_prompt}),
-            json!({"role": "user", "content": final_prompt}),
-        ];
+    /// Applies connect and total request timeouts.
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Result<Self, DeepSeekError> {
+        self.client = timeouts.build_client()?;
+        Ok(self)
+    }
+}
 
-        self.send_request("preprocessor", messages, temperature, output_directory)
-            .await
+#[async_trait]
+impl LlmProvider for OpenAiCompatibleApi {
+    fn name(&self) -> &str {
+        &self.model
     }
 
-    /// Calls the DeepSeek code editor API.
-    pub async fn call_deepseek_code_assistant(
+    async fn complete(
         &self,
-        user_system_prompt: &str,
-        user_prompt: &str,
-        file_chunks: &Vec<FileChunks>, // Use FileChunks instead of raw string
-        temperature: f32,
-        output_directory: String,
+        messages: &[Value],
+        options: CompletionOptions,
+        on_progress: Option<&ProgressFn>,
     ) -> Result<String, DeepSeekError> {
-        log::debug!("Calling DeepSeek code editor API");
+        let auth = self
+            .api_key
+            .as_deref()
+            .map(|api_key| (self.auth_header.as_str(), api_key));
 
-        // Serialize FileChunks to JSON
-        let file_content = serde_json::to_string(&file_chunks)?;
+        send_chat_completion(
+            &self.client,
+            &self.base_url,
+            &self.model,
+            auth,
+            messages,
+            options,
+            on_progress,
+        )
+        .await
+    }
+}
 
-        let final_prompt =
-            format!(
-            "<code_files>{}</code_files> <user_prompt>{}</user_prompt> <important>{}</important>",
-            file_content, user_prompt, config::CODE_EDITOR_IMPORTANT_TEXT,
-        );
+/// Sends a request to a `/chat/completions` endpoint and returns the content
+/// of the first choice.
+///
+/// `auth` is a `(header, api_key)` pair. The `Authorization` header gets a
+/// `Bearer` prefix, any other header receives the raw key.
+async fn send_chat_completion(
+    client: &Client,
+    base_url: &str,
+    model: &str,
+    auth: Option<(&str, &str)>,
+    messages: &[Value],
+    options: CompletionOptions,
+    on_progress: Option<&ProgressFn>,
+) -> Result<String, DeepSeekError> {
+    let mut request = client.post(format!("{}/chat/completions", base_url));
 
-        let final_system_prompt = format!(
-            "<system_prompt>{}</system_prompt> <user_system_prompt>{}</user_system_prompt>",
-            config::CODE_EDITOR_SYSTEM_PROMPT,
-            user_system_prompt
-        );
+    if let Some((header, api_key)) = auth {
+        let value = if header.eq_ignore_ascii_case(config::DEFAULT_AUTH_HEADER) {
+            format!("Bearer {}", api_key)
+        } else {
+            api_key.to_string()
+        };
+        request = request.header(header, value);
+    }
 
-        let messages = vec![
-            json!({"role": "system", "content": final_system_prompt}),
-            json!({"role": "user", "content": final_prompt}),
-        ];
+    let response = request
+        .json(&json!({
+            "model": model,
+            "messages": messages,
+            "temperature": options.temperature,
+            "max_tokens": options.max_tokens,
+            "response_format": {
+                "type": "json_object"
+            },
+            "stream": options.stream,
+        }))
+        .send()
+        .await?;
 
-        self.send_request("code_editor", messages, temperature, output_directory)
-            .await
+    if !response.status().is_success() {
+        let status = response.status().as_u16();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let message = response.text().await?;
+        return Err(DeepSeekError::StatusError {
+            status,
+            retry_after,
+            message,
+        });
     }
 
-    /// Sends a request to the DeepSeek API.
-    async fn send_request(
-        &self,
-        endpoint: &str,
-        messages: Vec<Value>,
-        temperature: f32,
-        output_directory: String,
-    ) -> Result<String, DeepSeekError> {
-        let response = self
-            .client
-            .post(&format!("{}/chat/completions", self.base_url))
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .json(&json!({
-                "model": "deepseek-chat",
-                "messages": messages,
-                "temperature": temperature,
-                "max_tokens": 8192,
-                "response_format": {
-                    "type": "json_object"
-                },
-            }))
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(DeepSeekError::ApiError(error_text));
-        }
+    if options.stream {
+        return read_stream(response, model, on_progress).await;
+    }
 
-        let raw_response = response.text().await?;
-        let json_response: Value = serde_json::from_str(&raw_response)?;
+    let raw_response = response.text().await?;
+    let json_response: Value = serde_json::from_str(&raw_response)?;
 
-        if let Some(error) = json_response.get("error") {
-            return Err(DeepSeekError::ApiError(error.to_string()));
-        }
+    if let Some(error) = json_response.get("error") {
+        return Err(DeepSeekError::ApiError(error.to_string()));
+    }
 
-        let response = json_response["choices"][0]["message"]["content"]
-            .as_str()
-            .unwrap_or("(No response)")
-            .to_string();
+    let response = json_response["choices"][0]["message"]["content"]
+        .as_str()
+        .unwrap_or("(No response)")
+        .to_string();
 
-        log::info!("DeepSeek response: {}", response);
+    log::info!("{} response: {}", model, response);
 
-        // Create the .logs directory if it doesn't exist
-        let logs_dir = std::path::Path::new(&output_directory).join("press.output/.logs");
-        if !logs_dir.exists() {
-            std::fs::create_dir_all(&logs_dir)?;
-        }
+    Ok(response)
+}
+
+/// Reads a server-sent events response chunk by chunk, reporting the number
+/// of tokens received so far to `on_progress`.
+async fn read_stream(
+    mut response: reqwest::Response,
+    model: &str,
+    on_progress: Option<&ProgressFn>,
+) -> Result<String, DeepSeekError> {
+    let mut assembler = StreamAssembler::new();
 
-        match endpoint {
-            "preprocessor" => {
-                // Save the prompt to {output_directory}/.logs/preprocessor_prompt.txt
-                let prompt_path = logs_dir.join("preprocessor_prompt.txt");
-                let mut prompt_file = std::fs::File::create(prompt_path)?;
-                writeln!(
-                    prompt_file,
-                    "{}",
-                    messages[1]["content"].as_str().unwrap_or("")
-                )?;
-
-                // Save the response to {output_directory}/.logs/preprocessor_raw_response.txt
-                let response_path = logs_dir.join("preprocessor_raw_response.json");
-                let mut response_file = std::fs::File::create(response_path)?;
-                writeln!(response_file, "{}", response)?;
-            }
-            "code_editor" => {
-                // Save the prompt to {output_directory}/.logs/code_assistant_prompt.txt
-                let prompt_path = logs_dir.join("code_assistant_prompt.txt");
-                let mut prompt_file = std::fs::File::create(prompt_path)?;
-                writeln!(
-                    prompt_file,
-                    "{}",
-                    messages[1]["content"].as_str().unwrap_or("")
-                )?;
-
-                // Save the response to {output_directory}/.logs/code_assistant_raw_response.txt
-                let response_path = logs_dir.join("code_assistant_raw_response.json");
-                let mut response_file = std::fs::File::create(response_path)?;
-                writeln!(response_file, "{}", response)?;
-            }
-            _ => {
-                return Err(DeepSeekError::ApiError("Invalid endpoint".to_string()));
-            }
+    while let Some(chunk) = response.chunk().await? {
+        assembler.push(&chunk)?;
+        if let Some(on_progress) = on_progress {
+            on_progress(assembler.tokens());
         }
+        if assembler.is_done() {
+            break;
+        }
+    }
+
+    let response = assembler.finish()?;
+    log::info!("{} response: {}", model, response);
 
-        Ok(response)
+    Ok(response)
+}
+
+/// Saves the user prompt and the raw response of a stage to
+/// `{output_directory}/press.output/.logs/`.
+pub(super) fn save_exchange_log(
+    stage: &str,
+    messages: &[Value],
+    response: &str,
+    output_directory: &str,
+) -> Result<(), DeepSeekError> {
+    // Create the .logs directory if it doesn't exist
+    let logs_dir = std::path::Path::new(output_directory).join("press.output/.logs");
+    if !logs_dir.exists() {
+        std::fs::create_dir_all(&logs_dir)?;
     }
+
+    // Save the prompt to {output_directory}/.logs/{stage}_prompt.txt
+    let prompt_path = logs_dir.join(format!("{}_prompt.txt", stage));
+    let mut prompt_file = std::fs::File::create(prompt_path)?;
+    writeln!(
+        prompt_file,
+        "{}",
+        messages[1]["content"].as_str().unwrap_or("")
+    )?;
+
+    // Save the response to {output_directory}/.logs/{stage}_raw_response.json
+    let response_path = logs_dir.join(format!("{}_raw_response.json", stage));
+    let mut response_file = std::fs::File::create(response_path)?;
+    writeln!(response_file, "{}", response)?;
+
+    Ok(())
 }
@@ -1,9 +1,9 @@
 // src/deep_seek_api/errors.rs
 
-use reqwest;
-use serde_json;
+use std::time::Duration;
 use thiserror::Error;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Error, Debug)]
 pub enum DeepSeekError {
     #[error("HTTP request failed: {0}")]
@@ -12,4 +12,15 @@ pub enum DeepSeekError {
     JsonError(#[from] serde_json::Error),
     #[error("API returned an error: {0}")]
     ApiError(String),
+    #[error("API returned HTTP {status}: {message}")]
+    StatusError {
+        status: u16,
+        retry_after: Option<Duration>,
+        message: String,
+    },
+    #[error("Invalid model response: {0}")]
+    InvalidResponse(String),
+    /// A local file operation failed, e.g. saving the exchange log.
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
 }
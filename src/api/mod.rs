@@ -1,3 +1,7 @@
+pub mod budget;
 pub mod client;
 pub mod config;
 pub mod errors;
+pub mod provider;
+pub mod retry;
+pub mod stream;
@@ -0,0 +1,261 @@
+use super::{
+    client::{self, Timeouts},
+    config,
+    errors::DeepSeekError,
+};
+use crate::errors::AppError;
+use crate::models::code_assistant_response::CodeAssistantResponse;
+use crate::models::parsing::parse_model_response;
+use crate::models::preprocessor_response::PreprocessorResponse;
+use crate::models::FileChunks;
+use crate::utils::config::{EditFormat, ProviderConfig, ProviderKind, Stage};
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+/// Sampling options sent along with every completion request.
+#[derive(Debug, Clone, Copy)]
+pub struct CompletionOptions {
+    pub temperature: f32,
+    pub max_tokens: u32,
+    /// Request a server-sent events stream instead of a single response.
+    pub stream: bool,
+    /// How many times to re-ask the model when its reply can't be parsed.
+    pub repair_attempts: u32,
+}
+
+/// Callback invoked with the number of tokens received so far while a
+/// response is being streamed.
+pub type ProgressFn = dyn Fn(usize) + Send + Sync;
+
+/// A chat-completions backend that press can send its prompts to.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Human readable name of the provider, used in the CLI output.
+    fn name(&self) -> &str;
+
+    /// Sends `messages` to the model and returns the content of the first choice.
+    /// `on_progress` is only called when `options.stream` is set.
+    async fn complete(
+        &self,
+        messages: &[Value],
+        options: CompletionOptions,
+        on_progress: Option<&ProgressFn>,
+    ) -> Result<String, DeepSeekError>;
+
+    /// Calls the preprocessor stage, which picks the parts that need editing.
+    async fn call_preprocessor(
+        &self,
+        user_system_prompt: &str,
+        user_prompt: &str,
+        file_chunks: &[FileChunks],
+        options: CompletionOptions,
+        log_directory: Option<&str>,
+        on_progress: Option<&ProgressFn>,
+    ) -> Result<PreprocessorResponse, DeepSeekError> {
+        log::debug!("Calling {} preprocessor", self.name());
+
+        let (system_prompt, important_text) =
+            config::stage_prompts(Stage::Preprocessor, EditFormat::default());
+        let messages = build_messages(
+            system_prompt,
+            important_text,
+            user_system_prompt,
+            user_prompt,
+            file_chunks,
+            &[],
+        )?;
+
+        complete_parsed(
+            self,
+            "preprocessor",
+            messages,
+            options,
+            log_directory,
+            on_progress,
+        )
+        .await
+    }
+
+    /// Calls the code assistant stage, which rewrites the selected parts
+    /// and describes its changes in `edit_format`.
+    ///
+    /// `preprocessor_plan` is the preprocessor's explanation of its choice and
+    /// `outline` sketches the parts it left out (see
+    /// [`outline_unselected_parts`](crate::file_processing::outline::outline_unselected_parts));
+    /// either may be empty.
+    #[allow(clippy::too_many_arguments)]
+    async fn call_code_assistant(
+        &self,
+        user_system_prompt: &str,
+        user_prompt: &str,
+        preprocessor_plan: &str,
+        outline: &str,
+        file_chunks: &[FileChunks],
+        edit_format: EditFormat,
+        options: CompletionOptions,
+        log_directory: Option<&str>,
+        on_progress: Option<&ProgressFn>,
+    ) -> Result<CodeAssistantResponse, DeepSeekError> {
+        log::debug!("Calling {} code assistant", self.name());
+
+        let (system_prompt, important_text) =
+            config::stage_prompts(Stage::CodeAssistant, edit_format);
+        let messages = build_messages(
+            system_prompt,
+            important_text,
+            user_system_prompt,
+            user_prompt,
+            file_chunks,
+            &[
+                ("preprocessor_plan", preprocessor_plan),
+                ("outline", outline),
+            ],
+        )?;
+
+        complete_parsed(
+            self,
+            "code_assistant",
+            messages,
+            options,
+            log_directory,
+            on_progress,
+        )
+        .await
+    }
+}
+
+/// Sends `messages` and parses the reply into `T`, logging the exchange under
+/// `log_directory` unless it is `None`.
+///
+/// When the reply isn't valid even after repair, the model is shown its own
+/// reply and the parse error and asked again, up to `options.repair_attempts`
+/// times.
+async fn complete_parsed<P, T>(
+    provider: &P,
+    stage: &str,
+    mut messages: Vec<Value>,
+    options: CompletionOptions,
+    log_directory: Option<&str>,
+    on_progress: Option<&ProgressFn>,
+) -> Result<T, DeepSeekError>
+where
+    P: LlmProvider + ?Sized,
+    T: DeserializeOwned,
+{
+    let mut attempt = 0;
+    loop {
+        let response = provider.complete(&messages, options, on_progress).await?;
+        if let Some(log_directory) = log_directory {
+            client::save_exchange_log(stage, &messages, &response, log_directory)?;
+        }
+
+        match parse_model_response(&response) {
+            Ok(parsed) => return Ok(parsed),
+            Err(e) if attempt < options.repair_attempts => {
+                attempt += 1;
+                log::warn!(
+                    "Invalid {} response, re-asking ({}/{}): {}",
+                    stage,
+                    attempt,
+                    options.repair_attempts,
+                    e
+                );
+                messages.push(json!({"role": "assistant", "content": response}));
+                messages.push(json!({
+                    "role": "user",
+                    "content": format!("{}{}", config::REPAIR_PROMPT, e),
+                }));
+            }
+            Err(e) => {
+                return Err(DeepSeekError::InvalidResponse(format!(
+                    "{} response still invalid after {} attempt(s): {}",
+                    stage,
+                    attempt + 1,
+                    e
+                )))
+            }
+        }
+    }
+}
+
+/// Builds the system and user messages shared by both stages. Each non-empty
+/// `(tag, content)` of `sections` goes between the user prompt and the
+/// important text.
+fn build_messages(
+    system_prompt: &str,
+    important_text: &str,
+    user_system_prompt: &str,
+    user_prompt: &str,
+    file_chunks: &[FileChunks],
+    sections: &[(&str, &str)],
+) -> Result<Vec<Value>, DeepSeekError> {
+    // Serialize FileChunks to JSON
+    let file_content = serde_json::to_string(file_chunks)?;
+
+    let mut final_prompt = format!(
+        "<code_files>{}</code_files> <user_prompt>{}</user_prompt>",
+        file_content, user_prompt,
+    );
+    for (tag, content) in sections {
+        if !content.trim().is_empty() {
+            final_prompt.push_str(&format!(" <{}>{}</{}>", tag, content.trim_end(), tag));
+        }
+    }
+    final_prompt.push_str(&format!(" <important>{}</important>", important_text));
+
+    let final_system_prompt = format!(
+        "<system_prompt>{}</system_prompt> <user_system_prompt>{}</user_system_prompt>",
+        system_prompt, user_system_prompt
+    );
+
+    Ok(vec![
+        json!({"role": "system", "content": final_system_prompt}),
+        json!({"role": "user", "content": final_prompt}),
+    ])
+}
+
+/// Builds the provider described by `provider_config`.
+///
+/// DeepSeek always needs an API key; generic OpenAI-compatible endpoints
+/// (self-hosted gateways, llama.cpp, vLLM) may run without one.
+pub fn build_provider(
+    provider_config: &ProviderConfig,
+    api_key: Option<String>,
+    timeouts: Timeouts,
+) -> Result<Box<dyn LlmProvider>, AppError> {
+    match provider_config.kind {
+        ProviderKind::DeepSeek => {
+            let api_key = api_key.ok_or(AppError::MissingApiKey)?;
+            let mut api = client::DeepSeekApi::new(api_key).with_timeouts(timeouts)?;
+            if let Some(base_url) = &provider_config.base_url {
+                api = api.with_base_url(base_url.clone());
+            }
+            if let Some(model) = &provider_config.model {
+                api = api.with_model(model.clone());
+            }
+            Ok(Box::new(api))
+        }
+        ProviderKind::OpenAiCompatible => {
+            let base_url = provider_config.base_url.clone().ok_or_else(|| {
+                AppError::InvalidInput(
+                    "The openai-compatible provider requires a base_url".to_string(),
+                )
+            })?;
+            let model = provider_config.model.clone().ok_or_else(|| {
+                AppError::InvalidInput(
+                    "The openai-compatible provider requires a model".to_string(),
+                )
+            })?;
+            let auth_header = provider_config
+                .auth_header
+                .clone()
+                .unwrap_or_else(|| config::DEFAULT_AUTH_HEADER.to_string());
+
+            Ok(Box::new(
+                client::OpenAiCompatibleApi::new(base_url, model, api_key, auth_header)
+                    .with_timeouts(timeouts)?,
+            ))
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use super::errors::DeepSeekError;
+use crate::utils::config::RetryConfig;
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+
+/// What kind of failure an API error represents, which decides whether
+/// retrying can help.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorClass {
+    /// 401/403: the API key is missing or wrong.
+    Auth,
+    /// 429: the provider asked us to slow down, optionally saying for how long.
+    RateLimited(Option<Duration>),
+    /// 5xx or an error reported inside an otherwise successful response.
+    Server,
+    /// The request or the connection timed out.
+    Timeout,
+    /// Connection failures and interrupted bodies.
+    Network,
+    /// The provider's response envelope wasn't valid JSON.
+    Json,
+    /// Any other 4xx: the request itself is wrong.
+    Client,
+    /// The model's reply was unusable even after re-asking.
+    InvalidResponse,
+    /// A local file operation failed; the request may already have been paid for.
+    Io,
+}
+
+impl ErrorClass {
+    /// Classifies a `DeepSeekError`.
+    pub fn of(error: &DeepSeekError) -> Self {
+        match error {
+            DeepSeekError::RequestError(e) if e.is_timeout() => ErrorClass::Timeout,
+            DeepSeekError::RequestError(_) => ErrorClass::Network,
+            DeepSeekError::JsonError(_) => ErrorClass::Json,
+            DeepSeekError::StatusError {
+                status,
+                retry_after,
+                ..
+            } => match status {
+                401 | 403 => ErrorClass::Auth,
+                408 => ErrorClass::Timeout,
+                429 => ErrorClass::RateLimited(*retry_after),
+                500..=599 => ErrorClass::Server,
+                _ => ErrorClass::Client,
+            },
+            DeepSeekError::ApiError(_) => ErrorClass::Server,
+            DeepSeekError::InvalidResponse(_) => ErrorClass::InvalidResponse,
+            DeepSeekError::IoError(_) => ErrorClass::Io,
+        }
+    }
+
+    /// Whether retrying the same request could succeed.
+    pub fn is_retryable(&self) -> bool {
+        !matches!(
+            self,
+            ErrorClass::Auth | ErrorClass::Client | ErrorClass::InvalidResponse | ErrorClass::Io
+        )
+    }
+}
+
+/// Retries failed API calls with exponential backoff and jitter.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Creates a policy allowing `max_retries` retries with the backoff
+    /// settings from the `[retry]` section of `config.toml`.
+    pub fn new(max_retries: u32, retry_config: &RetryConfig) -> Self {
+        Self {
+            max_retries,
+            base_delay: Duration::from_millis(retry_config.base_delay_ms),
+            max_delay: Duration::from_millis(retry_config.max_delay_ms),
+        }
+    }
+
+    /// Delay before retry number `retry` (starting at 0).
+    ///
+    /// A `Retry-After` sent with a 429 is honoured up to `max_delay`, so a
+    /// provider can't stall the run for hours; otherwise the delay doubles with
+    /// every retry up to `max_delay`, and a random jitter of up to half the
+    /// delay is subtracted so concurrent runs don't retry in lockstep.
+    pub fn delay(&self, retry: u32, class: ErrorClass) -> Duration {
+        if let ErrorClass::RateLimited(Some(retry_after)) = class {
+            return retry_after.min(self.max_delay);
+        }
+
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        let jitter = rand::thread_rng().gen_range(0.0..=0.5);
+        backoff.mul_f64(1.0 - jitter)
+    }
+
+    /// Runs `operation` until it succeeds, fails with an error that isn't
+    /// worth retrying, or the retry budget is used up.
+    pub async fn run<T, F, Fut>(&self, stage: &str, mut operation: F) -> Result<T, DeepSeekError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, DeepSeekError>>,
+    {
+        let mut retry = 0;
+        loop {
+            match operation().await {
+                Ok(result) => return Ok(result),
+                Err(e) => {
+                    let class = ErrorClass::of(&e);
+                    if !class.is_retryable() || retry >= self.max_retries {
+                        return Err(e);
+                    }
+
+                    let delay = self.delay(retry, class);
+                    retry += 1;
+                    log::warn!(
+                        "{} call failed ({:?}), retry {}/{} in {:.1?}: {}",
+                        stage,
+                        class,
+                        retry,
+                        self.max_retries,
+                        delay,
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(base_delay_ms: u64, max_delay_ms: u64) -> RetryPolicy {
+        RetryPolicy::new(
+            3,
+            &RetryConfig {
+                base_delay_ms,
+                max_delay_ms,
+            },
+        )
+    }
+
+    /// Checks that `delay` is the backoff `expected`, less at most half of it as jitter.
+    fn assert_jittered(delay: Duration, expected: Duration) {
+        assert!(
+            delay <= expected && delay >= expected / 2,
+            "{:?} is not within the jitter of {:?}",
+            delay,
+            expected
+        );
+    }
+
+    #[test]
+    fn delay_doubles_with_every_retry() {
+        let policy = policy(100, 60_000);
+        for (retry, expected_ms) in [(0, 100), (1, 200), (2, 400), (3, 800)] {
+            for _ in 0..20 {
+                assert_jittered(
+                    policy.delay(retry, ErrorClass::Server),
+                    Duration::from_millis(expected_ms),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn delay_is_capped_at_max_delay() {
+        let policy = policy(100, 1_000);
+        for retry in [4, 10, 40, u32::MAX] {
+            assert_jittered(
+                policy.delay(retry, ErrorClass::Timeout),
+                Duration::from_millis(1_000),
+            );
+        }
+    }
+
+    #[test]
+    fn retry_after_is_honoured_without_jitter() {
+        let policy = policy(100, 30_000);
+        let class = ErrorClass::RateLimited(Some(Duration::from_secs(7)));
+        assert_eq!(policy.delay(0, class), Duration::from_secs(7));
+        assert_eq!(policy.delay(2, class), Duration::from_secs(7));
+    }
+
+    #[test]
+    fn retry_after_is_clamped_to_max_delay() {
+        let policy = policy(100, 30_000);
+        let class = ErrorClass::RateLimited(Some(Duration::from_secs(3_600)));
+        assert_eq!(policy.delay(0, class), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn rate_limits_without_retry_after_back_off() {
+        let policy = policy(100, 60_000);
+        assert_jittered(
+            policy.delay(1, ErrorClass::RateLimited(None)),
+            Duration::from_millis(200),
+        );
+    }
+
+    #[test]
+    fn io_errors_are_not_retried() {
+        let error = DeepSeekError::from(std::io::Error::other("disk full"));
+        assert_eq!(ErrorClass::of(&error), ErrorClass::Io);
+        assert!(!ErrorClass::of(&error).is_retryable());
+    }
+
+    #[test]
+    fn server_errors_are_retried() {
+        let error = DeepSeekError::ApiError("overloaded".to_string());
+        assert!(ErrorClass::of(&error).is_retryable());
+    }
+}
@@ -0,0 +1,256 @@
+use super::errors::DeepSeekError;
+use serde_json::Value;
+
+/// Incrementally reassembles the content of a streamed (`"stream": true`)
+/// chat completion from its server-sent events.
+///
+/// Network chunks can end anywhere, including in the middle of an event or
+/// of a multi-byte character, so incomplete lines are buffered until the
+/// next chunk arrives.
+#[derive(Default)]
+pub struct StreamAssembler {
+    pending: Vec<u8>,
+    content: String,
+    tokens: usize,
+    done: bool,
+}
+
+impl StreamAssembler {
+    /// Creates an empty `StreamAssembler`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds the next chunk of the response body.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<(), DeepSeekError> {
+        self.pending.extend_from_slice(chunk);
+
+        while let Some(newline) = self.pending.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=newline).collect();
+            let line = String::from_utf8_lossy(&line);
+            self.handle_line(line.trim_end_matches(['\r', '\n']))?;
+        }
+
+        Ok(())
+    }
+
+    /// Number of content deltas received so far (roughly one per token).
+    pub fn tokens(&self) -> usize {
+        self.tokens
+    }
+
+    /// Whether the `[DONE]` marker has been received.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Flushes any unterminated last line and returns the assembled content.
+    pub fn finish(mut self) -> Result<String, DeepSeekError> {
+        if !self.pending.is_empty() {
+            let line = std::mem::take(&mut self.pending);
+            let line = String::from_utf8_lossy(&line).to_string();
+            self.handle_line(line.trim_end_matches('\r'))?;
+        }
+        Ok(self.content)
+    }
+
+    /// Handles a single SSE line. Only `data:` lines carry content; comments,
+    /// `event:` and `id:` lines are ignored.
+    fn handle_line(&mut self, line: &str) -> Result<(), DeepSeekError> {
+        let Some(data) = line.strip_prefix("data:") else {
+            return Ok(());
+        };
+
+        let data = data.trim_start();
+        if data.is_empty() {
+            return Ok(());
+        }
+        if data == "[DONE]" {
+            self.done = true;
+            return Ok(());
+        }
+
+        let event: Value = serde_json::from_str(data)?;
+        if let Some(error) = event.get("error") {
+            return Err(DeepSeekError::ApiError(error.to_string()));
+        }
+
+        if let Some(delta) = event["choices"][0]["delta"]["content"].as_str() {
+            self.content.push_str(delta);
+            self.tokens += 1;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::client::OpenAiCompatibleApi;
+    use crate::api::provider::{CompletionOptions, LlmProvider};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn event(content: &str) -> String {
+        format!(
+            "data: {}\n\n",
+            json!({"choices": [{"index": 0, "delta": {"content": content}}]})
+        )
+    }
+
+    fn sse_body(contents: &[&str]) -> String {
+        let mut body = ": keep-alive\n\n".to_string();
+        body.push_str("data: {\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\"}}]}\n\n");
+        for content in contents {
+            body.push_str(&event(content));
+        }
+        body.push_str("data: [DONE]\n\n");
+        body
+    }
+
+    /// Serves a single request, writing `body` back in `chunk_size` byte pieces.
+    async fn serve_sse(body: String, chunk_size: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+
+            // Read the request headers and body before answering
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            loop {
+                let read = socket.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request).to_lowercase();
+                if let Some(header_end) = text.find("\r\n\r\n") {
+                    let content_length = text
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length:"))
+                        .and_then(|value| value.trim().parse::<usize>().ok())
+                        .unwrap_or(0);
+                    if request.len() >= header_end + 4 + content_length {
+                        break;
+                    }
+                }
+                if read == 0 {
+                    break;
+                }
+            }
+            assert!(String::from_utf8_lossy(&request).contains("\"stream\":true"));
+
+            socket
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\nconnection: close\r\n\r\n",
+                )
+                .await
+                .unwrap();
+            for chunk in body.as_bytes().chunks(chunk_size) {
+                socket.write_all(chunk).await.unwrap();
+                socket.flush().await.unwrap();
+                tokio::time::sleep(Duration::from_millis(2)).await;
+            }
+            socket.shutdown().await.unwrap();
+        });
+
+        format!("http://{}", address)
+    }
+
+    #[test]
+    fn reassembles_events_split_across_chunks() {
+        let body = sse_body(&[
+            "{\"parts_to_edit\": [",
+            "], \"preprocessor_prompt\": ",
+            "\"ok\"}",
+        ]);
+
+        for chunk_size in [1, 3, 7, 64, body.len()] {
+            let mut assembler = StreamAssembler::new();
+            for chunk in body.as_bytes().chunks(chunk_size) {
+                assembler.push(chunk).unwrap();
+            }
+            assert!(assembler.is_done());
+            assert_eq!(assembler.tokens(), 3);
+            assert_eq!(
+                assembler.finish().unwrap(),
+                "{\"parts_to_edit\": [], \"preprocessor_prompt\": \"ok\"}"
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_multi_byte_characters_split_across_chunks() {
+        let body = sse_body(&["🍇 press", " – ünïcode"]);
+        let mut assembler = StreamAssembler::new();
+        for chunk in body.as_bytes().chunks(1) {
+            assembler.push(chunk).unwrap();
+        }
+        assert_eq!(assembler.finish().unwrap(), "🍇 press – ünïcode");
+    }
+
+    #[test]
+    fn handles_crlf_and_missing_trailing_newline() {
+        let body = event("a").replace('\n', "\r\n") + event("b").trim_end();
+        let mut assembler = StreamAssembler::new();
+        assembler.push(body.as_bytes()).unwrap();
+        assert!(!assembler.is_done());
+        assert_eq!(assembler.finish().unwrap(), "ab");
+    }
+
+    #[test]
+    fn surfaces_errors_sent_in_the_stream() {
+        let mut assembler = StreamAssembler::new();
+        let result = assembler.push(b"data: {\"error\": {\"message\": \"overloaded\"}}\n\n");
+        assert!(matches!(result, Err(DeepSeekError::ApiError(_))));
+    }
+
+    #[tokio::test]
+    async fn streams_from_a_mock_sse_server() {
+        let contents = [
+            "{\"updated_files\": []",
+            ", \"new_files\": []",
+            ", \"response\": \"done\"}",
+        ];
+        let base_url = serve_sse(sse_body(&contents), 5).await;
+
+        let api = OpenAiCompatibleApi::new(
+            base_url,
+            "mock-model".to_string(),
+            None,
+            "Authorization".to_string(),
+        );
+        let progress_calls = Arc::new(AtomicUsize::new(0));
+        let last_tokens = Arc::new(AtomicUsize::new(0));
+        let on_progress = {
+            let progress_calls = progress_calls.clone();
+            let last_tokens = last_tokens.clone();
+            move |tokens: usize| {
+                progress_calls.fetch_add(1, Ordering::SeqCst);
+                last_tokens.store(tokens, Ordering::SeqCst);
+            }
+        };
+
+        let response = api
+            .complete(
+                &[json!({"role": "user", "content": "hi"})],
+                CompletionOptions {
+                    temperature: 0.0,
+                    max_tokens: 16,
+                    stream: true,
+                    repair_attempts: 0,
+                },
+                Some(&on_progress),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response, contents.concat());
+        assert_eq!(last_tokens.load(Ordering::SeqCst), 3);
+        assert!(progress_calls.load(Ordering::SeqCst) >= 1);
+    }
+}
@@ -1,38 +1,182 @@
 use clap::{Parser, Subcommand};
+use press::errors::AppError;
+use press::utils::config::{
+    load_config, parse_config_value, BudgetPolicy, ChunkStrategy, ConfigOverrides, DirtyTreePolicy,
+    EditFormat, LoadedConfig, ProviderKind, Stage,
+};
 
 /// CLI arguments for the Press application.
 #[derive(Parser, Debug, PartialEq, Clone)]
 #[command(version, about, long_about = None)]
 pub struct Args {
+    /// Prompt for the AI.
+    #[arg(long)]
+    pub prompt: Option<String>,
+
+    /// Settings of the run.
+    #[command(flatten)]
+    pub run: RunArgs,
+
+    /// Config values for this run only.
+    #[command(flatten)]
+    pub config: ConfigArgs,
+
+    /// Subcommand to execute.
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+}
+
+/// Flags of a run, shared by `press --prompt ...` and `press recipe run`.
+#[derive(clap::Args, Debug, PartialEq, Clone, Default)]
+pub struct RunArgs {
     /// Paths to directories or files to process.
     #[arg(short, long, num_args = 1.., value_delimiter = '&')]
     pub paths: Vec<String>,
 
-    /// Prompt for the AI.
-    #[arg(short, long)]
-    pub prompt: Option<String>,
-
     /// Automatically overwrite original files with the same name.
     #[arg(short, long)]
     pub auto: bool,
 
-    /// Pipe the last N lines of console output to the AI.
+    /// Pipe the last N lines of console output to the AI: the output of the
+    /// last `press run`, or on Windows the console itself.
     #[arg(long, num_args = 0..=1, default_missing_value = "10")]
     pub pipe_output: Option<usize>,
 
+    /// Read extra context piped into press, e.g. `cargo build 2>&1 | press --stdin ...`.
+    #[arg(long, conflicts_with = "interactive")]
+    pub stdin: bool,
+
+    /// Files whose contents are added to the prompt as context, without being edited.
+    #[arg(long)]
+    pub context_file: Vec<String>,
+
     /// Paths to files or directories to ignore.
     #[arg(short, long, num_args = 1.., value_delimiter = '&')]
     pub ignore: Vec<String>,
 
-    /// Subcommand to execute.
-    #[command(subcommand)]
-    pub command: Option<Commands>,
+    /// Only process files in the given directories matching one of these globs.
+    #[arg(long, num_args = 1.., value_delimiter = '&')]
+    pub include: Vec<String>,
+
+    /// Skip files in the given directories matching any of these globs.
+    #[arg(long, num_args = 1.., value_delimiter = '&')]
+    pub exclude: Vec<String>,
+
+    /// Review every change hunk by hunk and only write the accepted ones.
+    #[arg(long, conflicts_with = "dry_run")]
+    pub interactive: bool,
+
+    /// Commit the changes on a new branch (git mode), even if it's off in the config.
+    #[arg(long)]
+    pub git: bool,
+
+    /// Run both stages and print the plan and diff without writing anything.
+    #[arg(long, conflicts_with = "auto")]
+    pub dry_run: bool,
+
+    /// Command run after the changes are written, e.g. "cargo test". When it fails,
+    /// its output goes back to the model for another round, and the run is rolled
+    /// back if it never passes.
+    #[arg(long, conflicts_with = "dry_run")]
+    pub verify: Option<String>,
+
+    /// Maximum number of fix rounds for --verify, overriding the config.
+    #[arg(long, requires = "verify")]
+    pub verify_iterations: Option<u32>,
+}
+
+/// Flags that override config values for a single command, without
+/// touching any config file. They are the last layer of the config.
+#[derive(clap::Args, Debug, PartialEq, Clone, Default)]
+pub struct ConfigArgs {
+    /// System prompt for the AI.
+    #[arg(long, global = true)]
+    pub system_prompt: Option<String>,
+
+    /// Temperature of the AI responses.
+    #[arg(long = "temp", global = true)]
+    pub temperature: Option<f64>,
+
+    /// Maximum number of retries for API calls.
+    #[arg(long, global = true)]
+    pub retries: Option<u32>,
+
+    /// Number of lines per part when splitting files.
+    #[arg(long, global = true)]
+    pub chunk_size: Option<usize>,
+
+    /// Log level (off, debug, info, warn, error).
+    #[arg(long, global = true)]
+    pub log_level: Option<String>,
+
+    /// API key of the provider.
+    #[arg(long, global = true)]
+    pub api_key: Option<String>,
+
+    /// Directory that holds press.output.
+    #[arg(long, global = true)]
+    pub output_directory: Option<String>,
+
+    /// Total timeout for each API request in seconds.
+    #[arg(long, global = true)]
+    pub timeout: Option<u64>,
+
+    /// Any other config value, as KEY=VALUE with sections joined by dots
+    /// (e.g. --set git.enabled=true). Repeatable.
+    #[arg(long = "set", value_name = "KEY=VALUE", global = true)]
+    pub values: Vec<String>,
+}
+
+impl ConfigArgs {
+    /// Resolves the config of the current directory with these flags on top.
+    /// Every command goes through here.
+    pub fn resolve(&self) -> Result<LoadedConfig, AppError> {
+        load_config(&self.overrides()?)
+    }
+
+    /// The values these flags set. A dedicated flag wins over `--set` for the same key.
+    fn overrides(&self) -> Result<ConfigOverrides, AppError> {
+        let mut overrides = ConfigOverrides::default();
+        for value in &self.values {
+            let (key, raw) = value.split_once('=').ok_or_else(|| {
+                AppError::InvalidInput(format!("Expected KEY=VALUE, got '{}'", value))
+            })?;
+            overrides.set(key.trim(), parse_config_value(key.trim(), raw)?);
+        }
+        if let Some(system_prompt) = &self.system_prompt {
+            overrides.set("system_prompt", system_prompt.as_str());
+        }
+        if let Some(temperature) = self.temperature {
+            overrides.set("temperature", temperature);
+        }
+        if let Some(retries) = self.retries {
+            overrides.set("retries", retries as i64);
+        }
+        if let Some(chunk_size) = self.chunk_size {
+            overrides.set("chunk_size", chunk_size as i64);
+        }
+        if let Some(log_level) = &self.log_level {
+            overrides.set("log_level", log_level.as_str());
+        }
+        if let Some(api_key) = &self.api_key {
+            overrides.set("api_key", api_key.as_str());
+        }
+        if let Some(output_directory) = &self.output_directory {
+            overrides.set("output_directory", output_directory.as_str());
+        }
+        if let Some(timeout) = self.timeout {
+            overrides.set("timeout_secs", timeout as i64);
+        }
+        Ok(overrides)
+    }
 }
 
 /// Subcommands for the Press application.
 #[derive(Subcommand, Debug, PartialEq, Clone)]
 pub enum Commands {
-    /// Manage configuration options.
+    /// Manage configuration options. The setters edit the user config
+    /// (~/.config/press/config.toml).
+    #[command(args_conflicts_with_subcommands = true)]
     Config {
         /// Set the chunk size for splitting files.
         #[arg(long)]
@@ -49,14 +193,81 @@ pub enum Commands {
         /// Set the maximum number of retries for API calls.
         #[arg(long)]
         set_retries: Option<u32>,
+
+        /// Stream responses and show live token progress (true, false).
+        #[arg(long)]
+        set_stream: Option<bool>,
+
+        /// Set how many times to re-ask the model when its reply is invalid JSON.
+        #[arg(long)]
+        set_repair_attempts: Option<u32>,
+
+        /// Set the connect timeout for API requests, in seconds.
+        #[arg(long)]
+        set_connect_timeout: Option<u64>,
+
+        /// Set the total timeout for each API request, in seconds.
+        #[arg(long)]
+        set_timeout: Option<u64>,
+
+        /// Set how files are split into parts (lines, syntax).
+        #[arg(long)]
+        set_chunk_strategy: Option<ChunkStrategy>,
+
+        /// Set the context budget of each request, in tokens.
+        #[arg(long)]
+        set_context_budget: Option<usize>,
+
+        /// Set what to do when files exceed the context budget (warn, fail, split).
+        #[arg(long)]
+        set_budget_policy: Option<BudgetPolicy>,
+
+        /// Set how the code assistant sends its edits (parts, search-replace).
+        #[arg(long)]
+        set_edit_format: Option<EditFormat>,
+
+        /// Commit every run on a new branch (true, false).
+        #[arg(long)]
+        set_git: Option<bool>,
+
+        /// Set what git mode does with uncommitted changes (refuse, stash).
+        #[arg(long)]
+        set_git_dirty: Option<DirtyTreePolicy>,
+
+        /// Set the prefix of the branches created in git mode.
+        #[arg(long)]
+        set_git_branch_prefix: Option<String>,
+
+        /// Only process files with these extensions, comma-separated (empty to allow all).
+        #[arg(long, value_delimiter = ',')]
+        set_allow_extensions: Option<Vec<String>>,
+
+        /// Never process files with these extensions, comma-separated.
+        #[arg(long, value_delimiter = ',')]
+        set_deny_extensions: Option<Vec<String>>,
+
+        /// Set the maximum number of fix rounds for --verify.
+        #[arg(long)]
+        set_verify_iterations: Option<u32>,
+
+        #[command(subcommand)]
+        command: Option<ConfigCommands>,
     },
 
     /// Manage model configuration options.
     ModelConfig {
-        /// Set the API key for DeepSeek.
+        /// Save the API key to ~/.config/press/api_key, readable only by you.
         #[arg(long)]
         set_api_key: Option<String>,
 
+        /// Set a command whose output is the API key (e.g. "pass show deepseek").
+        #[arg(long)]
+        set_api_key_command: Option<String>,
+
+        /// Set a file to read the API key from; other users must not be able to read it.
+        #[arg(long)]
+        set_api_key_file: Option<String>,
+
         /// Set the system prompt for the AI.
         #[arg(long)]
         set_system_prompt: Option<String>,
@@ -64,19 +275,237 @@ pub enum Commands {
         /// Set the temperature for the AI.
         #[arg(long)]
         set_temperature: Option<f32>,
+
+        /// Set the LLM provider (deepseek, openai-compatible).
+        #[arg(long)]
+        set_provider: Option<ProviderKind>,
+
+        /// Set the base URL of the provider's chat-completions API.
+        #[arg(long)]
+        set_base_url: Option<String>,
+
+        /// Set the model name sent to the provider.
+        #[arg(long)]
+        set_model: Option<String>,
+
+        /// Set the header used to send the API key (default: Authorization).
+        #[arg(long)]
+        set_auth_header: Option<String>,
+
+        /// Set the maximum number of tokens per response (requires --stage).
+        #[arg(long, requires = "stage")]
+        set_max_tokens: Option<u32>,
+
+        /// Apply the model settings to one stage only (preprocessor, code-assistant).
+        #[arg(long)]
+        stage: Option<Stage>,
     },
 
-    /// Rollback changes made by the last run.
-    Rollback,
+    /// Rollback changes made by a run, by default the last one.
+    Rollback {
+        /// ID of the run to roll back (see `press history list`).
+        id: Option<String>,
 
-    /// Create or revert to a checkpoint.
+        /// Overwrite files changed after the run, or discard uncommitted
+        /// changes in git mode, without asking.
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Run a command and keep the end of its output for --pipe-output.
+    Run {
+        /// Number of output lines to keep.
+        #[arg(short = 'n', long, default_value_t = 100)]
+        lines: usize,
+
+        /// The command to run, after `--`.
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// Inspect previous runs.
+    History {
+        #[command(subcommand)]
+        command: HistoryCommands,
+    },
+
+    /// Create, inspect and restore named checkpoints.
     Checkpoint {
-        /// Paths to directories or files to checkpoint.
+        #[command(subcommand)]
+        command: CheckpointCommands,
+    },
+
+    /// Run and list reusable prompt templates.
+    Recipe {
+        #[command(subcommand)]
+        command: RecipeCommands,
+    },
+}
+
+/// Subcommands of `press config`.
+#[derive(Subcommand, Debug, PartialEq, Clone)]
+pub enum ConfigCommands {
+    /// Print the effective config of the current directory.
+    Show {
+        /// Show which file, environment variable or flag each value came from.
+        #[arg(long)]
+        origin: bool,
+    },
+}
+
+/// Subcommands of `press history`.
+#[derive(Subcommand, Debug, PartialEq, Clone)]
+pub enum HistoryCommands {
+    /// List previous runs, oldest first.
+    List,
+
+    /// Show the prompt, files and diff of a run.
+    Show {
+        /// ID of the run, or an unambiguous prefix of it.
+        id: String,
+    },
+}
+
+/// Subcommands of `press checkpoint`.
+#[derive(Subcommand, Debug, PartialEq, Clone)]
+pub enum CheckpointCommands {
+    /// Save the current state of some files as a named checkpoint.
+    Create {
+        /// Name of the checkpoint.
+        name: String,
+
+        /// Paths to directories or files to checkpoint (defaults to the current directory).
         #[arg(short, long, num_args = 1.., value_delimiter = '&')]
         paths: Vec<String>,
+    },
+
+    /// List the checkpoints, oldest first.
+    List,
+
+    /// Show how the working tree differs from a checkpoint.
+    Diff {
+        /// Name of the checkpoint.
+        name: String,
+    },
+
+    /// Restore the files of a checkpoint and remove files created since.
+    Restore {
+        /// Name of the checkpoint.
+        name: String,
 
-        /// Revert to the last checkpoint.
+        /// Remove the files created since the checkpoint without asking.
         #[arg(long)]
-        revert: bool,
+        force: bool,
+    },
+
+    /// Delete a checkpoint.
+    Delete {
+        /// Name of the checkpoint.
+        name: String,
     },
-}
\ No newline at end of file
+}
+
+/// Subcommands of `press recipe`.
+#[derive(Subcommand, Debug, PartialEq, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum RecipeCommands {
+    /// Run a recipe: its prompt, with the variables filled in, on its default paths.
+    Run {
+        /// Name of the recipe.
+        name: String,
+
+        /// Value of a template variable, as NAME=VALUE. Repeatable.
+        #[arg(long = "var", value_name = "NAME=VALUE")]
+        vars: Vec<String>,
+
+        #[command(flatten)]
+        run: RunArgs,
+    },
+
+    /// List the project and user recipes.
+    List,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use toml::Value;
+
+    fn overrides(args: &[&str]) -> Result<ConfigOverrides, AppError> {
+        let args = Args::try_parse_from(["press"].iter().chain(args)).unwrap();
+        args.config.overrides()
+    }
+
+    #[test]
+    fn set_parses_values_by_key_type() {
+        let overrides = overrides(&[
+            "--set",
+            "git.enabled=true",
+            "--set",
+            "chunk_size=80",
+            "--set",
+            "temperature = 0.5",
+            "--set",
+            "files.deny_extensions=lock,min.js",
+            "--set",
+            "git.branch_prefix=ai/x=y",
+        ])
+        .unwrap();
+
+        assert_eq!(overrides.get("git.enabled"), Some(&Value::Boolean(true)));
+        assert_eq!(overrides.get("chunk_size"), Some(&Value::Integer(80)));
+        assert_eq!(overrides.get("temperature"), Some(&Value::Float(0.5)));
+        assert_eq!(
+            overrides.get("files.deny_extensions"),
+            Some(&Value::Array(vec![
+                Value::String("lock".to_string()),
+                Value::String("min.js".to_string()),
+            ]))
+        );
+        // Only the first `=` separates the key from the value
+        assert_eq!(
+            overrides.get("git.branch_prefix"),
+            Some(&Value::String("ai/x=y".to_string()))
+        );
+        assert_eq!(overrides.get("retries"), None);
+    }
+
+    #[test]
+    fn dedicated_flags_win_over_set() {
+        let overrides = overrides(&[
+            "--set",
+            "chunk_size=80",
+            "--chunk-size",
+            "120",
+            "--set",
+            "timeout_secs=10",
+            "--timeout",
+            "60",
+        ])
+        .unwrap();
+        assert_eq!(overrides.get("chunk_size"), Some(&Value::Integer(120)));
+        assert_eq!(overrides.get("timeout_secs"), Some(&Value::Integer(60)));
+    }
+
+    #[test]
+    fn overrides_apply_after_a_subcommand() {
+        let overrides = overrides(&["history", "list", "--set", "retries=7"]).unwrap();
+        assert_eq!(overrides.get("retries"), Some(&Value::Integer(7)));
+    }
+
+    #[test]
+    fn invalid_set_values_are_refused() {
+        for value in [
+            "chunk_size",
+            "no_such_key=1",
+            "chunk_size=many",
+            "git.enabled=yes",
+        ] {
+            assert!(
+                matches!(overrides(&["--set", value]), Err(AppError::InvalidInput(_))),
+                "{}",
+                value
+            );
+        }
+    }
+}
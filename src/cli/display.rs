@@ -1,6 +1,15 @@
 use colored::*;
 use indicatif::{ProgressBar, ProgressStyle};
-use std::time::Duration;
+use press::api::provider::ProgressFn;
+use press::errors::AppError;
+use press::file_processing::checkpoint::{Change, Checkpoint, FileChange};
+use press::file_processing::history::{FileState, RunRecord};
+use press::file_processing::reader::SkippedFile;
+use press::models::FilePartIds;
+use press::pipeline::{PipelineEvent, PipelineObserver};
+use press::utils::config::{ConfigEntry, Stage};
+use press::utils::recipe::Recipe;
+use std::time::{Duration, Instant};
 
 /// Manages CLI display and output formatting.
 pub struct CliDisplayManager {
@@ -29,20 +38,57 @@ impl CliDisplayManager {
         );
     }
 
+    /// Prints the files that were found but aren't sent to the model.
+    pub fn print_skipped_files(&self, skipped_files: &[SkippedFile]) {
+        if skipped_files.is_empty() {
+            return;
+        }
+        self.print_info(&format!("Skipped {} file(s):", skipped_files.len()));
+        for skipped in skipped_files {
+            println!(
+                "     {} {}",
+                skipped.path.display().to_string().dimmed(),
+                format!("({})", skipped.reason).dimmed()
+            );
+        }
+    }
+
     /// Prints a success message for file combining.
     pub fn print_file_combining_success(&self) {
         self.print_info("Successfully combined file contents");
     }
 
-    /// Prints the start of Preprocessor querying.
-    pub fn print_deepseek_query_start(&self) {
-        self.print_section("⚓", "[2/3] Querying DeepSeek API", "");
+    /// Prints the start of provider querying.
+    pub fn print_query_start(&self, provider_name: &str) {
+        self.print_section("⚓", &format!("[2/3] Querying {} API", provider_name), "");
     }
 
     pub fn print_preprocessor_query_start(&self) {
         self.print_info("Preparing prompt for Preprocessor");
     }
 
+    /// Prints how many calls the preprocessor stage was split into to fit the context budget.
+    pub fn print_preprocessor_batches(&self, batch_count: usize) {
+        self.print_info(&format!(
+            "Files exceed the context budget, splitting Preprocessor into {} calls",
+            batch_count
+        ));
+    }
+
+    /// Prints a warning that a prompt is larger than the stage's context budget.
+    pub fn print_budget_warning(&self, stage: Stage, estimated: usize, available: usize) {
+        println!(
+            "   {} {}",
+            "⚠".bright_yellow(),
+            format!(
+                "{} prompt needs ~{} tokens of file content but the context budget leaves {}",
+                stage, estimated, available
+            )
+            .italic()
+            .bright_yellow()
+        );
+    }
+
     /// Prints a success message for preprocessor response.
     pub fn print_preprocessor_response_success(&self) {
         self.print_info("Successfully received Preprocessor response");
@@ -71,12 +117,312 @@ impl CliDisplayManager {
         }
     }
 
+    /// Prints the start of a dry run, which replaces saving the results.
+    pub fn print_dry_run_start(&self) {
+        self.print_section("🔍", "[3/3] Dry Run", "Nothing will be written to disk");
+    }
+
+    /// Prints the parts the preprocessor chose to edit and its reasoning.
+    pub fn print_plan(&self, parts_to_edit: &[FilePartIds], reasoning: &str) {
+        println!();
+        println!("{}", "Plan".bright_cyan().bold());
+        if parts_to_edit.is_empty() {
+            self.print_info("No parts selected for editing");
+        }
+        for file in parts_to_edit {
+            let parts: Vec<String> = file.parts.iter().map(|part| part.to_string()).collect();
+            println!(
+                "   {} {} {}",
+                "→".bright_white(),
+                file.file_path.bright_white().bold(),
+                format!("(parts {})", parts.join(", ")).dimmed()
+            );
+        }
+        if !reasoning.trim().is_empty() {
+            println!();
+            println!("{}", "Reasoning".bright_cyan().bold());
+            for line in reasoning.trim().lines() {
+                println!("   {}", line.italic());
+            }
+        }
+        println!();
+    }
+
+    /// Prints a unified diff with added lines in green and removed lines in red.
+    pub fn print_diff(&self, diff: &str) {
+        for line in diff.lines() {
+            if line.starts_with("+++") || line.starts_with("---") {
+                println!("{}", line.bold());
+            } else if line.starts_with("@@") {
+                println!("{}", line.cyan());
+            } else if line.starts_with('+') {
+                println!("{}", line.green());
+            } else if line.starts_with('-') {
+                println!("{}", line.red());
+            } else {
+                println!("{}", line);
+            }
+        }
+        println!();
+    }
+
+    /// Prints the footer of a dry run along with the model's response message.
+    pub fn print_dry_run_footer(&self, response: &str, duration: Duration) {
+        if !response.trim().is_empty() {
+            println!("{}", "Response".bright_cyan().bold());
+            for line in response.trim().lines() {
+                println!("   {}", line.italic());
+            }
+            println!();
+        }
+        println!(
+            "{}",
+            "⚡ Dry run, no files were written".bright_white().dimmed()
+        );
+        println!(
+            "{}",
+            format!("⚡ Completed in {:.2?}", duration)
+                .bright_white()
+                .dimmed(),
+        );
+        println!();
+    }
+
+    /// Prints a notice that uncommitted changes were stashed before the run.
+    pub fn print_git_stashed(&self) {
+        self.print_info("Stashed uncommitted changes, restore them with 'git stash pop'");
+    }
+
+    /// Prints the branch and commit a git mode run was committed as.
+    pub fn print_git_commit(&self, branch: &str, commit: &str) {
+        self.print_info(&format!("Committed {} on branch '{}'", commit, branch));
+    }
+
+    /// Prints a notice that a git mode run had nothing to commit.
+    pub fn print_git_nothing_to_commit(&self) {
+        self.print_info("No changes to commit");
+    }
+
+    /// Prints that a failed git mode run put the tree back the way it found it.
+    pub fn print_git_restored(&self, checked_out: Option<&str>, unstashed: bool) {
+        if let Some(start) = checked_out {
+            self.print_info(&format!("Checked out '{}' again", start));
+        }
+        if unstashed {
+            self.print_info("Restored the stashed changes");
+        }
+    }
+
+    /// Prints why a failed git mode run couldn't put the tree back.
+    pub fn print_git_restore_failed(&self, error: &AppError) {
+        self.print_info(&format!("Couldn't restore the git tree: {}", error));
+    }
+
+    /// Prints the start of a verification command.
+    pub fn print_verify_start(&self, command: &str) {
+        self.print_section("🔎", "Verifying", &format!("Running `{}`", command));
+    }
+
+    /// Prints that the verification command passed after `fix_rounds` rounds.
+    pub fn print_verify_passed(&self, fix_rounds: u32) {
+        if fix_rounds == 0 {
+            self.print_info("Verification passed");
+        } else {
+            self.print_info(&format!(
+                "Verification passed after {} fix round(s)",
+                fix_rounds
+            ));
+        }
+        println!();
+    }
+
+    /// Prints the output of a failed verification before fix round `round`.
+    pub fn print_verify_failed(&self, output: &str, round: u32, max_rounds: u32) {
+        self.print_command_output(output);
+        self.print_info(&format!(
+            "Verification failed, asking for a fix (round {}/{})",
+            round, max_rounds
+        ));
+        println!();
+    }
+
+    /// Prints the output of the last failed verification before the run is rolled back.
+    pub fn print_verify_rolling_back(&self, output: &str, run_id: &str) {
+        self.print_command_output(output);
+        self.print_info(&format!(
+            "Verification never passed, rolling back run {}",
+            run_id
+        ));
+    }
+
+    /// Prints that fix round `round` failed and the run is being rolled back.
+    pub fn print_fix_round_failed(&self, round: u32, run_id: &str) {
+        self.print_info(&format!(
+            "Fix round {} failed, rolling back run {}",
+            round, run_id
+        ));
+    }
+
+    /// Prints that the verification command couldn't be run or was interrupted.
+    pub fn print_verify_command_failed(&self, run_id: &str) {
+        self.print_info(&format!(
+            "Verification stopped, rolling back run {}",
+            run_id
+        ));
+    }
+
+    /// Prints the ID of the run just recorded in the history.
+    pub fn print_run_recorded(&self, id: &str) {
+        self.print_info(&format!(
+            "Recorded run {}, undo it with 'press rollback {}'",
+            id, id
+        ));
+    }
+
+    /// Prints one line per recorded run.
+    pub fn print_history(&self, runs: &[RunRecord]) {
+        if runs.is_empty() {
+            println!("No runs recorded yet");
+            return;
+        }
+        for run in runs {
+            let status = if run.rolled_back_at.is_some() {
+                " (rolled back)".dimmed().to_string()
+            } else {
+                String::new()
+            };
+            println!(
+                "{}  {}  {}{}",
+                run.id.bright_cyan(),
+                format!(
+                    "{} modified, {} new",
+                    run.modified_files.len(),
+                    run.new_files.len()
+                )
+                .dimmed(),
+                run.prompt.lines().next().unwrap_or_default(),
+                status
+            );
+        }
+    }
+
+    /// Prints the details of a run: its prompt, the files it touched and
+    /// whether they changed since, and its diff.
+    pub fn print_run(&self, run: &RunRecord, file_states: &[(&str, FileState)], diff: &str) {
+        println!("{} {}", "Run".bright_cyan().bold(), run.id.bold());
+        println!("   Created:  {}", run.created_at);
+        if let Some(rolled_back_at) = &run.rolled_back_at {
+            println!("   Rolled back: {}", rolled_back_at);
+        }
+        if let Some(git) = &run.git {
+            println!("   Git:      {} (base {})", git.branch, git.base_commit);
+        }
+        println!("   Paths:    {}", run.paths.join(", "));
+        println!();
+        println!("{}", "Prompt".bright_cyan().bold());
+        for line in run.prompt.lines() {
+            println!("   {}", line.italic());
+        }
+        println!();
+
+        if !file_states.is_empty() {
+            println!("{}", "Files".bright_cyan().bold());
+            for (path, state) in file_states {
+                let state = match state {
+                    FileState::Unchanged => "unchanged since the run".dimmed(),
+                    FileState::Changed => "changed since the run".yellow(),
+                    FileState::Missing => "missing".red(),
+                };
+                println!("   {} ({})", path, state);
+            }
+            println!();
+        }
+
+        self.print_diff(diff);
+    }
+
+    /// Prints one line per checkpoint.
+    pub fn print_checkpoints(&self, checkpoints: &[Checkpoint]) {
+        if checkpoints.is_empty() {
+            println!("No checkpoints yet");
+            return;
+        }
+        for checkpoint in checkpoints {
+            println!(
+                "{}  {}  {}",
+                checkpoint.name.bright_cyan(),
+                checkpoint.created_at.dimmed(),
+                format!(
+                    "{} file(s) in {}",
+                    checkpoint.files.len(),
+                    checkpoint.paths.join(", ")
+                )
+                .dimmed()
+            );
+        }
+    }
+
+    /// Prints a line per recipe, with where it was found and its description.
+    pub fn print_recipes(&self, recipes: &[Recipe]) {
+        if recipes.is_empty() {
+            println!("No recipes yet, add them to .press/recipes or ~/.config/press/recipes");
+            return;
+        }
+        for recipe in recipes {
+            println!(
+                "{}  {}  {}",
+                recipe.name.bright_cyan(),
+                format!("({})", recipe.scope).dimmed(),
+                recipe.description.as_deref().unwrap_or("")
+            );
+        }
+    }
+
+    /// Prints a summary line per file that differs from a checkpoint.
+    pub fn print_checkpoint_changes(&self, changes: &[FileChange]) {
+        if changes.is_empty() {
+            println!("No changes since the checkpoint");
+            return;
+        }
+        for change in changes {
+            match change.change {
+                Change::Modified => println!("{} {}", "M".yellow(), change.path),
+                Change::Deleted => println!("{} {}", "D".red(), change.path),
+                Change::Added => println!("{} {}", "A".green(), change.path),
+            }
+        }
+        println!();
+    }
+
+    /// Prints the effective config as `key = value` lines, with the origin
+    /// of each value when `show_origin` is set.
+    pub fn print_config(&self, entries: &[ConfigEntry], show_origin: bool) {
+        for entry in entries {
+            if show_origin {
+                println!(
+                    "{} = {}  {}",
+                    entry.key,
+                    entry.value,
+                    format!("# {}", entry.source).dimmed()
+                );
+            } else {
+                println!("{} = {}", entry.key, entry.value);
+            }
+        }
+    }
+
     /// Prints the application footer.
-    pub fn print_footer(&self, new_files: usize, saved_files: usize, duration: Duration) {
+    pub fn print_footer(
+        &self,
+        new_files: usize,
+        saved_files: usize,
+        skipped_files: usize,
+        duration: Duration,
+    ) {
         println!();
         println!(
             "{}",
-            format!("⚡ Created {} file(s)", saved_files)
+            format!("⚡ Created {} file(s)", new_files)
                 .bright_white()
                 .dimmed(),
         );
@@ -86,6 +432,14 @@ impl CliDisplayManager {
                 .bright_white()
                 .dimmed(),
         );
+        if skipped_files > 0 {
+            println!(
+                "{}",
+                format!("⚡ Skipped {} non-text file(s)", skipped_files)
+                    .bright_white()
+                    .dimmed(),
+            );
+        }
         println!(
             "{}",
             format!("⚡ Completed in {:.2?}", duration)
@@ -100,7 +454,7 @@ impl CliDisplayManager {
         let spinner = ProgressBar::new_spinner();
         spinner.set_style(
             ProgressStyle::with_template(&format!(
-                "   {} {{spinner}} {}",
+                "   {} {{spinner}} {} {{msg}}",
                 "→".bright_white(),
                 "Waiting for 'Preprocessor' response"
                     .italic()
@@ -118,7 +472,7 @@ impl CliDisplayManager {
         let spinner = ProgressBar::new_spinner();
         spinner.set_style(
             ProgressStyle::with_template(&format!(
-                "   {} {{spinner}} {}",
+                "   {} {{spinner}} {} {{msg}}",
                 "→".bright_white(),
                 "Waiting for 'Code Assistant' response"
                     .italic()
@@ -131,6 +485,26 @@ impl CliDisplayManager {
         self.spinner = Some(spinner);
     }
 
+    /// Returns a callback that shows streaming progress (tokens received and
+    /// elapsed time) next to the current spinner.
+    pub fn stream_progress(&self) -> impl Fn(usize) + Send + Sync {
+        let spinner = self.spinner.clone();
+        let start_time = Instant::now();
+        move |tokens| {
+            if let Some(spinner) = &spinner {
+                spinner.set_message(
+                    format!(
+                        "({} tokens, {:.1}s)",
+                        tokens,
+                        start_time.elapsed().as_secs_f32()
+                    )
+                    .dimmed()
+                    .to_string(),
+                );
+            }
+        }
+    }
+
     /// Stops the spinner.
     pub fn stop_spinner(&mut self) {
         if let Some(spinner) = &self.spinner {
@@ -150,6 +524,14 @@ impl CliDisplayManager {
         }
     }
 
+    /// Helper function to print the last lines of a command's output.
+    fn print_command_output(&self, output: &str) {
+        let lines: Vec<&str> = output.trim_end().lines().collect();
+        for line in &lines[lines.len().saturating_sub(20)..] {
+            println!("     {}", line.dimmed());
+        }
+    }
+
     /// Helper function to print an informational message.
     fn print_info(&self, message: &str) {
         println!(
@@ -159,3 +541,79 @@ impl CliDisplayManager {
         );
     }
 }
+
+impl PipelineObserver for CliDisplayManager {
+    fn on_event(&mut self, event: PipelineEvent<'_>) {
+        match event {
+            PipelineEvent::GitStashed => self.print_git_stashed(),
+            PipelineEvent::FilesDiscovered { count, skipped } => {
+                self.print_file_processing_start(count);
+                self.print_skipped_files(skipped);
+            }
+            PipelineEvent::FilesCombined => self.print_file_combining_success(),
+            PipelineEvent::QueryStarted { provider } => self.print_query_start(provider),
+            PipelineEvent::PreprocessorStarted => self.print_preprocessor_query_start(),
+            PipelineEvent::PreprocessorBatches(batch_count) => {
+                self.print_preprocessor_batches(batch_count)
+            }
+            PipelineEvent::BudgetExceeded {
+                stage,
+                estimated,
+                available,
+            } => self.print_budget_warning(stage, estimated, available),
+            PipelineEvent::WaitingForModel(Stage::Preprocessor) => {
+                self.start_spinner_preprocessor()
+            }
+            PipelineEvent::WaitingForModel(Stage::CodeAssistant) => self.start_spinner_assistant(),
+            PipelineEvent::PreprocessorFinished => {
+                self.stop_spinner();
+                self.print_preprocessor_response_success();
+            }
+            PipelineEvent::CodeAssistantStarted => self.print_code_assistant_query_start(),
+            PipelineEvent::CodeAssistantFinished => {
+                self.stop_spinner();
+                self.print_code_assistant_response_success();
+            }
+            PipelineEvent::SavingStarted => self.print_saving_results_start(),
+            PipelineEvent::SavingFinished { in_place } => {
+                self.print_saving_results_success(in_place)
+            }
+            PipelineEvent::VerifyStarted { command } => self.print_verify_start(command),
+            PipelineEvent::VerifyPassed { fix_rounds } => self.print_verify_passed(fix_rounds),
+            PipelineEvent::VerifyFailed {
+                output,
+                round,
+                max_rounds,
+            } => self.print_verify_failed(output, round, max_rounds),
+            PipelineEvent::VerifyRollingBack { output, run_id } => {
+                self.print_verify_rolling_back(output, run_id)
+            }
+            PipelineEvent::FixRoundFailed { round, run_id } => {
+                self.stop_spinner();
+                self.print_fix_round_failed(round, run_id);
+            }
+            PipelineEvent::VerifyCommandFailed { run_id } => {
+                self.stop_spinner();
+                self.print_verify_command_failed(run_id);
+            }
+            PipelineEvent::GitCommitted { branch, commit } => self.print_git_commit(branch, commit),
+            PipelineEvent::GitNothingToCommit => self.print_git_nothing_to_commit(),
+            PipelineEvent::GitRestored {
+                checked_out,
+                unstashed,
+            } => {
+                self.stop_spinner();
+                self.print_git_restored(checked_out, unstashed);
+            }
+            PipelineEvent::GitRestoreFailed { error } => {
+                self.stop_spinner();
+                self.print_git_restore_failed(error);
+            }
+            PipelineEvent::RunRecorded { run_id } => self.print_run_recorded(run_id),
+        }
+    }
+
+    fn progress(&self) -> Option<Box<ProgressFn>> {
+        Some(Box::new(self.stream_progress()))
+    }
+}
@@ -1,2 +1,3 @@
 pub mod args;
 pub mod display;
+pub mod review;
@@ -0,0 +1,259 @@
+use colored::*;
+use press::errors::AppError;
+use press::pipeline::ChangeReview;
+use similar::{capture_diff_slices, group_diff_ops, Algorithm, DiffTag};
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::process::Command;
+
+/// Number of unchanged lines shown around each hunk.
+const CONTEXT_LINES: usize = 3;
+
+/// What to do with a hunk.
+#[derive(Clone, Debug, PartialEq)]
+enum Decision {
+    Accept,
+    Reject,
+    /// Use the text the user wrote in their editor instead.
+    Edit(String),
+    /// Reject this hunk and every remaining hunk of the file.
+    Skip,
+    /// Reject this hunk and everything that hasn't been reviewed yet.
+    Quit,
+}
+
+/// A group of changes plus its surrounding context lines.
+struct Hunk {
+    old: Range<usize>,
+    new: Range<usize>,
+}
+
+/// Walks the proposed changes hunk by hunk, like `git add -p`, and keeps
+/// only what the user accepts.
+#[derive(Default)]
+pub struct Reviewer {
+    quit: bool,
+}
+
+impl Reviewer {
+    /// Creates a new `Reviewer`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ChangeReview for Reviewer {
+    /// Reviews the changes from `old` to `new` and returns the content with
+    /// only the accepted (or edited) hunks applied, or `None` when every hunk
+    /// was rejected.
+    fn review_file(
+        &mut self,
+        file_path: &str,
+        old: &str,
+        new: &str,
+    ) -> Result<Option<String>, AppError> {
+        let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+        let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+        let ops = capture_diff_slices(Algorithm::Myers, &old_lines, &new_lines);
+        let hunks: Vec<Hunk> = group_diff_ops(ops, CONTEXT_LINES)
+            .iter()
+            .filter_map(|group| {
+                let (first, last) = (group.first()?, group.last()?);
+                Some(Hunk {
+                    old: first.old_range().start..last.old_range().end,
+                    new: first.new_range().start..last.new_range().end,
+                })
+            })
+            .collect();
+
+        let mut reviewed = String::with_capacity(new.len());
+        let mut cursor = 0;
+        let mut changed = false;
+        let mut skip_rest = false;
+
+        for (index, hunk) in hunks.iter().enumerate() {
+            reviewed.push_str(&old_lines[cursor..hunk.old.start].concat());
+            cursor = hunk.old.end;
+
+            let old_text = old_lines[hunk.old.clone()].concat();
+            let new_text = new_lines[hunk.new.clone()].concat();
+
+            let decision = if self.quit || skip_rest {
+                Decision::Reject
+            } else {
+                print_hunk(file_path, index, hunks.len(), hunk, &old_lines, &new_lines);
+                ask_decision(&new_text)?
+            };
+
+            match decision {
+                Decision::Accept => {
+                    reviewed.push_str(&new_text);
+                    changed = true;
+                }
+                Decision::Edit(edited) => {
+                    changed |= edited != old_text;
+                    reviewed.push_str(&edited);
+                }
+                Decision::Reject => reviewed.push_str(&old_text),
+                Decision::Skip => {
+                    skip_rest = true;
+                    reviewed.push_str(&old_text);
+                }
+                Decision::Quit => {
+                    self.quit = true;
+                    reviewed.push_str(&old_text);
+                }
+            }
+        }
+        reviewed.push_str(&old_lines[cursor..].concat());
+
+        Ok(changed.then_some(reviewed))
+    }
+
+    /// Asks whether the new file at `file_path` should be created.
+    fn review_new_file(&mut self, file_path: &str, content: &str) -> Result<bool, AppError> {
+        if self.quit {
+            return Ok(false);
+        }
+
+        println!("{}", format!("+++ {} (new file)", file_path).bold());
+        for line in content.lines() {
+            println!("{}", format!("+{}", line).green());
+        }
+
+        loop {
+            match prompt(&format!("Create {} [a,r,q]? ", file_path))?.as_str() {
+                "a" => return Ok(true),
+                "r" => return Ok(false),
+                "q" => {
+                    self.quit = true;
+                    return Ok(false);
+                }
+                _ => println!("{}", "a - create the file, r - don't, q - quit".dimmed()),
+            }
+        }
+    }
+}
+
+/// Prints a hunk with its header and colored lines.
+fn print_hunk(
+    file_path: &str,
+    index: usize,
+    hunk_count: usize,
+    hunk: &Hunk,
+    old_lines: &[&str],
+    new_lines: &[&str],
+) {
+    println!();
+    println!(
+        "{} {}",
+        format!("--- {}", file_path).bold(),
+        format!("(hunk {}/{})", index + 1, hunk_count).dimmed()
+    );
+    println!(
+        "{}",
+        format!(
+            "@@ -{},{} +{},{} @@",
+            hunk.old.start + 1,
+            hunk.old.len(),
+            hunk.new.start + 1,
+            hunk.new.len()
+        )
+        .cyan()
+    );
+
+    let ops = capture_diff_slices(
+        Algorithm::Myers,
+        &old_lines[hunk.old.clone()],
+        &new_lines[hunk.new.clone()],
+    );
+    for op in ops {
+        let old = &old_lines[hunk.old.start..][op.old_range()];
+        let new = &new_lines[hunk.new.start..][op.new_range()];
+        match op.tag() {
+            DiffTag::Equal => old.iter().for_each(|line| print!(" {}", line)),
+            DiffTag::Delete => old.iter().for_each(|line| print_line('-', line)),
+            DiffTag::Insert => new.iter().for_each(|line| print_line('+', line)),
+            DiffTag::Replace => {
+                old.iter().for_each(|line| print_line('-', line));
+                new.iter().for_each(|line| print_line('+', line));
+            }
+        }
+    }
+    println!();
+}
+
+fn print_line(sign: char, line: &str) {
+    let line = format!("{}{}", sign, line.trim_end_matches('\n'));
+    match sign {
+        '+' => println!("{}", line.green()),
+        _ => println!("{}", line.red()),
+    }
+}
+
+/// Asks what to do with the hunk until a valid answer is given.
+fn ask_decision(new_text: &str) -> Result<Decision, AppError> {
+    loop {
+        match prompt("Apply this hunk [a,r,e,s,q]? ")?.as_str() {
+            "a" => return Ok(Decision::Accept),
+            "r" => return Ok(Decision::Reject),
+            "e" => return Ok(Decision::Edit(edit_in_editor(new_text)?)),
+            "s" => return Ok(Decision::Skip),
+            "q" => return Ok(Decision::Quit),
+            _ => println!(
+                "{}",
+                "a - accept, r - reject, e - edit, s - skip the rest of this file, q - quit"
+                    .dimmed()
+            ),
+        }
+    }
+}
+
+/// Prints `question` and reads a trimmed answer. End of input counts as quitting.
+fn prompt(question: &str) -> Result<String, AppError> {
+    print!("{}", question.bright_blue().bold());
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer)? == 0 {
+        println!();
+        return Ok("q".to_string());
+    }
+    Ok(answer.trim().to_lowercase())
+}
+
+/// Opens the proposed hunk in `$VISUAL` or `$EDITOR` and returns the saved text.
+fn edit_in_editor(text: &str) -> Result<String, AppError> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| {
+            if cfg!(windows) {
+                "notepad".to_string()
+            } else {
+                "vi".to_string()
+            }
+        });
+
+    let path = std::env::temp_dir().join(format!("press-hunk-{}.txt", std::process::id()));
+    std::fs::write(&path, text)?;
+
+    // Editors such as `code --wait` come with their own arguments
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = Command::new(program).args(words).arg(&path).status()?;
+    let edited = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+
+    if !status.success() {
+        return Err(AppError::InvalidInput(format!(
+            "Editor '{}' exited with {}",
+            editor, status
+        )));
+    }
+
+    let mut edited = edited?;
+    if text.ends_with('\n') && !edited.ends_with('\n') {
+        edited.push('\n');
+    }
+    Ok(edited)
+}
@@ -1,6 +1,6 @@
 use crate::api::errors::DeepSeekError;
+use crate::utils::config::Stage;
 use std::fmt;
-use toml;
 
 #[derive(Debug)]
 pub enum AppError {
@@ -10,9 +10,18 @@ pub enum AppError {
     InvalidPartId(String),
     MissingPrompt,
     MissingApiKey,
+    ApiKeyError(String),
     RollbackError(String),
     CheckpointError(String),
+    RecipeError(String),
     InvalidInput(String),
+    Interrupted(Stage),
+    InvalidModelResponse(String),
+    ContextBudgetExceeded(String),
+    EditNotApplied(String),
+    GitError(String),
+    CorruptBackup(String),
+    VerificationFailed(String),
 }
 
 impl fmt::Display for AppError {
@@ -23,10 +32,25 @@ impl fmt::Display for AppError {
             AppError::TomlError(e) => write!(f, "TOML parsing error: {}", e),
             AppError::MissingPrompt => write!(f, "Prompt is required"),
             AppError::InvalidPartId(e) => write!(f, "Invalid part ID: {}", e),
-            AppError::MissingApiKey => write!(f, "API key is required"),
+            AppError::MissingApiKey => write!(
+                f,
+                "API key is required: set PRESS_API_KEY or DEEPSEEK_API_KEY, pass --api-key, \
+                 or save one with `press model-config --set-api-key`"
+            ),
+            AppError::ApiKeyError(e) => write!(f, "API key error: {}", e),
             AppError::RollbackError(e) => write!(f, "Rollback error: {}", e),
             AppError::CheckpointError(e) => write!(f, "Checkpoint error: {}", e),
+            AppError::RecipeError(e) => write!(f, "Recipe error: {}", e),
             AppError::InvalidInput(e) => write!(f, "Invalid input: {}", e),
+            AppError::InvalidModelResponse(e) => write!(f, "Invalid model response: {}", e),
+            AppError::ContextBudgetExceeded(e) => write!(f, "Context budget exceeded: {}", e),
+            AppError::EditNotApplied(e) => write!(f, "Could not apply edit: {}", e),
+            AppError::GitError(e) => write!(f, "Git error: {}", e),
+            AppError::CorruptBackup(e) => write!(f, "Corrupt backup: {}", e),
+            AppError::VerificationFailed(e) => write!(f, "Verification failed: {}", e),
+            AppError::Interrupted(stage) => {
+                write!(f, "Interrupted by Ctrl-C during the {} stage", stage)
+            }
         }
     }
 }
@@ -37,12 +61,6 @@ impl From<std::io::Error> for AppError {
     }
 }
 
-impl From<std::io::Error> for DeepSeekError {
-    fn from(err: std::io::Error) -> Self {
-        DeepSeekError::ApiError(err.to_string())
-    }
-}
-
 impl From<toml::de::Error> for AppError {
     fn from(err: toml::de::Error) -> Self {
         AppError::TomlError(err)
@@ -51,6 +69,9 @@ impl From<toml::de::Error> for AppError {
 
 impl From<DeepSeekError> for AppError {
     fn from(err: DeepSeekError) -> Self {
-        AppError::DeepSeekError(err)
+        match err {
+            DeepSeekError::InvalidResponse(e) => AppError::InvalidModelResponse(e),
+            err => AppError::DeepSeekError(err),
+        }
     }
 }
@@ -0,0 +1,206 @@
+use crate::errors::AppError;
+use sha2::{Digest, Sha256};
+use std::path::{Component, Path, PathBuf};
+use tokio::fs;
+
+/// Content-addressed storage for file backups: every blob is named after the
+/// SHA-256 of its content, so identical files are stored once and a blob can
+/// always be checked against its name.
+pub struct BlobStore {
+    dir: PathBuf,
+}
+
+impl BlobStore {
+    /// Opens the store of `press.output` in `output_directory`.
+    pub fn new(output_directory: &Path) -> Self {
+        Self {
+            dir: output_directory.join("press.output").join("blobs"),
+        }
+    }
+
+    /// Stores `content` and returns its checksum.
+    pub async fn put(&self, content: &[u8]) -> Result<String, AppError> {
+        let checksum = checksum_of(content);
+        let path = self.path_of(&checksum);
+        // A blob that is already stored intact doesn't need to be written again
+        if let Ok(stored) = fs::read(&path).await {
+            if checksum_of(&stored) == checksum {
+                return Ok(checksum);
+            }
+        }
+
+        // Write to a temporary file first, so a blob is never half written
+        fs::create_dir_all(&self.dir).await?;
+        let temp_path = self.dir.join(format!("{}.tmp", checksum));
+        fs::write(&temp_path, content).await?;
+        fs::rename(&temp_path, &path).await?;
+        Ok(checksum)
+    }
+
+    /// Reads the blob `checksum`, failing if it is missing or corrupted.
+    pub async fn get(&self, checksum: &str) -> Result<Vec<u8>, AppError> {
+        let content = fs::read(self.path_of(checksum))
+            .await
+            .map_err(|e| AppError::CorruptBackup(format!("blob {} is missing: {}", checksum, e)))?;
+        if checksum_of(&content) != checksum {
+            return Err(AppError::CorruptBackup(format!(
+                "blob {} no longer matches its checksum",
+                checksum
+            )));
+        }
+        Ok(content)
+    }
+
+    /// Checks every blob in `checksums`, so a restore can fail before it
+    /// touches any file.
+    pub async fn verify<'a>(
+        &self,
+        checksums: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), AppError> {
+        for checksum in checksums {
+            self.get(checksum).await?;
+        }
+        Ok(())
+    }
+
+    fn path_of(&self, checksum: &str) -> PathBuf {
+        self.dir.join(checksum)
+    }
+}
+
+/// Hex-encoded SHA-256 of `content`.
+pub fn checksum_of(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Permission bits of the file at `path`, where the platform has them.
+pub async fn file_mode(path: &Path) -> Result<Option<u32>, AppError> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        Ok(Some(fs::metadata(path).await?.permissions().mode()))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(None)
+    }
+}
+
+/// Writes `content` to `path`, creating its parent directories and applying
+/// the recorded `mode`.
+pub async fn restore_file(path: &Path, content: &[u8], mode: Option<u32>) -> Result<(), AppError> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(path, content).await?;
+
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).await?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    Ok(())
+}
+
+/// `path` relative to the current directory when it lies inside it, without
+/// any `.` components, so the same file is always recorded the same way.
+pub fn relative_path(path: &Path) -> String {
+    let path = std::env::current_dir()
+        .ok()
+        .and_then(|current_dir| path.strip_prefix(current_dir).ok())
+        .unwrap_or(path);
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect::<PathBuf>()
+        .to_string_lossy()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn blobs_are_stored_once_under_their_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BlobStore::new(dir.path());
+
+        let checksum = store.put(b"content").await.unwrap();
+        assert_eq!(checksum, checksum_of(b"content"));
+        assert_eq!(store.put(b"content").await.unwrap(), checksum);
+        assert_eq!(store.get(&checksum).await.unwrap(), b"content");
+        assert_eq!(std::fs::read_dir(&store.dir).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn missing_and_corrupt_blobs_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BlobStore::new(dir.path());
+        let intact = store.put(b"intact").await.unwrap();
+        let corrupt = store.put(b"corrupt").await.unwrap();
+        std::fs::write(store.path_of(&corrupt), b"tampered").unwrap();
+        let missing = checksum_of(b"missing");
+
+        assert!(matches!(
+            store.get(&missing).await,
+            Err(AppError::CorruptBackup(_))
+        ));
+        assert!(matches!(
+            store.get(&corrupt).await,
+            Err(AppError::CorruptBackup(_))
+        ));
+        store.verify([intact.as_str()]).await.unwrap();
+        assert!(store
+            .verify([intact.as_str(), corrupt.as_str()])
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn a_corrupt_blob_is_rewritten_when_stored_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BlobStore::new(dir.path());
+        let checksum = store.put(b"content").await.unwrap();
+        std::fs::write(store.path_of(&checksum), b"tampered").unwrap();
+
+        store.put(b"content").await.unwrap();
+        assert_eq!(store.get(&checksum).await.unwrap(), b"content");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn restored_files_get_their_mode_back() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bin").join("run.sh");
+
+        restore_file(&path, b"#!/bin/sh\n", Some(0o100755))
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"#!/bin/sh\n");
+        assert_eq!(file_mode(&path).await.unwrap().unwrap() & 0o777, 0o755);
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+    }
+
+    #[test]
+    fn relative_paths_drop_current_dir_components() {
+        assert_eq!(
+            relative_path(Path::new("./src/./main.rs")),
+            Path::new("src").join("main.rs").to_string_lossy()
+        );
+    }
+}
@@ -0,0 +1,458 @@
+use super::blobs::{self, checksum_of, BlobStore};
+use super::history::confirm;
+use super::reader;
+use crate::errors::AppError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// A checkpoint as recorded in `press.output/checkpoints/<name>.toml`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Checkpoint {
+    pub name: String,
+    pub created_at: String,
+    /// Directories and files the checkpoint was taken of.
+    pub paths: Vec<String>,
+    pub files: Vec<CheckpointFile>,
+}
+
+/// A file saved in a checkpoint. Its content lives in the backup store.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CheckpointFile {
+    /// Path relative to the directory press ran in.
+    pub path: String,
+    /// Permission bits, on platforms that have them.
+    pub mode: Option<u32>,
+    pub checksum: String,
+}
+
+/// How a file in the working tree differs from a checkpoint.
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    Modified,
+    /// In the checkpoint but deleted since.
+    Deleted,
+    /// Created since the checkpoint.
+    Added,
+}
+
+/// A file that differs from a checkpoint, with its content on both sides
+/// (`None` where the file doesn't exist).
+pub struct FileChange {
+    pub path: String,
+    pub change: Change,
+    pub checkpoint_content: Option<Vec<u8>>,
+    pub current_content: Option<Vec<u8>>,
+}
+
+/// Saves every file under `paths` as the checkpoint `name`.
+pub async fn create(
+    output_directory: &Path,
+    name: &str,
+    paths: &[String],
+) -> Result<Checkpoint, AppError> {
+    validate_name(name)?;
+    let manifest_path = manifest_path(output_directory, name);
+    if manifest_path.exists() {
+        return Err(AppError::CheckpointError(format!(
+            "Checkpoint '{}' already exists, delete it first",
+            name
+        )));
+    }
+
+    if let Some(missing) = paths.iter().find(|path| !Path::new(path).exists()) {
+        return Err(AppError::CheckpointError(format!(
+            "Path does not exist: {}",
+            missing
+        )));
+    }
+
+    let blob_store = BlobStore::new(output_directory);
+    let mut files = Vec::new();
+    for file_path in collect_files(paths) {
+        let content = fs::read(&file_path).await?;
+        files.push(CheckpointFile {
+            path: blobs::relative_path(&file_path),
+            mode: blobs::file_mode(&file_path).await?,
+            checksum: blob_store.put(&content).await?,
+        });
+    }
+
+    let checkpoint = Checkpoint {
+        name: name.to_string(),
+        created_at: chrono::Local::now().to_rfc3339(),
+        paths: paths.to_vec(),
+        files,
+    };
+    let checkpoint_str =
+        toml::to_string(&checkpoint).map_err(|e| AppError::CheckpointError(e.to_string()))?;
+    fs::create_dir_all(checkpoints_dir(output_directory)).await?;
+    fs::write(manifest_path, checkpoint_str).await?;
+
+    Ok(checkpoint)
+}
+
+/// Loads every checkpoint, oldest first.
+pub async fn list(output_directory: &Path) -> Result<Vec<Checkpoint>, AppError> {
+    let checkpoints_dir = checkpoints_dir(output_directory);
+    if !checkpoints_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut checkpoints = Vec::new();
+    let mut entries = fs::read_dir(&checkpoints_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.path().extension().is_some_and(|ext| ext == "toml") {
+            checkpoints.push(read_manifest(&entry.path()).await?);
+        }
+    }
+    checkpoints.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    Ok(checkpoints)
+}
+
+/// Loads the checkpoint `name`.
+pub async fn load(output_directory: &Path, name: &str) -> Result<Checkpoint, AppError> {
+    validate_name(name)?;
+    let manifest_path = manifest_path(output_directory, name);
+    if !manifest_path.exists() {
+        return Err(AppError::CheckpointError(format!(
+            "No checkpoint named '{}'",
+            name
+        )));
+    }
+    read_manifest(&manifest_path).await
+}
+
+/// Compares the working tree under the checkpoint's paths with the
+/// checkpoint, returning the files that were modified, deleted or added.
+pub async fn diff(
+    output_directory: &Path,
+    checkpoint: &Checkpoint,
+) -> Result<Vec<FileChange>, AppError> {
+    let blob_store = BlobStore::new(output_directory);
+    let mut current: BTreeMap<String, PathBuf> = collect_files(&checkpoint.paths)
+        .into_iter()
+        .map(|path| (blobs::relative_path(&path), path))
+        .collect();
+
+    let mut changes = Vec::new();
+    for file in &checkpoint.files {
+        // Checkpoints taken before ignore files were honoured may hold
+        // ignored files, which are compared where they are
+        let current_path = current.remove(&file.path).or_else(|| {
+            Path::new(&file.path)
+                .is_file()
+                .then(|| PathBuf::from(&file.path))
+        });
+        match current_path {
+            Some(path) => {
+                let current_content = fs::read(&path).await?;
+                if checksum_of(&current_content) != file.checksum {
+                    changes.push(FileChange {
+                        path: file.path.clone(),
+                        change: Change::Modified,
+                        checkpoint_content: Some(blob_store.get(&file.checksum).await?),
+                        current_content: Some(current_content),
+                    });
+                }
+            }
+            None => changes.push(FileChange {
+                path: file.path.clone(),
+                change: Change::Deleted,
+                checkpoint_content: Some(blob_store.get(&file.checksum).await?),
+                current_content: None,
+            }),
+        }
+    }
+
+    // Whatever is left wasn't there when the checkpoint was taken
+    for (relative_path, path) in current {
+        changes.push(FileChange {
+            path: relative_path,
+            change: Change::Added,
+            checkpoint_content: None,
+            current_content: Some(fs::read(&path).await?),
+        });
+    }
+
+    Ok(changes)
+}
+
+/// Puts the working tree back the way it was when the checkpoint was taken:
+/// modified and deleted files are restored, and files added since are removed.
+///
+/// The files to remove are listed and need confirmation unless `force` is
+/// set. Every blob is verified before any file is touched.
+pub async fn restore(
+    output_directory: &Path,
+    checkpoint: &Checkpoint,
+    force: bool,
+) -> Result<(), AppError> {
+    let blob_store = BlobStore::new(output_directory);
+    blob_store
+        .verify(checkpoint.files.iter().map(|file| file.checksum.as_str()))
+        .await?;
+
+    let changes = diff(output_directory, checkpoint).await?;
+    let added: Vec<&str> = changes
+        .iter()
+        .filter(|change| change.change == Change::Added)
+        .map(|change| change.path.as_str())
+        .collect();
+    if !added.is_empty() && !force {
+        println!(
+            "These files were created after checkpoint '{}':",
+            checkpoint.name
+        );
+        for path in &added {
+            println!("  {}", path);
+        }
+        if !confirm("Restoring will delete them. Continue? [y/N] ")? {
+            return Err(AppError::CheckpointError("Restore cancelled".to_string()));
+        }
+    }
+
+    for change in changes {
+        let path = Path::new(&change.path);
+        match (change.change, change.checkpoint_content) {
+            (Change::Added, _) => {
+                fs::remove_file(path).await?;
+                println!("Deleted: {}", path.display());
+            }
+            (_, Some(content)) => {
+                let mode = checkpoint
+                    .files
+                    .iter()
+                    .find(|file| file.path == change.path)
+                    .and_then(|file| file.mode);
+                blobs::restore_file(path, &content, mode).await?;
+                println!("Restored: {}", path.display());
+            }
+            (_, None) => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Deletes the checkpoint `name`. Its blobs stay in the backup store, where
+/// runs and other checkpoints may share them.
+pub async fn delete(output_directory: &Path, name: &str) -> Result<(), AppError> {
+    load(output_directory, name).await?;
+    fs::remove_file(manifest_path(output_directory, name)).await?;
+    Ok(())
+}
+
+/// Every file under `paths`, walked like the files of a run: ignore files
+/// are honoured and `.git` and press's own output are skipped.
+/// Paths that don't exist (anymore) have no files.
+fn collect_files(paths: &[String]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for path_str in paths {
+        let path = Path::new(path_str);
+        if path.is_dir() {
+            files.extend(reader::walk_directory(path));
+        } else if path.exists() {
+            files.push(path.to_path_buf());
+        }
+    }
+    // Overlapping paths shouldn't save the same file twice
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// Only plain names are allowed, since the name becomes a file name.
+fn validate_name(name: &str) -> Result<(), AppError> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::CheckpointError(format!(
+            "Invalid checkpoint name '{}', use letters, digits, '-', '_' and '.'",
+            name
+        )))
+    }
+}
+
+fn checkpoints_dir(output_directory: &Path) -> PathBuf {
+    output_directory.join("press.output").join("checkpoints")
+}
+
+fn manifest_path(output_directory: &Path, name: &str) -> PathBuf {
+    checkpoints_dir(output_directory).join(format!("{}.toml", name))
+}
+
+async fn read_manifest(path: &Path) -> Result<Checkpoint, AppError> {
+    let checkpoint_str = fs::read_to_string(path).await?;
+    toml::from_str(&checkpoint_str).map_err(|e| AppError::CheckpointError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A project with two files and a separate output directory.
+    struct Fixture {
+        project: tempfile::TempDir,
+        output: tempfile::TempDir,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let fixture = Self {
+                project: tempfile::tempdir().unwrap(),
+                output: tempfile::tempdir().unwrap(),
+            };
+            std::fs::create_dir(fixture.path("src")).unwrap();
+            std::fs::write(fixture.path("src/main.rs"), "main").unwrap();
+            std::fs::write(fixture.path("src/lib.rs"), "lib").unwrap();
+            fixture
+        }
+
+        fn path(&self, relative: &str) -> PathBuf {
+            self.project.path().join(relative)
+        }
+
+        fn paths(&self) -> Vec<String> {
+            vec![self.project.path().to_string_lossy().to_string()]
+        }
+
+        fn read(&self, relative: &str) -> Option<String> {
+            std::fs::read_to_string(self.path(relative)).ok()
+        }
+
+        /// Modifies main.rs, deletes lib.rs and adds new.rs.
+        fn change_files(&self) {
+            std::fs::write(self.path("src/main.rs"), "changed").unwrap();
+            std::fs::remove_file(self.path("src/lib.rs")).unwrap();
+            std::fs::write(self.path("src/new.rs"), "new").unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn diff_lists_modified_deleted_and_added_files() {
+        let fixture = Fixture::new();
+        let checkpoint = create(fixture.output.path(), "before", &fixture.paths())
+            .await
+            .unwrap();
+        assert_eq!(checkpoint.files.len(), 2);
+        assert!(diff(fixture.output.path(), &checkpoint)
+            .await
+            .unwrap()
+            .is_empty());
+
+        fixture.change_files();
+        let mut changes: Vec<(String, Change)> = diff(fixture.output.path(), &checkpoint)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|change| (change.path, change.change))
+            .collect();
+        changes.sort_by(|a, b| a.0.cmp(&b.0));
+        let path = |relative| blobs::relative_path(&fixture.path(relative));
+        assert_eq!(
+            changes,
+            vec![
+                (path("src/lib.rs"), Change::Deleted),
+                (path("src/main.rs"), Change::Modified),
+                (path("src/new.rs"), Change::Added),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn restore_puts_back_changed_files_and_deletes_added_ones() {
+        let fixture = Fixture::new();
+        create(fixture.output.path(), "before", &fixture.paths())
+            .await
+            .unwrap();
+        fixture.change_files();
+
+        let checkpoint = load(fixture.output.path(), "before").await.unwrap();
+        restore(fixture.output.path(), &checkpoint, true)
+            .await
+            .unwrap();
+        assert_eq!(fixture.read("src/main.rs").as_deref(), Some("main"));
+        assert_eq!(fixture.read("src/lib.rs").as_deref(), Some("lib"));
+        assert_eq!(fixture.read("src/new.rs"), None);
+        assert!(diff(fixture.output.path(), &checkpoint)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn restore_touches_nothing_when_a_blob_is_corrupt() {
+        let fixture = Fixture::new();
+        let checkpoint = create(fixture.output.path(), "before", &fixture.paths())
+            .await
+            .unwrap();
+        fixture.change_files();
+        let blob = fixture
+            .output
+            .path()
+            .join("press.output")
+            .join("blobs")
+            .join(checksum_of(b"lib"));
+        std::fs::write(&blob, "tampered").unwrap();
+
+        assert!(matches!(
+            restore(fixture.output.path(), &checkpoint, true).await,
+            Err(AppError::CorruptBackup(_))
+        ));
+        std::fs::remove_file(&blob).unwrap();
+        assert!(matches!(
+            restore(fixture.output.path(), &checkpoint, true).await,
+            Err(AppError::CorruptBackup(_))
+        ));
+        assert_eq!(fixture.read("src/main.rs").as_deref(), Some("changed"));
+        assert_eq!(fixture.read("src/lib.rs"), None);
+        assert_eq!(fixture.read("src/new.rs").as_deref(), Some("new"));
+    }
+
+    #[tokio::test]
+    async fn checkpoints_are_listed_and_deleted_by_name() {
+        let fixture = Fixture::new();
+        let output = fixture.output.path();
+        create(output, "first", &fixture.paths()).await.unwrap();
+        create(output, "second", &fixture.paths()).await.unwrap();
+        assert!(matches!(
+            create(output, "first", &fixture.paths()).await,
+            Err(AppError::CheckpointError(_))
+        ));
+
+        let names: Vec<String> = list(output)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|checkpoint| checkpoint.name)
+            .collect();
+        assert_eq!(names, ["first", "second"]);
+
+        delete(output, "first").await.unwrap();
+        assert!(matches!(
+            load(output, "first").await,
+            Err(AppError::CheckpointError(_))
+        ));
+        assert!(delete(output, "first").await.is_err());
+        // The blobs stay for the checkpoints sharing them
+        let second = load(output, "second").await.unwrap();
+        restore(output, &second, true).await.unwrap();
+    }
+
+    #[test]
+    fn names_must_be_plain_file_names() {
+        for name in ["before-refactor", "v1.2", "snake_case"] {
+            assert!(validate_name(name).is_ok(), "{}", name);
+        }
+        for name in ["", ".hidden", "../escape", "a/b", "a b"] {
+            assert!(validate_name(name).is_err(), "{}", name);
+        }
+    }
+}
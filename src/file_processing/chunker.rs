@@ -0,0 +1,348 @@
+use crate::utils::config::ChunkStrategy;
+use std::path::Path;
+
+/// Lines starting with one of these attach to the item that follows them.
+const ATTACHED_PREFIXES: [&str; 6] = ["//", "/*", "*", "#", "@", "--"];
+
+/// How top-level items are recognised in a file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Syntax {
+    /// Languages whose blocks are delimited by braces (Rust, C-family, JS/TS, Go, ...).
+    Braces,
+    /// Languages whose blocks are delimited by indentation (Python).
+    Indentation,
+    /// Anything else: items are paragraphs separated by blank lines.
+    Paragraphs,
+}
+
+impl Syntax {
+    fn for_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+
+        match extension.as_str() {
+            "rs" | "c" | "h" | "cc" | "cpp" | "hpp" | "cs" | "java" | "kt" | "kts" | "scala"
+            | "swift" | "go" | "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" | "php" | "dart"
+            | "css" | "scss" | "proto" => Syntax::Braces,
+            "py" | "pyi" => Syntax::Indentation,
+            _ => Syntax::Paragraphs,
+        }
+    }
+}
+
+/// Splits `contents` into parts according to `strategy`.
+///
+/// Joining the returned parts with `"\n"` always yields the lines of the
+/// original content, so part IDs (their 1-based positions) stay stable
+/// between reading a file and writing the edited parts back.
+pub fn split_into_parts(
+    path: &Path,
+    contents: &str,
+    chunk_size: usize,
+    strategy: ChunkStrategy,
+) -> Vec<String> {
+    let lines: Vec<&str> = contents.lines().collect();
+    let chunk_size = chunk_size.max(1);
+
+    let ranges = match strategy {
+        ChunkStrategy::Lines => fixed_ranges(0, lines.len(), chunk_size),
+        ChunkStrategy::Syntax => {
+            let items = item_ranges(&lines, Syntax::for_path(path));
+            pack_ranges(&lines, &items, chunk_size)
+        }
+    };
+
+    ranges
+        .into_iter()
+        .map(|(start, end)| lines[start..end].join("\n"))
+        .collect()
+}
+
+/// Splits `start..end` into windows of at most `chunk_size` lines.
+fn fixed_ranges(start: usize, end: usize, chunk_size: usize) -> Vec<(usize, usize)> {
+    (start..end)
+        .step_by(chunk_size)
+        .map(|window_start| (window_start, (window_start + chunk_size).min(end)))
+        .collect()
+}
+
+/// Returns the line ranges of the top-level items of a file.
+fn item_ranges(lines: &[&str], syntax: Syntax) -> Vec<(usize, usize)> {
+    let mut starts = vec![0];
+    let mut braces = BraceState::default();
+
+    for (index, line) in lines.iter().enumerate() {
+        // A line inside a block or a multi-line template string can't start an item
+        let nested = braces.depth > 0 || braces.in_template;
+        if syntax == Syntax::Braces {
+            braces.scan(line);
+        }
+
+        if index > 0 && starts_item(lines, index, nested, syntax) {
+            starts.push(index);
+        }
+    }
+
+    starts.push(lines.len());
+    starts.dedup();
+    starts.windows(2).map(|pair| (pair[0], pair[1])).collect()
+}
+
+/// Whether line `index` starts a new top-level item.
+fn starts_item(lines: &[&str], index: usize, nested: bool, syntax: Syntax) -> bool {
+    let line = lines[index];
+    let previous = lines[index - 1];
+    let trimmed = line.trim_start();
+
+    if trimmed.is_empty() {
+        return false;
+    }
+
+    let previous_blank = previous.trim().is_empty();
+    if syntax == Syntax::Paragraphs {
+        return previous_blank;
+    }
+
+    // Only unindented lines outside of any block can start an item
+    if nested || trimmed.len() != line.len() {
+        return false;
+    }
+    if trimmed.starts_with(['}', ')', ']', '{']) {
+        return false;
+    }
+
+    // Comments, attributes and decorators belong to the item below them
+    if !previous_blank && is_attached(previous) {
+        return false;
+    }
+
+    match syntax {
+        Syntax::Braces => {
+            let previous = previous.trim_end();
+            previous_blank
+                || previous.ends_with('}')
+                || previous.ends_with(';')
+                || previous.ends_with("};")
+        }
+        Syntax::Indentation => {
+            let continues = ["else", "elif", "except", "finally"]
+                .iter()
+                .any(|keyword| trimmed.starts_with(keyword));
+            let previous = previous.trim_end();
+            !continues && !previous.ends_with(['\\', ',', '(', '[', '{'])
+        }
+        Syntax::Paragraphs => unreachable!(),
+    }
+}
+
+/// Whether an unindented line is a comment, attribute or decorator.
+fn is_attached(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.len() == line.len()
+        && ATTACHED_PREFIXES
+            .iter()
+            .any(|prefix| trimmed.starts_with(prefix))
+}
+
+/// Brace nesting at the end of a line, along with the block comment or
+/// template string still open there.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct BraceState {
+    depth: usize,
+    in_block_comment: bool,
+    in_template: bool,
+}
+
+impl BraceState {
+    /// Tracks brace nesting through `line`, ignoring braces inside string,
+    /// char and template literals and inside comments.
+    fn scan(&mut self, line: &str) {
+        let chars: Vec<char> = line.chars().collect();
+        let mut in_string = false;
+        let mut index = 0;
+
+        while index < chars.len() {
+            let c = chars[index];
+            let next = chars.get(index + 1).copied();
+            index += 1;
+
+            if self.in_block_comment {
+                if c == '*' && next == Some('/') {
+                    self.in_block_comment = false;
+                    index += 1;
+                }
+                continue;
+            }
+            if in_string || self.in_template {
+                match c {
+                    '\\' => index += 1,
+                    '"' if in_string => in_string = false,
+                    '`' if self.in_template => self.in_template = false,
+                    _ => {}
+                }
+                continue;
+            }
+
+            match c {
+                '"' => in_string = true,
+                '`' => self.in_template = true,
+                '\'' => index += char_literal_length(&chars[index..]),
+                '/' if next == Some('/') => break,
+                '/' if next == Some('*') => {
+                    self.in_block_comment = true;
+                    index += 1;
+                }
+                '{' => self.depth += 1,
+                '}' => self.depth = self.depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Length of the rest of a char literal after its opening quote, like `{'`
+/// or `\u{7b}'`, or 0 when the quote doesn't open one (a Rust lifetime, an
+/// apostrophe in text).
+fn char_literal_length(rest: &[char]) -> usize {
+    match rest {
+        ['\\', ..] => rest
+            .iter()
+            .skip(2)
+            .position(|&c| c == '\'')
+            .map_or(0, |close| close + 3),
+        [_, '\'', ..] => 2,
+        _ => 0,
+    }
+}
+
+/// Packs consecutive items into parts of at most `chunk_size` lines.
+/// Items longer than `chunk_size` are split at blank lines, and only cut
+/// mid-paragraph when there is no blank line to split at.
+fn pack_ranges(lines: &[&str], items: &[(usize, usize)], chunk_size: usize) -> Vec<(usize, usize)> {
+    let mut pieces = Vec::new();
+    for &(start, end) in items {
+        if end - start <= chunk_size {
+            pieces.push((start, end));
+        } else {
+            for (paragraph_start, paragraph_end) in paragraph_ranges(lines, start, end) {
+                pieces.extend(fixed_ranges(paragraph_start, paragraph_end, chunk_size));
+            }
+        }
+    }
+
+    let mut parts: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in pieces {
+        match parts.last_mut() {
+            Some(last) if end - last.0 <= chunk_size => last.1 = end,
+            _ => parts.push((start, end)),
+        }
+    }
+    parts
+}
+
+/// Splits `start..end` after every run of blank lines.
+fn paragraph_ranges(lines: &[&str], start: usize, end: usize) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut paragraph_start = start;
+
+    for index in start + 1..end {
+        let previous_blank = lines[index - 1].trim().is_empty();
+        if previous_blank && !lines[index].trim().is_empty() {
+            ranges.push((paragraph_start, index));
+            paragraph_start = index;
+        }
+    }
+
+    ranges.push((paragraph_start, end));
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RUST: &str = "use std::fmt;\n\n/// A point.\n#[derive(Debug)]\nstruct Point {\n    x: i32,\n    y: i32,\n}\n\nimpl Point {\n    fn new() -> Self {\n        Point { x: 0, y: 0 }\n    }\n\n    fn label(&self) -> &str {\n        \"}\"\n    }\n}\nfn main() {\n    let p = Point::new(); // {\n}\n";
+
+    const PYTHON: &str = "import os\n\n@decorator\ndef a(x,\n      y):\n    if x:\n        return y\n    else:\n        return x\n\nclass B:\n    def c(self):\n        pass\nz = a(1, 2)\n";
+
+    const TEXT: &str =
+        "# Title\n\nFirst paragraph\nstill first.\n\n\nSecond paragraph.\r\nWith CRLF.\r\n";
+
+    fn assert_round_trip(path: &str, contents: &str) {
+        let original: Vec<&str> = contents.lines().collect();
+        for strategy in [ChunkStrategy::Lines, ChunkStrategy::Syntax] {
+            for chunk_size in [0, 1, 2, 3, 5, 8, 50] {
+                let parts = split_into_parts(Path::new(path), contents, chunk_size, strategy);
+                assert_eq!(
+                    parts.join("\n"),
+                    original.join("\n"),
+                    "{} with {:?} and chunk size {}",
+                    path,
+                    strategy,
+                    chunk_size
+                );
+                assert!(parts
+                    .iter()
+                    .all(|part| part.lines().count() <= chunk_size.max(1)));
+            }
+        }
+    }
+
+    #[test]
+    fn joining_the_parts_gives_back_the_original_lines() {
+        assert_round_trip("main.rs", RUST);
+        assert_round_trip("script.py", PYTHON);
+        assert_round_trip("README.md", TEXT);
+        assert_round_trip("empty.rs", "");
+        assert_round_trip("blank.rs", "\n\n\n");
+    }
+
+    #[test]
+    fn items_start_at_top_level_declarations_with_their_attributes() {
+        let lines: Vec<&str> = RUST.lines().collect();
+        assert_eq!(
+            item_ranges(&lines, Syntax::Braces),
+            vec![(0, 2), (2, 9), (9, 18), (18, 21)]
+        );
+    }
+
+    #[test]
+    fn a_brace_in_a_char_literal_does_not_open_a_block() {
+        let contents = "fn open() -> char {\n    '{'\n}\n\nfn quote<'a>(s: &'a str) -> char {\n    '\\''\n}\n\nfn last() {}\n";
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(
+            item_ranges(&lines, Syntax::Braces),
+            vec![(0, 4), (4, 8), (8, 9)]
+        );
+    }
+
+    #[test]
+    fn braces_in_block_comments_and_template_strings_are_ignored() {
+        let contents = "/* {\n{ */\nfunction a() {\n  return `\n{\n`;\n}\n\nfunction b() {}\n";
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(item_ranges(&lines, Syntax::Braces), vec![(0, 8), (8, 9)]);
+    }
+
+    #[test]
+    fn brace_state_carries_comments_and_templates_across_lines() {
+        let mut braces = BraceState::default();
+        braces.scan("let s = `${a} {");
+        assert_eq!(braces.depth, 0);
+        assert!(braces.in_template);
+        braces.scan("}` + f(/* {");
+        assert!(!braces.in_template);
+        assert!(braces.in_block_comment);
+        braces.scan("*/ x) { '\\u{7b}' \"{\" // {");
+        assert_eq!(
+            braces,
+            BraceState {
+                depth: 1,
+                in_block_comment: false,
+                in_template: false,
+            }
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use crate::errors::AppError;
+use crate::models::code_assistant_response::SearchReplace;
+use std::borrow::Cow;
+
+/// Applies `edits` to `content` in order, each to the result of the previous one.
+///
+/// Every search text has to match exactly once; an edit that matches nowhere
+/// or in several places fails the whole file, so nothing is written for it.
+/// In a file with CRLF line endings, the edits' LF line endings are converted
+/// to match.
+pub fn apply_search_replace(
+    file_path: &str,
+    content: &str,
+    edits: &[SearchReplace],
+) -> Result<String, AppError> {
+    let crlf = content.contains("\r\n");
+    let mut content = content.to_string();
+
+    for (index, edit) in edits.iter().enumerate() {
+        let (search, replace) = if crlf {
+            (
+                Cow::Owned(to_crlf(&edit.search)),
+                Cow::Owned(to_crlf(&edit.replace)),
+            )
+        } else {
+            (
+                Cow::Borrowed(edit.search.as_str()),
+                Cow::Borrowed(edit.replace.as_str()),
+            )
+        };
+        let describe = || {
+            format!(
+                "edit {} of {} in {} (search starting with {:?})",
+                index + 1,
+                edits.len(),
+                file_path,
+                edit.search.lines().next().unwrap_or_default()
+            )
+        };
+
+        if search.is_empty() {
+            return Err(AppError::EditNotApplied(format!(
+                "{} has an empty search text",
+                describe()
+            )));
+        }
+
+        let Some(start) = content.find(&*search) else {
+            return Err(AppError::EditNotApplied(format!(
+                "{} does not match the file exactly",
+                describe()
+            )));
+        };
+        // Look for a second match starting one character later, so overlapping matches count too
+        let next_char = content[start..].chars().next().map_or(1, char::len_utf8);
+        if content[start + next_char..].contains(&*search) {
+            return Err(AppError::EditNotApplied(format!(
+                "{} matches more than one place, it needs more context to be unique",
+                describe()
+            )));
+        }
+
+        content.replace_range(start..start + search.len(), &replace);
+    }
+
+    Ok(content)
+}
+
+/// Turns the LF line endings of `text` into CRLF, leaving existing CRLFs alone.
+fn to_crlf(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\n', "\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(search: &str, replace: &str) -> SearchReplace {
+        SearchReplace {
+            search: search.to_string(),
+            replace: replace.to_string(),
+        }
+    }
+
+    #[test]
+    fn applies_edits_in_order() {
+        let content = "fn a() {}\nfn b() {}\n";
+        let edits = [edit("fn a() {}", "fn a2() {}"), edit("a2", "a3")];
+        assert_eq!(
+            apply_search_replace("lib.rs", content, &edits).unwrap(),
+            "fn a3() {}\nfn b() {}\n"
+        );
+    }
+
+    #[test]
+    fn rejects_a_search_that_matches_nowhere() {
+        let result = apply_search_replace("lib.rs", "fn a() {}\n", &[edit("fn b", "fn c")]);
+        assert!(
+            matches!(result, Err(AppError::EditNotApplied(message)) if message.contains("does not match"))
+        );
+    }
+
+    #[test]
+    fn rejects_an_ambiguous_search() {
+        let result =
+            apply_search_replace("lib.rs", "x = 1;\nx = 1;\n", &[edit("x = 1;", "x = 2;")]);
+        assert!(
+            matches!(result, Err(AppError::EditNotApplied(message)) if message.contains("more than one place"))
+        );
+    }
+
+    #[test]
+    fn counts_overlapping_matches_as_ambiguous() {
+        let result = apply_search_replace("lib.rs", "aaa", &[edit("aa", "b")]);
+        assert!(matches!(result, Err(AppError::EditNotApplied(_))));
+    }
+
+    #[test]
+    fn rejects_an_empty_search() {
+        let result = apply_search_replace("lib.rs", "fn a() {}\n", &[edit("", "x")]);
+        assert!(
+            matches!(result, Err(AppError::EditNotApplied(message)) if message.contains("empty search"))
+        );
+    }
+
+    #[test]
+    fn a_failed_edit_fails_the_whole_file() {
+        let edits = [edit("fn a", "fn b"), edit("missing", "x")];
+        assert!(apply_search_replace("lib.rs", "fn a() {}\n", &edits).is_err());
+    }
+
+    #[test]
+    fn keeps_crlf_line_endings() {
+        let content = "fn a() {\r\n    1\r\n}\r\n";
+        let edits = [edit("{\n    1\n}", "{\n    2\n}")];
+        assert_eq!(
+            apply_search_replace("lib.rs", content, &edits).unwrap(),
+            "fn a() {\r\n    2\r\n}\r\n"
+        );
+    }
+}
@@ -0,0 +1,576 @@
+use super::blobs::{self, checksum_of, BlobStore};
+use crate::errors::AppError;
+use crate::utils::config::Config;
+use crate::utils::git;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// A run as recorded in `press.output/runs/<id>/run.toml`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RunRecord {
+    pub id: String,
+    pub created_at: String,
+    pub prompt: String,
+    pub paths: Vec<String>,
+    /// Manifest of the existing files the run changed.
+    #[serde(default)]
+    pub modified_files: Vec<ModifiedFile>,
+    /// Files the run created.
+    #[serde(default)]
+    pub new_files: Vec<CreatedFile>,
+    /// Set when the run was committed in git mode instead of backed up.
+    pub git: Option<GitRecord>,
+    pub rolled_back_at: Option<String>,
+}
+
+/// An existing file changed by a run.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ModifiedFile {
+    /// Path relative to the directory press ran in.
+    pub path: String,
+    /// Permission bits of the original file, on platforms that have them.
+    pub mode: Option<u32>,
+    /// Checksum of the original content, naming its blob in the backup store.
+    /// Missing in records of older git mode runs, which weren't backed up.
+    pub original_checksum: Option<String>,
+    /// Checksum of the file right after the run, to detect later changes.
+    pub checksum: String,
+}
+
+/// A file created by a run.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreatedFile {
+    pub path: String,
+    pub checksum: String,
+}
+
+/// The branch and base commit of a run made in git mode.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GitRecord {
+    pub branch: String,
+    pub base_commit: String,
+}
+
+/// Whether a recorded file still looks the way the run left it.
+#[derive(Debug, PartialEq)]
+pub enum FileState {
+    Unchanged,
+    Changed,
+    Missing,
+}
+
+impl RunRecord {
+    /// Checks every file touched by the run against the checksum recorded
+    /// when it was written.
+    pub async fn file_states(&self) -> Vec<(&str, FileState)> {
+        let recorded = self
+            .modified_files
+            .iter()
+            .map(|file| (file.path.as_str(), file.checksum.as_str()))
+            .chain(
+                self.new_files
+                    .iter()
+                    .map(|file| (file.path.as_str(), file.checksum.as_str())),
+            );
+
+        let mut states = Vec::new();
+        for (path, checksum) in recorded {
+            let state = match fs::read(path).await {
+                Ok(content) if checksum_of(&content) == checksum => FileState::Unchanged,
+                Ok(_) => FileState::Changed,
+                Err(_) => FileState::Missing,
+            };
+            states.push((path, state));
+        }
+        states
+    }
+}
+
+/// Records a run while its changes are being applied.
+///
+/// The record is written ahead of every file the run touches, so a run that
+/// stops halfway (an IO error, a crash) can still be rolled back.
+pub struct RunRecorder {
+    dir: PathBuf,
+    blobs: BlobStore,
+    record: RunRecord,
+}
+
+impl RunRecorder {
+    /// Creates the run directory and stores the prompt and a snapshot of the
+    /// config (without the API key).
+    pub async fn start(
+        output_directory: &Path,
+        prompt: &str,
+        paths: &[String],
+        config: &Config,
+    ) -> Result<Self, AppError> {
+        let now = chrono::Local::now();
+        let id = format!(
+            "{}-{:04x}",
+            now.format("%Y%m%d-%H%M%S"),
+            rand::thread_rng().gen::<u16>()
+        );
+        let dir = runs_dir(output_directory).join(&id);
+        fs::create_dir_all(&dir).await?;
+
+        let mut config_snapshot = toml::Value::try_from(config)
+            .map_err(|e| AppError::InvalidInput(format!("Could not snapshot config: {}", e)))?;
+        if let Some(table) = config_snapshot.as_table_mut() {
+            table.remove("api_key");
+        }
+        fs::write(dir.join("config.toml"), config_snapshot.to_string()).await?;
+
+        Ok(Self {
+            dir,
+            blobs: BlobStore::new(output_directory),
+            record: RunRecord {
+                id,
+                created_at: now.to_rfc3339(),
+                prompt: prompt.to_string(),
+                paths: paths.to_vec(),
+                modified_files: Vec::new(),
+                new_files: Vec::new(),
+                git: None,
+                rolled_back_at: None,
+            },
+        })
+    }
+
+    /// Records `path` before the run overwrites it and backs it up, even in
+    /// git mode, where a run that fails before its commit is rolled back from
+    /// the backups. `content_after_run` is what the file will contain once
+    /// the run is done.
+    ///
+    /// A file the run already changed or created keeps its first record, so
+    /// rollback still restores the content from before the run.
+    pub async fn record_modified_file(
+        &mut self,
+        path: &Path,
+        content_after_run: &str,
+    ) -> Result<(), AppError> {
+        let relative_path = blobs::relative_path(path);
+        let checksum = checksum_of(content_after_run.as_bytes());
+        if let Some(file) = self
+            .record
+            .modified_files
+            .iter_mut()
+            .find(|file| file.path == relative_path)
+        {
+            file.checksum = checksum;
+            return write_record(&self.dir, &self.record).await;
+        }
+        if let Some(file) = self
+            .record
+            .new_files
+            .iter_mut()
+            .find(|file| file.path == relative_path)
+        {
+            file.checksum = checksum;
+            return write_record(&self.dir, &self.record).await;
+        }
+
+        let original_checksum = Some(self.blobs.put(&fs::read(path).await?).await?);
+
+        self.record.modified_files.push(ModifiedFile {
+            path: relative_path,
+            mode: blobs::file_mode(path).await?,
+            original_checksum,
+            checksum,
+        });
+        write_record(&self.dir, &self.record).await
+    }
+
+    /// Records a file the run is about to create. A path that already exists
+    /// is recorded as modified instead, so its content is backed up.
+    pub async fn record_new_file(&mut self, path: &Path, content: &str) -> Result<(), AppError> {
+        if fs::try_exists(path).await? {
+            return self.record_modified_file(path, content).await;
+        }
+
+        let relative_path = blobs::relative_path(path);
+        let checksum = checksum_of(content.as_bytes());
+        match self
+            .record
+            .new_files
+            .iter_mut()
+            .find(|file| file.path == relative_path)
+        {
+            Some(file) => file.checksum = checksum,
+            None => self.record.new_files.push(CreatedFile {
+                path: relative_path,
+                checksum,
+            }),
+        }
+        write_record(&self.dir, &self.record).await
+    }
+
+    /// The ID of the run being recorded.
+    pub fn id(&self) -> &str {
+        &self.record.id
+    }
+
+    /// Records the commit of a git mode run, which rollback resets.
+    pub fn set_git(&mut self, git: GitRecord) {
+        self.record.git = Some(git);
+    }
+
+    /// Writes the run record and its diff, returning the run's ID.
+    pub async fn finish(self, diff: &str, response: &str) -> Result<String, AppError> {
+        fs::write(self.dir.join("diff.patch"), diff).await?;
+        if !response.is_empty() {
+            fs::write(self.dir.join("response.txt"), response).await?;
+        }
+        write_record(&self.dir, &self.record).await?;
+        Ok(self.record.id)
+    }
+}
+
+/// Directory holding one subdirectory per run.
+pub fn runs_dir(output_directory: &Path) -> PathBuf {
+    output_directory.join("press.output").join("runs")
+}
+
+/// Loads every recorded run, oldest first.
+pub async fn list_runs(output_directory: &Path) -> Result<Vec<RunRecord>, AppError> {
+    let runs_dir = runs_dir(output_directory);
+    if !runs_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut runs = Vec::new();
+    let mut entries = fs::read_dir(&runs_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.path().join("run.toml").exists() {
+            runs.push(read_record(&entry.path()).await?);
+        }
+    }
+    // IDs start with the timestamp, so they sort chronologically
+    runs.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(runs)
+}
+
+/// Loads the run `id`, which may be any unambiguous prefix of a run ID.
+pub async fn load_run(output_directory: &Path, id: &str) -> Result<(PathBuf, RunRecord), AppError> {
+    let mut matches: Vec<RunRecord> = list_runs(output_directory)
+        .await?
+        .into_iter()
+        .filter(|run| run.id.starts_with(id))
+        .collect();
+
+    match matches.len() {
+        0 => Err(AppError::RollbackError(format!("No run with ID '{}'", id))),
+        1 => {
+            let run = matches.remove(0);
+            Ok((runs_dir(output_directory).join(&run.id), run))
+        }
+        _ => Err(AppError::RollbackError(format!(
+            "Run ID '{}' is ambiguous, it matches {} runs",
+            id,
+            matches.len()
+        ))),
+    }
+}
+
+/// Rolls back run `id` after it failed halfway, if it recorded any change
+/// and isn't rolled back yet. Nothing is asked: the files are put back the
+/// way the run found them.
+pub async fn rollback_unfinished_run(output_directory: &Path, id: &str) -> Result<(), AppError> {
+    let dir = runs_dir(output_directory).join(id);
+    if !dir.join("run.toml").exists() || read_record(&dir).await?.rolled_back_at.is_some() {
+        return Ok(());
+    }
+    rollback_run(output_directory, Some(id), true).await
+}
+
+/// Rolls back run `id`, or the most recent run that hasn't been rolled back.
+///
+/// Files changed since the run would be clobbered by the rollback, so they
+/// are listed and need confirmation unless `force` is set, like uncommitted
+/// changes a git mode reset would discard. Every backup blob is verified
+/// before any file is touched.
+pub async fn rollback_run(
+    output_directory: &Path,
+    id: Option<&str>,
+    force: bool,
+) -> Result<(), AppError> {
+    let (dir, mut run) = match id {
+        Some(id) => load_run(output_directory, id).await?,
+        None => {
+            let run = list_runs(output_directory)
+                .await?
+                .into_iter()
+                .rev()
+                .find(|run| run.rolled_back_at.is_none())
+                .ok_or_else(|| AppError::RollbackError("No changes to rollback".to_string()))?;
+            (runs_dir(output_directory).join(&run.id), run)
+        }
+    };
+
+    if let Some(rolled_back_at) = &run.rolled_back_at {
+        return Err(AppError::RollbackError(format!(
+            "Run {} was already rolled back at {}",
+            run.id, rolled_back_at
+        )));
+    }
+
+    let changed: Vec<&str> = run
+        .file_states()
+        .await
+        .into_iter()
+        .filter(|(_, state)| *state == FileState::Changed)
+        .map(|(path, _)| path)
+        .collect();
+    if !changed.is_empty() && !force {
+        println!("These files were changed after run {}:", run.id);
+        for path in &changed {
+            println!("  {}", path);
+        }
+        if !confirm("Rolling back will overwrite those changes. Continue? [y/N] ")? {
+            return Err(AppError::RollbackError("Rollback cancelled".to_string()));
+        }
+    }
+
+    let blob_store = BlobStore::new(output_directory);
+    if run.git.is_none() {
+        let checksums: Vec<&str> = run
+            .modified_files
+            .iter()
+            .filter_map(|file| file.original_checksum.as_deref())
+            .collect();
+        blob_store.verify(checksums).await?;
+    }
+
+    if let Some(git_record) = &run.git {
+        let uncommitted = git::uncommitted_changes()?;
+        if !uncommitted.is_empty() && !force {
+            println!("These files have uncommitted changes:");
+            for line in &uncommitted {
+                println!("  {}", line);
+            }
+            if !confirm("Resetting the branch will discard them. Continue? [y/N] ")? {
+                return Err(AppError::RollbackError("Rollback cancelled".to_string()));
+            }
+        }
+        git::reset_to(&git_record.branch, &git_record.base_commit)?;
+        println!("Reset {} to {}", git_record.branch, git_record.base_commit);
+    } else {
+        // Delete new files created during the run
+        for new_file in &run.new_files {
+            let path = Path::new(&new_file.path);
+            if path.exists() {
+                fs::remove_file(path).await?;
+                println!("Deleted new file: {}", path.display());
+            }
+        }
+
+        // Restore original files from the backup store
+        for modified_file in &run.modified_files {
+            let Some(original_checksum) = &modified_file.original_checksum else {
+                continue;
+            };
+            let path = Path::new(&modified_file.path);
+            let content = blob_store.get(original_checksum).await?;
+            blobs::restore_file(path, &content, modified_file.mode).await?;
+            println!("Restored: {}", path.display());
+        }
+    }
+
+    run.rolled_back_at = Some(chrono::Local::now().to_rfc3339());
+    write_record(&dir, &run).await
+}
+
+async fn read_record(dir: &Path) -> Result<RunRecord, AppError> {
+    let record_str = fs::read_to_string(dir.join("run.toml")).await?;
+    toml::from_str(&record_str).map_err(|e| AppError::RollbackError(e.to_string()))
+}
+
+async fn write_record(dir: &Path, record: &RunRecord) -> Result<(), AppError> {
+    let record_str = toml::to_string(record).map_err(|e| AppError::RollbackError(e.to_string()))?;
+    fs::write(dir.join("run.toml"), record_str).await?;
+    Ok(())
+}
+
+/// Asks a yes/no question on stdin; anything but "y" or "yes" means no.
+pub(crate) fn confirm(question: &str) -> Result<bool, AppError> {
+    print!("{}", question);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records a run that rewrites each of `modified` and creates each of
+    /// `created`, and writes the files like the pipeline would.
+    async fn record_run(
+        output_directory: &Path,
+        modified: &[(&Path, &str)],
+        created: &[(&Path, &str)],
+    ) -> String {
+        let mut recorder = RunRecorder::start(output_directory, "prompt", &[], &Config::default())
+            .await
+            .unwrap();
+        for (path, content) in modified {
+            recorder.record_modified_file(path, content).await.unwrap();
+            fs::write(path, content).await.unwrap();
+        }
+        for (path, content) in created {
+            recorder.record_new_file(path, content).await.unwrap();
+            fs::write(path, content).await.unwrap();
+        }
+        recorder.finish("", "").await.unwrap()
+    }
+
+    fn read(path: &Path) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[tokio::test]
+    async fn rollback_restores_modified_files_and_deletes_new_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let (changed, created) = (dir.path().join("a.rs"), dir.path().join("b.rs"));
+        std::fs::write(&changed, "old").unwrap();
+
+        let id = record_run(dir.path(), &[(&changed, "new")], &[(&created, "created")]).await;
+        assert_eq!(read(&changed), "new");
+
+        rollback_run(dir.path(), Some(&id), true).await.unwrap();
+        assert_eq!(read(&changed), "old");
+        assert!(!created.exists());
+
+        let (_, run) = load_run(dir.path(), &id).await.unwrap();
+        assert!(run.rolled_back_at.is_some());
+        assert!(matches!(
+            rollback_run(dir.path(), Some(&id), true).await,
+            Err(AppError::RollbackError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rollback_restores_a_file_deleted_since_the_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.rs");
+        std::fs::write(&path, "old").unwrap();
+
+        let id = record_run(dir.path(), &[(&path, "new")], &[]).await;
+        std::fs::remove_file(&path).unwrap();
+
+        rollback_run(dir.path(), Some(&id), true).await.unwrap();
+        assert_eq!(read(&path), "old");
+    }
+
+    #[tokio::test]
+    async fn a_file_changed_twice_in_a_run_is_restored_to_its_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.rs");
+        std::fs::write(&path, "old").unwrap();
+
+        let id = record_run(dir.path(), &[(&path, "first"), (&path, "second")], &[]).await;
+        let (_, run) = load_run(dir.path(), &id).await.unwrap();
+        assert_eq!(run.modified_files.len(), 1);
+        assert_eq!(run.modified_files[0].checksum, checksum_of(b"second"));
+
+        rollback_run(dir.path(), Some(&id), true).await.unwrap();
+        assert_eq!(read(&path), "old");
+    }
+
+    #[tokio::test]
+    async fn any_earlier_run_can_be_rolled_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let (first, second) = (dir.path().join("a.rs"), dir.path().join("b.rs"));
+        std::fs::write(&first, "a").unwrap();
+        std::fs::write(&second, "b").unwrap();
+
+        let first_id = record_run(dir.path(), &[(&first, "a2")], &[]).await;
+        let second_id = record_run(dir.path(), &[(&second, "b2")], &[]).await;
+
+        rollback_run(dir.path(), Some(&first_id), true)
+            .await
+            .unwrap();
+        assert_eq!(read(&first), "a");
+        assert_eq!(read(&second), "b2");
+
+        // Without an ID, the latest run that isn't rolled back yet is picked
+        rollback_run(dir.path(), None, true).await.unwrap();
+        assert_eq!(read(&second), "b");
+        let (_, run) = load_run(dir.path(), &second_id).await.unwrap();
+        assert!(run.rolled_back_at.is_some());
+        assert!(matches!(
+            rollback_run(dir.path(), None, true).await,
+            Err(AppError::RollbackError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rollback_touches_nothing_when_a_backup_is_corrupt() {
+        let dir = tempfile::tempdir().unwrap();
+        let (intact, corrupt, created) = (
+            dir.path().join("a.rs"),
+            dir.path().join("b.rs"),
+            dir.path().join("c.rs"),
+        );
+        std::fs::write(&intact, "a").unwrap();
+        std::fs::write(&corrupt, "b").unwrap();
+
+        let id = record_run(
+            dir.path(),
+            &[(&intact, "a2"), (&corrupt, "b2")],
+            &[(&created, "c")],
+        )
+        .await;
+        let blob = dir
+            .path()
+            .join("press.output")
+            .join("blobs")
+            .join(checksum_of(b"b"));
+        std::fs::write(&blob, "tampered").unwrap();
+
+        assert!(matches!(
+            rollback_run(dir.path(), Some(&id), true).await,
+            Err(AppError::CorruptBackup(_))
+        ));
+        assert_eq!(read(&intact), "a2");
+        assert_eq!(read(&corrupt), "b2");
+        assert!(created.exists());
+
+        // A missing blob is refused the same way
+        std::fs::remove_file(&blob).unwrap();
+        assert!(matches!(
+            rollback_run(dir.path(), Some(&id), true).await,
+            Err(AppError::CorruptBackup(_))
+        ));
+        let (_, run) = load_run(dir.path(), &id).await.unwrap();
+        assert!(run.rolled_back_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn unfinished_runs_are_rolled_back_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.rs");
+        std::fs::write(&path, "old").unwrap();
+
+        let mut recorder = RunRecorder::start(dir.path(), "prompt", &[], &Config::default())
+            .await
+            .unwrap();
+        let id = recorder.id().to_string();
+        recorder.record_modified_file(&path, "new").await.unwrap();
+        std::fs::write(&path, "new").unwrap();
+        drop(recorder);
+
+        rollback_unfinished_run(dir.path(), &id).await.unwrap();
+        assert_eq!(read(&path), "old");
+        // Already rolled back, and a run that recorded nothing, are left alone
+        rollback_unfinished_run(dir.path(), &id).await.unwrap();
+        rollback_unfinished_run(dir.path(), "20000101-000000-0000")
+            .await
+            .unwrap();
+    }
+}
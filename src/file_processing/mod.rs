@@ -1,2 +1,8 @@
+pub mod blobs;
+pub mod checkpoint;
+pub mod chunker;
+pub mod edits;
+pub mod history;
+pub mod outline;
 pub mod reader;
 pub mod writer;
@@ -0,0 +1,231 @@
+use crate::models::FileChunks;
+use std::collections::HashMap;
+
+/// Longest signature kept in an outline; longer lines are cut.
+const MAX_SIGNATURE_LENGTH: usize = 120;
+
+/// Deepest indentation, in columns, at which a line can still be a declaration.
+const MAX_DECLARATION_INDENT: usize = 4;
+
+/// First words of a declaration at the top level of a file.
+const TOP_LEVEL_KEYWORDS: [&str; 26] = [
+    "fn",
+    "pub",
+    "struct",
+    "enum",
+    "trait",
+    "impl",
+    "mod",
+    "type",
+    "const",
+    "static",
+    "union",
+    "class",
+    "def",
+    "async",
+    "function",
+    "interface",
+    "export",
+    "func",
+    "fun",
+    "public",
+    "private",
+    "protected",
+    "internal",
+    "abstract",
+    "final",
+    "module",
+];
+
+/// First words of a declaration nested in a type or module, such as a method.
+/// Narrower than the top-level list, so statements in function bodies stay out.
+const NESTED_KEYWORDS: [&str; 12] = [
+    "fn",
+    "pub",
+    "def",
+    "async",
+    "function",
+    "func",
+    "fun",
+    "public",
+    "private",
+    "protected",
+    "internal",
+    "override",
+];
+
+/// Outlines what the code assistant isn't sent: every file of the run, and
+/// the declarations in the parts `selected` leaves out, one per line.
+///
+/// Files missing from `selected` are outlined in full. Returns an empty
+/// string when every part was selected.
+pub fn outline_unselected_parts(
+    file_chunks: &[FileChunks],
+    selected: &HashMap<String, Vec<usize>>,
+) -> String {
+    let mut outline = String::new();
+    let mut anything_left_out = false;
+
+    for file in file_chunks {
+        let selected_parts = selected.get(&file.file_path);
+        let sent: Vec<usize> = file
+            .parts
+            .iter()
+            .map(|part| part.part_id)
+            .filter(|part_id| selected_parts.is_some_and(|parts| parts.contains(part_id)))
+            .collect();
+        if sent.len() < file.parts.len() {
+            anything_left_out = true;
+        }
+
+        outline.push_str(&file.file_path);
+        match sent.len() {
+            0 => outline.push_str(" (not sent)\n"),
+            n if n == file.parts.len() => outline.push_str(" (sent in full)\n"),
+            _ => outline.push_str(&format!(
+                " (sent parts: {})\n",
+                sent.iter()
+                    .map(|part_id| part_id.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+
+        for part in file
+            .parts
+            .iter()
+            .filter(|part| !sent.contains(&part.part_id))
+        {
+            for signature in part.content.lines().filter_map(signature_of) {
+                outline.push_str(&format!("  {}: {}\n", part.part_id, signature));
+            }
+        }
+    }
+
+    if anything_left_out {
+        outline
+    } else {
+        String::new()
+    }
+}
+
+/// The signature declared by `line`, without its body, if `line` starts a
+/// declaration. Nested declarations keep a two-space indent.
+fn signature_of(line: &str) -> Option<String> {
+    let trimmed = line.trim();
+    let indent: usize = line
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum();
+    if indent > MAX_DECLARATION_INDENT {
+        return None;
+    }
+
+    let first_word = trimmed
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .next()
+        .unwrap_or("");
+    let keywords: &[&str] = if indent == 0 {
+        &TOP_LEVEL_KEYWORDS
+    } else {
+        &NESTED_KEYWORDS
+    };
+    if !keywords.contains(&first_word) {
+        return None;
+    }
+
+    let signature = trimmed.trim_end_matches('{').trim_end();
+    let nesting = if indent == 0 { "" } else { "  " };
+    if signature.chars().count() > MAX_SIGNATURE_LENGTH {
+        let cut: String = signature.chars().take(MAX_SIGNATURE_LENGTH).collect();
+        Some(format!("{}{}...", nesting, cut))
+    } else {
+        Some(format!("{}{}", nesting, signature))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::FilePart;
+
+    fn file(path: &str, parts: &[&str]) -> FileChunks {
+        FileChunks {
+            file_path: path.to_string(),
+            parts: parts
+                .iter()
+                .enumerate()
+                .map(|(index, content)| FilePart {
+                    part_id: index + 1,
+                    content: content.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    fn selected(files: &[(&str, &[usize])]) -> HashMap<String, Vec<usize>> {
+        files
+            .iter()
+            .map(|(path, parts)| (path.to_string(), parts.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn nothing_is_outlined_when_every_part_is_sent() {
+        let files = [file("a.rs", &["fn a() {}", "fn b() {}"])];
+        assert_eq!(
+            outline_unselected_parts(&files, &selected(&[("a.rs", &[1, 2])])),
+            ""
+        );
+    }
+
+    #[test]
+    fn unselected_parts_are_outlined_by_their_declarations() {
+        let files = [
+            file(
+                "src/lib.rs",
+                &[
+                    "fn edited() {\n    work();\n}\n",
+                    "pub struct Config {\n    pub size: usize,\n}\n\nimpl Config {\n    pub fn new() -> Self {\n        let size = 1;\n        Self { size }\n    }\n}\n",
+                ],
+            ),
+            file("src/main.rs", &["fn main() {\n    run();\n}\n"]),
+            file("src/util.rs", &["pub fn helper() {}\n"]),
+        ];
+        let outline = outline_unselected_parts(
+            &files,
+            &selected(&[("src/lib.rs", &[1]), ("src/util.rs", &[1])]),
+        );
+
+        assert_eq!(
+            outline,
+            "src/lib.rs (sent parts: 1)\n\
+             \x20 2: pub struct Config\n\
+             \x20 2:   pub size: usize,\n\
+             \x20 2: impl Config\n\
+             \x20 2:   pub fn new() -> Self\n\
+             src/main.rs (not sent)\n\
+             \x20 1: fn main()\n\
+             src/util.rs (sent in full)\n"
+        );
+    }
+
+    #[test]
+    fn signatures_drop_bodies_and_deep_lines() {
+        assert_eq!(signature_of("pub fn a() {").as_deref(), Some("pub fn a()"));
+        assert_eq!(
+            signature_of("\tdef method(self):").as_deref(),
+            Some("  def method(self):")
+        );
+        assert_eq!(signature_of("    let x = 1;"), None);
+        assert_eq!(signature_of("        fn deeply_nested() {"), None);
+        assert_eq!(signature_of("let x = 1;"), None);
+        assert_eq!(signature_of("fnord();"), None);
+
+        let long = format!("fn long({}) {{", "a".repeat(200));
+        let signature = signature_of(&long).unwrap();
+        assert!(signature.ends_with("..."));
+        assert_eq!(signature.chars().count(), MAX_SIGNATURE_LENGTH + 3);
+    }
+}
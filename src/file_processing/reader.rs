@@ -1,27 +1,84 @@
+use super::chunker;
 use crate::errors::AppError;
 use crate::models::{FileChunks, FilePart};
+use crate::utils::config::{ChunkStrategy, FilesConfig};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
 use std::collections::HashSet;
+use std::fmt;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 use tokio::fs;
 
 /// Maximum allowed file size (10 MB).
 const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
 
+/// Number of bytes read from the start of a file to decide whether it is text.
+const SNIFF_LENGTH: usize = 8 * 1024;
+
+/// Files found for a run, and the ones left out because they aren't text.
+pub struct DiscoveredFiles {
+    pub files: Vec<PathBuf>,
+    pub skipped: Vec<SkippedFile>,
+}
+
+/// A file that was found but not sent to the model.
+#[derive(Debug)]
+pub struct SkippedFile {
+    pub path: PathBuf,
+    pub reason: SkipReason,
+}
+
+/// Why a file was skipped.
+#[derive(Debug)]
+pub enum SkipReason {
+    /// The file contains null bytes.
+    Binary,
+    /// The file isn't valid UTF-8.
+    NotUtf8,
+    /// The extension is in `deny_extensions`.
+    DeniedExtension,
+    /// `allow_extensions` is set and doesn't contain the extension.
+    NotAllowedExtension,
+    /// The file is a known place for credentials, such as `.env` or a private key.
+    Secret,
+    /// The file couldn't be opened or read.
+    Unreadable(String),
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::Binary => write!(f, "binary"),
+            SkipReason::NotUtf8 => write!(f, "not UTF-8"),
+            SkipReason::DeniedExtension => write!(f, "denied extension"),
+            SkipReason::NotAllowedExtension => write!(f, "extension not allowed"),
+            SkipReason::Secret => write!(f, "may contain secrets"),
+            SkipReason::Unreadable(e) => write!(f, "unreadable: {}", e),
+        }
+    }
+}
+
 /// Reads and combines text files into a vector of `FileChunks`.
 pub async fn combine_text_files(
     paths: Vec<PathBuf>,
     chunk_size: usize,
+    chunk_strategy: ChunkStrategy,
 ) -> Result<Vec<FileChunks>, AppError> {
     let mut file_chunks_list = Vec::new();
     for path in paths {
-        let file_chunks = read_and_format_file(&path, chunk_size).await?;
+        let file_chunks = read_and_format_file(&path, chunk_size, chunk_strategy).await?;
         file_chunks_list.push(file_chunks);
     }
     Ok(file_chunks_list)
 }
 
 /// Reads a file and splits it into chunks.
-async fn read_and_format_file(path: &Path, chunk_size: usize) -> Result<FileChunks, AppError> {
+async fn read_and_format_file(
+    path: &Path,
+    chunk_size: usize,
+    chunk_strategy: ChunkStrategy,
+) -> Result<FileChunks, AppError> {
     // Check file size
     let metadata = fs::metadata(path).await?;
     if metadata.len() > MAX_FILE_SIZE {
@@ -32,17 +89,18 @@ async fn read_and_format_file(path: &Path, chunk_size: usize) -> Result<FileChun
         )));
     }
 
-    // Read file content
-    let contents = fs::read_to_string(path).await?;
-    let lines: Vec<&str> = contents.lines().collect();
+    // Read file content; sniffing only looked at the start of the file
+    let contents = String::from_utf8(fs::read(path).await?).map_err(|_| {
+        AppError::InvalidInput(format!("File is not valid UTF-8: {}", path.display()))
+    })?;
 
     // Split file content into chunks
-    let parts = lines
-        .chunks(chunk_size)
+    let parts = chunker::split_into_parts(path, &contents, chunk_size, chunk_strategy)
+        .into_iter()
         .enumerate()
-        .map(|(part_id, chunk)| FilePart {
+        .map(|(part_id, content)| FilePart {
             part_id: part_id + 1,
-            content: chunk.join("\n"),
+            content,
         })
         .collect();
 
@@ -55,22 +113,183 @@ async fn read_and_format_file(path: &Path, chunk_size: usize) -> Result<FileChun
     Ok(file_chunks)
 }
 
+/// Project-level ignore file read alongside `.gitignore` and `.ignore`.
+const PRESS_IGNORE_FILENAME: &str = ".pressignore";
+
+/// Directory holding press's backups, logs and run history, never walked.
+const OUTPUT_DIRECTORY_NAME: &str = "press.output";
+
+/// Hidden files and directories that are still sent when a directory is walked.
+const SENT_HIDDEN_NAMES: [&str; 4] = [".github", ".gitlab", ".gitlab-ci.yml", ".circleci"];
+
+/// Names of files that commonly hold credentials, never sent even when named
+/// explicitly. `.press.toml` can hold `api_key` or `api_key_command`.
+const SECRET_FILE_NAMES: [&str; 12] = [
+    ".press.toml",
+    ".env",
+    ".envrc",
+    ".npmrc",
+    ".pypirc",
+    ".netrc",
+    ".git-credentials",
+    ".dockercfg",
+    "id_rsa",
+    "id_dsa",
+    "id_ecdsa",
+    "id_ed25519",
+];
+
+/// Extensions of key and certificate stores, never sent.
+const SECRET_EXTENSIONS: [&str; 6] = ["pem", "key", "p12", "pfx", "jks", "keystore"];
+
 /// Gets a list of files to process, filtering out ignored paths.
-pub fn get_files_to_press(paths: &[String], ignore_paths: &[String]) -> Vec<PathBuf> {
-    let mut files = Vec::new();
+///
+/// Directories are walked honouring `.gitignore`, `.ignore` and `.pressignore`
+/// files, then filtered by the `include` and `exclude` globs, which match
+/// paths relative to the directory being walked. `.git` and `press.output`
+/// directories are always skipped, and so are hidden files and directories
+/// other than CI configuration like `.github/`. Files named explicitly are
+/// always considered unless they are under one of `ignore_paths`.
+///
+/// Every candidate then goes through the secret file check, the extension
+/// lists of `files_config` and content sniffing; the files that fail are
+/// returned as skipped.
+pub fn get_files_to_press(
+    paths: &[String],
+    ignore_paths: &[String],
+    include: &[String],
+    exclude: &[String],
+    files_config: &FilesConfig,
+) -> Result<DiscoveredFiles, AppError> {
+    let mut candidates = Vec::new();
     let ignored: HashSet<_> = ignore_paths.iter().map(PathBuf::from).collect();
+    let include = build_glob_set(include)?;
+    let exclude = build_glob_set(exclude)?;
 
     for path in paths {
         let path = PathBuf::from(path);
         if path.is_file() && !is_ignored(&path, &ignored) {
-            files.push(path);
+            candidates.push(path);
         } else if path.is_dir() {
-            if let Ok(dir_files) = get_directory_text_files(&path, &ignored) {
-                files.extend(dir_files);
-            }
+            candidates.extend(get_directory_files(
+                &path,
+                &ignored,
+                include.as_ref(),
+                exclude.as_ref(),
+            ));
         }
     }
-    files
+
+    let mut discovered = DiscoveredFiles {
+        files: Vec::new(),
+        skipped: Vec::new(),
+    };
+    for path in candidates {
+        match check_text_file(&path, files_config) {
+            Ok(()) => discovered.files.push(path),
+            Err(reason) => discovered.skipped.push(SkippedFile { path, reason }),
+        }
+    }
+    Ok(discovered)
+}
+
+/// Decides whether `path` should be sent to the model: it mustn't look like
+/// a secret, its extension must pass the allow/deny lists and its first bytes
+/// must look like UTF-8 text.
+fn check_text_file(path: &Path, files_config: &FilesConfig) -> Result<(), SkipReason> {
+    if is_secret_file(path) {
+        return Err(SkipReason::Secret);
+    }
+
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    let listed = |extensions: &[String]| {
+        extensions.iter().any(|listed| {
+            listed
+                .trim_start_matches('.')
+                .eq_ignore_ascii_case(&extension)
+        })
+    };
+
+    if listed(&files_config.deny_extensions) {
+        return Err(SkipReason::DeniedExtension);
+    }
+    if !files_config.allow_extensions.is_empty() && !listed(&files_config.allow_extensions) {
+        return Err(SkipReason::NotAllowedExtension);
+    }
+
+    let mut sample = Vec::with_capacity(SNIFF_LENGTH);
+    std::fs::File::open(path)
+        .and_then(|file| file.take(SNIFF_LENGTH as u64).read_to_end(&mut sample))
+        .map_err(|e| SkipReason::Unreadable(e.to_string()))?;
+
+    if sample.contains(&0) {
+        return Err(SkipReason::Binary);
+    }
+    match std::str::from_utf8(&sample) {
+        Ok(_) => Ok(()),
+        // The sample may end in the middle of a multi-byte character
+        Err(e) if e.error_len().is_none() => Ok(()),
+        Err(_) => Err(SkipReason::NotUtf8),
+    }
+}
+
+/// Whether `path` is a common credentials file: one of `SECRET_FILE_NAMES`, a
+/// variant like `.env.local` (but not `.env.example`), or a key store.
+fn is_secret_file(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    let name = name.to_lowercase();
+    let template = [".example", ".sample", ".template", ".dist"]
+        .iter()
+        .any(|suffix| name.ends_with(suffix));
+
+    SECRET_FILE_NAMES
+        .iter()
+        .any(|secret| name == *secret || (!template && name.starts_with(&format!("{}.", secret))))
+        || path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                SECRET_EXTENSIONS
+                    .iter()
+                    .any(|secret| secret.eq_ignore_ascii_case(ext))
+            })
+}
+
+/// Whether `relative_path` goes through a hidden file or directory that
+/// isn't in `SENT_HIDDEN_NAMES`.
+fn is_hidden(relative_path: &Path) -> bool {
+    relative_path.components().any(|component| {
+        component.as_os_str().to_str().is_some_and(|name| {
+            name.starts_with('.')
+                && name != "."
+                && name != ".."
+                && !SENT_HIDDEN_NAMES.contains(&name)
+        })
+    })
+}
+
+/// Compiles `globs` into a single matcher, or `None` when there are none.
+fn build_glob_set(globs: &[String]) -> Result<Option<GlobSet>, AppError> {
+    if globs.is_empty() {
+        return Ok(None);
+    }
+
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        let glob = Glob::new(glob)
+            .map_err(|e| AppError::InvalidInput(format!("Invalid glob '{}': {}", glob, e)))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map(Some)
+        .map_err(|e| AppError::InvalidInput(e.to_string()))
 }
 
 /// Checks if a path should be ignored.
@@ -80,44 +299,181 @@ fn is_ignored(path: &Path, ignored: &HashSet<PathBuf>) -> bool {
         .any(|ignored_path| path.starts_with(ignored_path))
 }
 
-/// Recursively gets all text files in a directory.
-fn get_directory_text_files(
+/// Recursively gets all files in a directory that aren't ignored or hidden.
+fn get_directory_files(
     directory: &Path,
     ignored: &HashSet<PathBuf>,
-) -> Result<Vec<PathBuf>, std::io::Error> {
-    let text_extensions = [
-        "txt", "rs", "ts", "js", "go", "json", "py", "cpp", "c", "h", "hpp", "css", "html", "md",
-        "yaml", "yml", "toml", "xml", "tsx",
-    ];
-    let mut text_files = Vec::new();
-
-    fn visit_dirs(
-        dir: &Path,
-        text_extensions: &[&str],
-        text_files: &mut Vec<PathBuf>,
-        ignored: &HashSet<PathBuf>,
-    ) -> Result<(), std::io::Error> {
-        for entry in std::fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-
-            if is_ignored(&path, ignored) {
+    include: Option<&GlobSet>,
+    exclude: Option<&GlobSet>,
+) -> Vec<PathBuf> {
+    walk_directory(directory)
+        .into_iter()
+        .filter(|path| {
+            let relative_path = path.strip_prefix(directory).unwrap_or(path);
+            !(is_ignored(path, ignored)
+                || is_hidden(relative_path)
+                || include.is_some_and(|include| !include.is_match(relative_path))
+                || exclude.is_some_and(|exclude| exclude.is_match(relative_path)))
+        })
+        .collect()
+}
+
+/// Every file under `directory`, honouring `.gitignore`, `.ignore` and
+/// `.pressignore` files. `.git` and `press.output` directories are skipped.
+///
+/// Hidden files are included, so checkpoints cover them; file discovery
+/// leaves them out itself.
+pub fn walk_directory(directory: &Path) -> Vec<PathBuf> {
+    // Never the `.git` directory itself, nor press's own output. Ignore files
+    // apply even outside of a git repository.
+    let walker = WalkBuilder::new(directory)
+        .hidden(false)
+        .require_git(false)
+        .add_custom_ignore_filename(PRESS_IGNORE_FILENAME)
+        .filter_entry(|entry| {
+            entry.depth() == 0
+                || !(entry
+                    .file_type()
+                    .is_some_and(|file_type| file_type.is_dir())
+                    && (entry.file_name() == ".git" || entry.file_name() == OUTPUT_DIRECTORY_NAME))
+        })
+        .build();
+
+    let mut files = Vec::new();
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                log::warn!("Skipping unreadable path: {}", e);
                 continue;
             }
+        };
+        if entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_file())
+        {
+            files.push(entry.into_path());
+        }
+    }
 
-            if path.is_file() {
-                if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
-                    if text_extensions.contains(&extension.to_lowercase().as_str()) {
-                        text_files.push(path);
-                    }
-                }
-            } else if path.is_dir() {
-                visit_dirs(&path, text_extensions, text_files, ignored)?;
-            }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write(root: &Path, relative_path: &str, contents: &str) {
+        let path = root.join(relative_path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn relative(root: &Path, mut files: Vec<PathBuf>) -> Vec<String> {
+        files.sort();
+        files
+            .iter()
+            .map(|path| {
+                path.strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect()
+    }
+
+    fn discover(root: &Path) -> DiscoveredFiles {
+        get_files_to_press(
+            &[root.to_string_lossy().to_string()],
+            &[],
+            &[],
+            &[],
+            &FilesConfig::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn discovery_skips_hidden_files_except_ci_configuration() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        write(root, "src/main.rs", "fn main() {}\n");
+        write(root, ".github/workflows/ci.yml", "on: push\n");
+        write(root, ".env", "TOKEN=secret\n");
+        write(root, ".npmrc", "//registry/:_authToken=secret\n");
+        write(root, ".press.toml", "api_key = \"secret\"\n");
+        write(root, ".press/recipes/docs.md", "Add docs\n");
+        write(root, "src/.hidden/notes.txt", "notes\n");
+        write(root, ".git/config", "[core]\n");
+        write(root, "press.output/last_run.toml", "output = \"\"\n");
+        write(root, ".gitignore", "target/\n");
+        write(root, "target/debug/out.rs", "fn out() {}\n");
+
+        let discovered = discover(root);
+        assert_eq!(
+            relative(root, discovered.files),
+            vec![".github/workflows/ci.yml", "src/main.rs"]
+        );
+        assert!(discovered.skipped.is_empty());
+    }
+
+    #[test]
+    fn walking_keeps_hidden_files_but_not_git_or_press_output() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        write(root, "src/main.rs", "fn main() {}\n");
+        write(root, ".env", "TOKEN=secret\n");
+        write(root, ".git/config", "[core]\n");
+        write(root, "press.output/last_run.toml", "output = \"\"\n");
+        write(root, ".gitignore", "target/\n");
+        write(root, "target/debug/out.rs", "fn out() {}\n");
+
+        assert_eq!(
+            relative(root, walk_directory(root)),
+            vec![".env", ".gitignore", "src/main.rs"]
+        );
+    }
+
+    #[test]
+    fn secret_files_are_never_sent() {
+        for name in [
+            ".env",
+            ".env.local",
+            ".envrc",
+            ".npmrc",
+            ".press.toml",
+            ".git-credentials",
+            "id_ed25519",
+            "server.pem",
+            "tls.KEY",
+        ] {
+            assert!(is_secret_file(Path::new(name)), "{} is a secret", name);
+        }
+        for name in [".env.example", "environment.rs", "keys.rs", "README.md"] {
+            assert!(!is_secret_file(Path::new(name)), "{} isn't a secret", name);
         }
-        Ok(())
     }
 
-    visit_dirs(directory, &text_extensions, &mut text_files, ignored)?;
-    Ok(text_files)
+    #[test]
+    fn secret_files_are_skipped_even_when_named() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        write(root, ".env", "TOKEN=secret\n");
+        write(root, "config/.press.toml", "api_key = \"secret\"\n");
+        write(root, "src/main.rs", "fn main() {}\n");
+
+        let paths: Vec<String> = [".env", "config/.press.toml", "src/main.rs"]
+            .iter()
+            .map(|path| root.join(path).to_string_lossy().to_string())
+            .collect();
+        let discovered =
+            get_files_to_press(&paths, &[], &[], &[], &FilesConfig::default()).unwrap();
+        assert_eq!(relative(root, discovered.files), vec!["src/main.rs"]);
+        assert_eq!(discovered.skipped.len(), 2);
+        assert!(discovered
+            .skipped
+            .iter()
+            .all(|file| matches!(file.reason, SkipReason::Secret)));
+    }
 }
@@ -0,0 +1,36 @@
+//! press sends source files to a preprocessor model, which picks the parts
+//! that need editing, and a code assistant model, which rewrites them.
+//!
+//! The `press` binary is a thin CLI over [`Pipeline`]:
+//!
+//! ```no_run
+//! use press::{ApplyStrategy, Pipeline};
+//! use press::utils::config::read_config;
+//!
+//! # async fn example() -> Result<(), press::AppError> {
+//! let outcome = Pipeline::new(read_config()?)
+//!     .paths(["src"])
+//!     .prompt("Add doc comments to every public function")
+//!     .apply(ApplyStrategy::DryRun)
+//!     .run(&mut ())
+//!     .await?;
+//!
+//! for diff in &outcome.diffs {
+//!     println!("{}", diff.diff);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod api;
+pub mod errors;
+pub mod file_processing;
+pub mod models;
+pub mod pipeline;
+pub mod utils;
+
+pub use errors::AppError;
+pub use pipeline::{
+    ApplyStrategy, ChangeReview, FileDiff, GitCommit, Pipeline, PipelineEvent, PipelineObserver,
+    PipelineOutcome, RollbackHandle,
+};
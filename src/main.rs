@@ -1,412 +1,321 @@
-mod api;
 mod cli;
-mod errors;
-mod file_processing;
-mod models;
-mod utils;
-
-use crate::models::{FileChunks, FilePart};
-use crate::utils::logger;
-use api::client::DeepSeekApi;
+
 use clap::Parser;
 use cli::args::Args;
-use cli::args::Commands;
-use errors::AppError;
-use file_processing::{reader, writer};
-use log;
-use models::code_assistant_response::CodeAssistantResponse;
-use models::preprocessor_response::PreprocessorResponse;
-use similar::TextDiff;
-use std::io::Write;
-use std::{
-    path::{Path, PathBuf},
-    time::{Duration, Instant},
+use cli::args::{
+    CheckpointCommands, Commands, ConfigArgs, ConfigCommands, HistoryCommands, RecipeCommands,
+    RunArgs,
+};
+use cli::display::CliDisplayManager;
+use cli::review::Reviewer;
+use press::errors::AppError;
+use press::file_processing::checkpoint::{self, Change};
+use press::file_processing::{history, reader, writer};
+use press::pipeline::{unified_diff, ApplyStrategy, Pipeline};
+use press::utils::api_key::store_api_key;
+use press::utils::config::{
+    read_user_config, write_config, BudgetPolicy, ChunkStrategy, Config, DirtyTreePolicy,
+    EditFormat, ProviderKind, Stage,
+};
+use press::utils::console_capture::{
+    consume_console_capture, get_last_console_output, read_piped_stdin, run_and_capture,
 };
-use tokio;
-use utils::config::{read_config, write_config};
-use utils::console_capture::get_last_console_output;
+use press::utils::logger;
+use press::utils::recipe::{find_recipe, list_recipes};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 /// The main entry point of the application
 #[tokio::main]
 async fn main() -> Result<(), AppError> {
     let args = Args::parse();
-    let start_time = Instant::now();
-
-    // Create the CLI display manager
-    let mut display_manager = cli::display::CliDisplayManager::new();
 
     // Handle subcommands
-    handle_subcommands(args.command.clone()).await?;
-
-    match &args.command {
-        Some(_) => return Ok(()),
-        None => {}
+    if let Some(command) = args.command {
+        // clap accepts the run flags before any subcommand, but only the ones
+        // after `recipe run <name>` apply to a recipe
+        if matches!(command, Commands::Recipe { .. }) && args.run != RunArgs::default() {
+            return Err(AppError::InvalidInput(
+                "Put the run flags after the recipe name, e.g. `press recipe run docs -p src`"
+                    .to_string(),
+            ));
+        }
+        return handle_subcommands(command, &args.config).await;
     }
 
     // Ensure prompt is provided
     let prompt = args.prompt.ok_or(AppError::MissingPrompt)?;
 
-    // Read config.toml
-    let config = read_config()?;
-    let chunk_size = config.chunk_size;
+    // Read the layered config, with the flags of this run on top
+    let config = args.config.resolve()?.config;
+    run_pipeline(prompt, args.run, config).await
+}
+
+/// Runs `prompt` on the files of `args` and prints the outcome.
+async fn run_pipeline(prompt: String, args: RunArgs, config: Config) -> Result<(), AppError> {
+    let start_time = Instant::now();
 
-    // Handle API key
-    let api_key = config.api_key.clone().ok_or(AppError::MissingApiKey)?;
+    // Create the CLI display manager
+    let mut display_manager = CliDisplayManager::new();
 
-    // Capture console output before initializing the logger
-    let previous_console_output: Option<String> = if let Some(pipe_output) = args.pipe_output {
-        Some(get_last_console_output(pipe_output))
-    } else {
-        None
-    };
+    let output_directory = PathBuf::from(&config.output_directory);
+
+    // Gather extra context for the prompt: console output (captured before
+    // initializing the logger), piped input and context files
+    let mut context_sections = Vec::new();
+    if let Some(lines) = args.pipe_output {
+        context_sections.push(get_last_console_output(lines, &output_directory)?);
+    }
+    if args.stdin {
+        context_sections.push(format!("Piped input:\n{}", read_piped_stdin()?));
+    }
+    for context_file in &args.context_file {
+        let content = tokio::fs::read_to_string(context_file).await?;
+        context_sections.push(format!("Contents of {}:\n{}", context_file, content));
+    }
 
     // Initialize logger after capturing console output
     logger::setup_logger(&config);
 
     display_manager.print_header();
 
-    let output_directory = Path::new(&config.output_directory);
-    let directory_files = reader::get_files_to_press(&args.paths, &args.ignore);
-    let file_count = directory_files.len();
-
-    display_manager.print_file_processing_start(file_count);
-
-    let output_file_text = reader::combine_text_files(directory_files.clone(), chunk_size).await?;
-    display_manager.print_file_combining_success();
-
-    display_manager.print_deepseek_query_start();
-
-    let deepseek_api = DeepSeekApi::new(api_key);
-
-    display_manager.start_spinner_preprocessor();
-
-    let mut retries = config.retries;
-    let mut combined_prompt = prompt;
-    if args.pipe_output.is_some() && previous_console_output.is_some() {
-        combined_prompt.push_str(&previous_console_output.unwrap());
-    }
-
-    let preprocessed_prompt = loop {
-        match deepseek_api
-            .call_deepseek_preprocessor(
-                &config.system_prompt,
-                &combined_prompt,
-                &output_file_text,
-                config.temperature.clone(),
-                config.output_directory.clone(),
-            )
-            .await
-        {
-            Ok(response) => break response,
-            Err(e) if retries > 0 => {
-                retries -= 1;
-                log::warn!("API call failed, retries left: {} ({})", retries, e);
-                tokio::time::sleep(Duration::from_secs(1)).await;
-            }
-            Err(e) => return Err(e.into()),
-        }
+    let apply = if args.dry_run {
+        ApplyStrategy::DryRun
+    } else if args.auto {
+        ApplyStrategy::InPlace
+    } else {
+        ApplyStrategy::OutputDirectory
     };
-
-    // Parse the preprocessor response using the new type
-    let preprocessor_response: PreprocessorResponse =
-        serde_json::from_str(&preprocessed_prompt).expect("Failed to parse preprocessor response");
-
-    log::debug!(
-        "Preprocessor Response - Parts to Edit: {:?}",
-        preprocessor_response.parts_to_edit
-    );
-    log::debug!(
-        "Preprocessor Response - Prompt: {}",
-        preprocessor_response.preprocessor_prompt
-    );
-
-    // Create a hashmap of parts to edit
-    let parts_to_edit = preprocessor_response.parts_to_edit;
-
-    let mut parts_to_edit_hashmap: std::collections::HashMap<String, Vec<usize>> =
-        std::collections::HashMap::new();
-
-    for file in parts_to_edit {
-        let file_path = file.file_path;
-        // turn each part in file.parts from "1" to 1 usize
-        let parts: Vec<usize> = file.parts;
-
-        parts_to_edit_hashmap.insert(file_path, parts);
+    let git = args.git || config.git.enabled;
+    let verify_iterations = args.verify_iterations.unwrap_or(config.verify_iterations);
+    let mut pipeline = Pipeline::new(config)
+        .paths(args.paths)
+        .ignore(args.ignore)
+        .include(args.include)
+        .exclude(args.exclude)
+        .prompt(prompt)
+        .apply(apply)
+        .git(git)
+        .interrupt_on_ctrl_c(true);
+    for context_section in context_sections {
+        pipeline = pipeline.context(context_section);
+    }
+    if args.interactive {
+        pipeline = pipeline.review(Box::new(Reviewer::new()));
+    }
+    if let Some(command) = args.verify {
+        pipeline = pipeline.verify(command, verify_iterations);
     }
 
-    // Use the parsed response to filter the preprocessed prompt
-    let filtered_prompt = filter_out_unused_parts(&output_file_text, &parts_to_edit_hashmap);
-
-    log::debug!("Filtered Preprocessed Prompt:\n{:?}", filtered_prompt);
-
-    display_manager.stop_spinner();
-    display_manager.print_preprocessor_response_success();
-
-    display_manager.start_spinner_assistant();
-
-    // Get code assistant response from DeepSeek API
-    let response = loop {
-        match deepseek_api
-            .call_deepseek_code_assistant(
-                &config.system_prompt,
-                &combined_prompt,
-                &filtered_prompt,
-                config.temperature.clone(),
-                config.output_directory.clone(),
-            )
-            .await
-        {
-            Ok(response) => break response,
-            Err(e) if retries > 0 => {
-                retries -= 1;
-                log::warn!("API call failed, retries left: {} ({})", retries, e);
-                tokio::time::sleep(Duration::from_secs(1)).await;
-            }
-            Err(e) => return Err(e.into()),
+    let outcome = match pipeline.run(&mut display_manager).await {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            display_manager.stop_spinner();
+            return Err(e);
         }
     };
 
-    let code_assistant_response: CodeAssistantResponse =
-        serde_json::from_str(&response).expect("Failed to parse code assistant response");
-
-    display_manager.stop_spinner();
-    display_manager.print_code_assistant_response_success();
-    display_manager.print_saving_results_start();
-
-    let press_output_dir = output_directory.join("press.output");
-    tokio::fs::create_dir_all(&press_output_dir).await?;
-
-    // Process the code assistant response
-    let (saved_files, new_files) = process_code_assistant_response(
-        &code_assistant_response,
-        &directory_files,
-        &press_output_dir,
-        args.auto,
-        chunk_size,
-    )
-    .await?;
-
-    display_manager.print_saving_results_success(args.auto);
-    display_manager.print_footer(new_files, saved_files, start_time.elapsed());
-
-    Ok(())
-}
-
-///
-/// Processes the `CodeAssistantResponse` to save updated files, create new files,
-/// and write the response text. We now call `save_rollback` before overwriting.
-/// generates a unified diff (diff.patch) in `output_directory/press.output/`.**
-///
-pub async fn process_code_assistant_response(
-    response: &CodeAssistantResponse,
-    original_paths: &[PathBuf],
-    output_directory: &Path,
-    auto: bool,
-    chunk_size: usize,
-) -> Result<(usize, usize), AppError> {
-    // Gather data for rollback
-    let mut new_files_for_rollback: Vec<String> = Vec::new();
-    let mut modified_files_for_rollback: Vec<(String, String)> = Vec::new();
-
-    // Vector to track pairs of (old_file_path, new_file_path) for diff generation
-    let mut updated_files_for_diff: Vec<(PathBuf, PathBuf)> = Vec::new();
-
-    // New files
-    for new_file in &response.new_files {
-        new_files_for_rollback.push(new_file.file_path.clone());
-    }
-
-    // Updated files
-    for updated_file in &response.updated_files {
-        let fallback = PathBuf::from(&updated_file.file_path);
-        let original_file_path = original_paths
-            .iter()
-            .find(|p| p.to_string_lossy().ends_with(&updated_file.file_path))
-            .unwrap_or(&fallback);
-
-        // We'll pass an empty string as the second tuple item; the writer saves the real backup path.
-        modified_files_for_rollback.push((
-            original_file_path.to_string_lossy().to_string(),
-            "".to_string(),
-        ));
-    }
-
-    // **Save rollback info BEFORE we overwrite or create any files.**
-    writer::save_rollback(
-        output_directory,
-        new_files_for_rollback.clone(),
-        modified_files_for_rollback.clone(),
-    )
-    .await?;
-
-    // Now, proceed with overwriting (updated) and creating (new) files.
-    let mut saved_files = 0;
-    let mut new_files = 0;
-
-    // Process updated files
-    for updated_file in &response.updated_files {
-        let fallback = PathBuf::from(&updated_file.file_path);
-        let original_file_path = original_paths
-            .iter()
-            .find(|p| p.to_string_lossy().ends_with(&updated_file.file_path))
-            .unwrap_or(&fallback);
-
-        let original_content = tokio::fs::read_to_string(&original_file_path).await?;
-
-        let lines: Vec<&str> = original_content.lines().collect();
-        let mut parts: Vec<String> = if chunk_size == 0 {
-            vec![original_content]
-        } else {
-            lines
-                .chunks(chunk_size)
-                .map(|chunk| chunk.join("\n"))
-                .collect()
-        };
-
-        for part in &updated_file.parts {
-            // Parse `part_id` into `usize`
-            let part_id: usize = part.part_id;
-
-            // Compare `part_id` with `parts.len()`
-            if part_id > 0 && part_id <= parts.len() {
-                parts[part_id - 1] = part.content.clone();
-            }
-        }
-
-        let new_content = parts.join("\n");
-
-        // If --auto is used, overwrite the original file directly
-        // otherwise, put the updated file in output_directory/press.output/code/<file_path>
-        let output_file_path = if auto {
-            original_file_path.to_path_buf()
-        } else {
-            output_directory.join("code").join(&updated_file.file_path)
-        };
-
-        if let Some(parent) = output_file_path.parent() {
-            tokio::fs::create_dir_all(parent).await?;
-        }
-
-        tokio::fs::write(&output_file_path, new_content.as_bytes()).await?;
-        saved_files += 1;
-
-        // Record these paths so we can generate a diff later
-        updated_files_for_diff.push((original_file_path.to_path_buf(), output_file_path.clone()));
-    }
-
-    // Process new files
-    for new_file in &response.new_files {
-        let file_path = PathBuf::from(&new_file.file_path);
-        if let Some(parent) = file_path.parent() {
-            tokio::fs::create_dir_all(parent).await?;
+    if args.dry_run {
+        display_manager.print_dry_run_start();
+        display_manager.print_plan(
+            &outcome.preprocessor_response.parts_to_edit,
+            &outcome.preprocessor_response.preprocessor_prompt,
+        );
+        for diff in &outcome.diffs {
+            display_manager.print_diff(&diff.diff);
         }
-        tokio::fs::write(&file_path, new_file.content.as_bytes()).await?;
-        new_files += 1;
+        display_manager.print_dry_run_footer(
+            &outcome.code_assistant_response.response,
+            start_time.elapsed(),
+        );
+        return Ok(());
     }
 
-    // Write the response text if present
-    if !response.response.is_empty() {
-        let response_txt_path = output_directory.join("response.txt");
-        tokio::fs::create_dir_all(output_directory).await?;
-        tokio::fs::write(&response_txt_path, response.response.as_bytes()).await?;
+    // The captured output has served its purpose once changes based on it are written
+    if args.pipe_output.is_some() {
+        consume_console_capture(&output_directory)?;
     }
 
-    // --- Generate diffs only if we actually updated files (and only if you want to do so).
-    // If you only want diffs in "non-auto" mode, wrap this with if !auto { ... }
-    if !updated_files_for_diff.is_empty() {
-        generate_unified_diffs(&updated_files_for_diff, output_directory).await?;
-    }
-
-    Ok((saved_files, new_files))
-}
-
-///
-/// Generates a unified diff for each `(old_file, new_file)` pair
-/// and writes them all to `output_directory/press.output/diff.patch`.
-///
-async fn generate_unified_diffs(
-    updated_files: &[(PathBuf, PathBuf)],
-    output_directory: &Path,
-) -> Result<(), AppError> {
-    // We'll store the final diff in output_directory/press.output/diff.patch
-    let patch_dir = output_directory;
-    tokio::fs::create_dir_all(&patch_dir).await?;
-
-    let patch_path = patch_dir.join("diff.patch");
-
-    // Use synchronous File for writing the patch
-    let mut patch_file = std::fs::File::create(&patch_path).expect("Failed to create diff.patch");
-
-    for (old_path, new_path) in updated_files {
-        // Safely read old file (if it doesn't exist, treat as empty)
-        let old_content = std::fs::read_to_string(old_path).unwrap_or_default();
-        // Read new file (also treat missing file as empty)
-        let new_content = std::fs::read_to_string(new_path).unwrap_or_default();
-
-        let diff = TextDiff::from_lines(&old_content, &new_content)
-            .unified_diff()
-            .context_radius(3) // optional
-            .header(
-                old_path.to_string_lossy().as_ref(),
-                new_path.to_string_lossy().as_ref(),
-            )
-            .to_string();
-
-        // Append this diff to the patch file (add a blank line if you prefer separate sections)
-        writeln!(patch_file, "{}", diff).expect("Failed to write diff.patch");
-    }
+    display_manager.print_footer(
+        outcome.new_files.len(),
+        outcome.saved_files.len(),
+        outcome.skipped_files.len(),
+        start_time.elapsed(),
+    );
 
     Ok(())
 }
-async fn handle_subcommands(command: Option<Commands>) -> Result<(), AppError> {
+
+async fn handle_subcommands(command: Commands, config_args: &ConfigArgs) -> Result<(), AppError> {
     match command {
-        Some(Commands::Rollback) => {
-            handle_rollback_subcommand().await?;
+        Commands::Rollback { id, force } => {
+            handle_rollback_subcommand(&config_args.resolve()?.config, id, force).await?;
         }
-        Some(Commands::Config {
+        Commands::Run { lines, command } => {
+            let config = config_args.resolve()?.config;
+            let exit_code = run_and_capture(&command, lines, Path::new(&config.output_directory))?;
+            // Exit like the command did, so `press run` can stand in for it in scripts
+            if exit_code != Some(0) {
+                std::process::exit(exit_code.unwrap_or(1));
+            }
+        }
+        Commands::History { command } => {
+            handle_history_subcommand(&config_args.resolve()?.config, command).await?;
+        }
+        Commands::Config {
+            command: Some(ConfigCommands::Show { origin }),
+            ..
+        } => {
+            let loaded = config_args.resolve()?;
+            CliDisplayManager::new().print_config(&loaded.entries, origin);
+        }
+        Commands::Config {
             set_chunk_size,
             set_log_level,
             set_output_directory,
             set_retries,
-        }) => {
+            set_stream,
+            set_repair_attempts,
+            set_connect_timeout,
+            set_timeout,
+            set_chunk_strategy,
+            set_context_budget,
+            set_budget_policy,
+            set_edit_format,
+            set_git,
+            set_git_dirty,
+            set_git_branch_prefix,
+            set_allow_extensions,
+            set_deny_extensions,
+            set_verify_iterations,
+            command: None,
+        } => {
             handle_config_subcommand(
                 set_chunk_size,
                 set_log_level,
                 set_output_directory,
                 set_retries,
+                set_stream,
+                set_repair_attempts,
+                set_connect_timeout,
+                set_timeout,
+                set_chunk_strategy,
+                set_context_budget,
+                set_budget_policy,
+                set_edit_format,
+                set_git,
+                set_git_dirty,
+                set_git_branch_prefix,
+                set_allow_extensions,
+                set_deny_extensions,
+                set_verify_iterations,
             )
             .await?;
         }
-        Some(Commands::ModelConfig {
+        Commands::ModelConfig {
             set_api_key,
+            set_api_key_command,
+            set_api_key_file,
             set_system_prompt,
             set_temperature,
-        }) => {
-            handle_model_config_subcommand(set_api_key, set_system_prompt, set_temperature).await?;
+            set_provider,
+            set_base_url,
+            set_model,
+            set_auth_header,
+            set_max_tokens,
+            stage,
+        } => {
+            handle_model_config_subcommand(
+                set_api_key,
+                set_api_key_command,
+                set_api_key_file,
+                set_system_prompt,
+                set_temperature,
+                set_provider,
+                set_base_url,
+                set_model,
+                set_auth_header,
+                set_max_tokens,
+                stage,
+            )
+            .await?;
         }
-        Some(Commands::Checkpoint { paths, revert }) => {
-            handle_checkpoint_subcommand(paths, revert).await?;
+        Commands::Checkpoint { command } => {
+            handle_checkpoint_subcommand(&config_args.resolve()?.config, command).await?;
+        }
+        Commands::Recipe { command } => {
+            handle_recipe_subcommand(config_args, command).await?;
         }
-        None => {}
     }
 
     Ok(())
 }
 
-async fn handle_rollback_subcommand() -> Result<(), AppError> {
-    let config = read_config()?;
+/// Rolls back the run `id`, or the last run. Without any recorded run, falls
+/// back to the single `.rollback` snapshot kept by older versions.
+async fn handle_rollback_subcommand(
+    config: &Config,
+    id: Option<String>,
+    force: bool,
+) -> Result<(), AppError> {
+    let output_directory = Path::new(&config.output_directory);
+    if id.is_none()
+        && history::list_runs(output_directory).await?.is_empty()
+        && output_directory.join("press.output/.rollback").exists()
+    {
+        return writer::rollback_last_run(output_directory).await;
+    }
+    history::rollback_run(output_directory, id.as_deref(), force).await
+}
+
+/// Handles the history subcommand.
+async fn handle_history_subcommand(
+    config: &Config,
+    command: HistoryCommands,
+) -> Result<(), AppError> {
     let output_directory = Path::new(&config.output_directory);
-    writer::rollback_last_run(output_directory).await
+    let display_manager = CliDisplayManager::new();
+
+    match command {
+        HistoryCommands::List => {
+            display_manager.print_history(&history::list_runs(output_directory).await?);
+        }
+        HistoryCommands::Show { id } => {
+            let (dir, run) = history::load_run(output_directory, &id).await?;
+            let diff = tokio::fs::read_to_string(dir.join("diff.patch"))
+                .await
+                .unwrap_or_default();
+            display_manager.print_run(&run, &run.file_states().await, &diff);
+        }
+    }
+
+    Ok(())
 }
 
 /// Handles the config subcommand
+#[allow(clippy::too_many_arguments)]
 async fn handle_config_subcommand(
     set_chunk_size: Option<usize>,
     set_log_level: Option<String>,
     set_output_directory: Option<String>,
     set_retries: Option<u32>,
+    set_stream: Option<bool>,
+    set_repair_attempts: Option<u32>,
+    set_connect_timeout: Option<u64>,
+    set_timeout: Option<u64>,
+    set_chunk_strategy: Option<ChunkStrategy>,
+    set_context_budget: Option<usize>,
+    set_budget_policy: Option<BudgetPolicy>,
+    set_edit_format: Option<EditFormat>,
+    set_git: Option<bool>,
+    set_git_dirty: Option<DirtyTreePolicy>,
+    set_git_branch_prefix: Option<String>,
+    set_allow_extensions: Option<Vec<String>>,
+    set_deny_extensions: Option<Vec<String>>,
+    set_verify_iterations: Option<u32>,
 ) -> Result<(), AppError> {
-    let mut config = read_config()?;
+    let mut config = read_user_config()?;
 
     if let Some(chunk_size) = set_chunk_size {
         config.chunk_size = chunk_size;
@@ -428,21 +337,135 @@ async fn handle_config_subcommand(
         println!("Retries set to {}", retries);
     }
 
+    if let Some(stream) = set_stream {
+        config.stream = stream;
+        println!("Streaming set to {}", stream);
+    }
+
+    if let Some(repair_attempts) = set_repair_attempts {
+        config.repair_attempts = repair_attempts;
+        println!("Repair attempts set to {}", repair_attempts);
+    }
+
+    if let Some(connect_timeout) = set_connect_timeout {
+        config.connect_timeout_secs = connect_timeout;
+        println!("Connect timeout set to {}s", connect_timeout);
+    }
+
+    if let Some(timeout) = set_timeout {
+        config.timeout_secs = timeout;
+        println!("Request timeout set to {}s", timeout);
+    }
+
+    if let Some(chunk_strategy) = set_chunk_strategy {
+        config.chunk_strategy = chunk_strategy;
+        println!("Chunk strategy set to {:?}", chunk_strategy);
+    }
+
+    if let Some(context_budget) = set_context_budget {
+        config.context_budget = context_budget;
+        println!("Context budget set to {} tokens", context_budget);
+    }
+
+    if let Some(budget_policy) = set_budget_policy {
+        config.budget_policy = budget_policy;
+        println!("Budget policy set to {:?}", budget_policy);
+    }
+
+    if let Some(edit_format) = set_edit_format {
+        config.edit_format = edit_format;
+        println!("Edit format set to {:?}", edit_format);
+    }
+
+    if let Some(git) = set_git {
+        config.git.enabled = git;
+        println!("Git mode set to {}", git);
+    }
+
+    if let Some(git_dirty) = set_git_dirty {
+        config.git.dirty = git_dirty;
+        println!("Git dirty tree policy set to {:?}", git_dirty);
+    }
+
+    if let Some(git_branch_prefix) = set_git_branch_prefix {
+        config.git.branch_prefix = git_branch_prefix.clone();
+        println!("Git branch prefix set to {}", git_branch_prefix);
+    }
+
+    if let Some(allow_extensions) = set_allow_extensions {
+        config.files.allow_extensions = non_empty(allow_extensions);
+        println!(
+            "Allowed extensions set to [{}]",
+            config.files.allow_extensions.join(", ")
+        );
+    }
+
+    if let Some(deny_extensions) = set_deny_extensions {
+        config.files.deny_extensions = non_empty(deny_extensions);
+        println!(
+            "Denied extensions set to [{}]",
+            config.files.deny_extensions.join(", ")
+        );
+    }
+
+    if let Some(verify_iterations) = set_verify_iterations {
+        config.verify_iterations = verify_iterations;
+        println!("Verify iterations set to {}", verify_iterations);
+    }
+
     write_config(&config)?;
     Ok(())
 }
 
-/// Handles the model-config subcommand
+/// Drops the empty entries of a comma-separated list, so `""` clears it.
+fn non_empty(values: Vec<String>) -> Vec<String> {
+    values
+        .into_iter()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// Handles the model-config subcommand.
+/// With `stage`, the model settings go to that stage's `[models.*]` section.
+#[allow(clippy::too_many_arguments)]
 async fn handle_model_config_subcommand(
     set_api_key: Option<String>,
+    set_api_key_command: Option<String>,
+    set_api_key_file: Option<String>,
     set_system_prompt: Option<String>,
     set_temperature: Option<f32>,
+    set_provider: Option<ProviderKind>,
+    set_base_url: Option<String>,
+    set_model: Option<String>,
+    set_auth_header: Option<String>,
+    set_max_tokens: Option<u32>,
+    stage: Option<Stage>,
 ) -> Result<(), AppError> {
-    let mut config = read_config()?;
+    let mut config = read_user_config()?;
 
+    // The key goes to its own file, readable only by the current user, and
+    // any copy left in the user config is dropped
     if let Some(api_key) = set_api_key {
-        config.api_key = Some(api_key.clone());
-        println!("API key set");
+        let path = store_api_key(&api_key)?;
+        config.api_key = None;
+        println!("API key saved to {}", path.display());
+    }
+
+    if let Some(api_key_command) = set_api_key_command {
+        config.api_key_command = non_empty(vec![api_key_command]).pop();
+        println!(
+            "API key command set to {}",
+            config.api_key_command.as_deref().unwrap_or("nothing")
+        );
+    }
+
+    if let Some(api_key_file) = set_api_key_file {
+        config.api_key_file = non_empty(vec![api_key_file]).pop();
+        println!(
+            "API key file set to {}",
+            config.api_key_file.as_deref().unwrap_or("nothing")
+        );
     }
 
     if let Some(system_prompt) = set_system_prompt {
@@ -450,154 +473,187 @@ async fn handle_model_config_subcommand(
         println!("System prompt set to: {}", system_prompt);
     }
 
-    if let Some(temperature) = set_temperature {
-        config.temperature = temperature;
-    }
+    if let Some(stage) = stage {
+        let stage_config = config.stage_config_mut(stage);
 
-    write_config(&config)?;
-    Ok(())
-}
+        if let Some(temperature) = set_temperature {
+            stage_config.temperature = Some(temperature);
+            println!("{} temperature set to {}", stage, temperature);
+        }
 
-use walkdir::WalkDir;
+        if let Some(provider) = set_provider {
+            stage_config.provider = Some(provider);
+            println!("{} provider set to {:?}", stage, provider);
+        }
 
-async fn handle_checkpoint_subcommand(paths: Vec<String>, revert: bool) -> Result<(), AppError> {
-    let config = read_config()?;
-    let output_dir = Path::new(&config.output_directory).join("press.output");
-    tokio::fs::create_dir_all(&output_dir).await?;
-    let checkpoint_dir = output_dir.join(".checkpoint");
+        if let Some(base_url) = set_base_url {
+            stage_config.base_url = Some(base_url.clone());
+            println!("{} base URL set to {}", stage, base_url);
+        }
 
-    if revert {
-        if !checkpoint_dir.exists() {
-            return Err(AppError::CheckpointError(
-                "No checkpoint to revert to".to_string(),
-            ));
+        if let Some(model) = set_model {
+            stage_config.model = Some(model.clone());
+            println!("{} model set to {}", stage, model);
         }
 
-        let checkpoint_config_path = checkpoint_dir.join("checkpoint.toml");
-        let checkpoint_config_str = tokio::fs::read_to_string(&checkpoint_config_path).await?;
-        let checkpoint_config: crate::file_processing::writer::CheckpointConfig =
-            toml::from_str(&checkpoint_config_str)
-                .map_err(|e| AppError::CheckpointError(e.to_string()))?;
-
-        for (original_path, backup_path) in checkpoint_config.checkpoint_files {
-            let original_path = Path::new(&original_path);
-            let backup_path = Path::new(&backup_path);
-            if backup_path.exists() {
-                if let Some(parent) = original_path.parent() {
-                    tokio::fs::create_dir_all(parent).await?;
-                }
-                tokio::fs::copy(backup_path, original_path).await?;
-                println!("Restored: {}", original_path.display());
-            }
+        if let Some(auth_header) = set_auth_header {
+            stage_config.auth_header = Some(auth_header.clone());
+            println!("{} auth header set to {}", stage, auth_header);
+        }
+
+        if let Some(max_tokens) = set_max_tokens {
+            stage_config.max_tokens = Some(max_tokens);
+            println!("{} max tokens set to {}", stage, max_tokens);
         }
     } else {
-        if checkpoint_dir.exists() {
-            tokio::fs::remove_dir_all(&checkpoint_dir).await?;
+        if let Some(temperature) = set_temperature {
+            config.temperature = temperature;
         }
-        tokio::fs::create_dir_all(&checkpoint_dir).await?;
-
-        let mut checkpoint_files = Vec::new();
-        let mut files_to_process = Vec::new();
-
-        // First, collect all files using WalkDir (synchronously, but very fast)
-        for path_str in paths {
-            let path = Path::new(&path_str);
-            if !path.exists() {
-                return Err(AppError::CheckpointError(format!(
-                    "Path does not exist: {}",
-                    path.display()
-                )));
-            }
 
-            if path.is_dir() {
-                for entry in WalkDir::new(path)
-                    .follow_links(true)
-                    .into_iter()
-                    .filter_map(|e| e.ok())
-                {
-                    if entry.file_type().is_file() {
-                        files_to_process.push(entry.path().to_path_buf());
-                    }
-                }
-            } else {
-                files_to_process.push(path.to_path_buf());
-            }
+        if let Some(provider) = set_provider {
+            config.provider.kind = provider;
+            println!("Provider set to {:?}", provider);
         }
 
-        // Then process all files using async operations
-        for file_path in files_to_process {
-            // Create a unique backup path that preserves the directory structure
-            let relative_path = file_path.strip_prefix(".").unwrap_or(&file_path);
-            let backup_path = checkpoint_dir.join(
-                relative_path
-                    .to_string_lossy()
-                    .to_string()
-                    .replace("\\", "_")
-                    .replace("/", "_"),
-            );
-
-            tokio::fs::copy(&file_path, &backup_path).await?;
+        if let Some(base_url) = set_base_url {
+            config.provider.base_url = Some(base_url.clone());
+            println!("Base URL set to {}", base_url);
+        }
 
-            checkpoint_files.push((
-                file_path.to_string_lossy().to_string(),
-                backup_path.to_string_lossy().to_string(),
-            ));
+        if let Some(model) = set_model {
+            config.provider.model = Some(model.clone());
+            println!("Model set to {}", model);
+        }
 
-            println!("Checkpointed: {}", file_path.display());
+        if let Some(auth_header) = set_auth_header {
+            config.provider.auth_header = Some(auth_header.clone());
+            println!("Auth header set to {}", auth_header);
         }
+    }
 
-        let checkpoint_config =
-            crate::file_processing::writer::CheckpointConfig { checkpoint_files };
+    write_config(&config)?;
+    Ok(())
+}
+
+/// Handles the checkpoint subcommand.
+async fn handle_checkpoint_subcommand(
+    config: &Config,
+    command: CheckpointCommands,
+) -> Result<(), AppError> {
+    let output_directory = Path::new(&config.output_directory);
+    let display_manager = CliDisplayManager::new();
 
-        let checkpoint_config_str = toml::to_string(&checkpoint_config)
-            .map_err(|e| AppError::CheckpointError(e.to_string()))?;
-        tokio::fs::write(
-            checkpoint_dir.join("checkpoint.toml"),
-            checkpoint_config_str,
-        )
-        .await?;
+    match command {
+        CheckpointCommands::Create { name, mut paths } => {
+            if paths.is_empty() {
+                paths.push(".".to_string());
+            }
+            let checkpoint = checkpoint::create(output_directory, &name, &paths).await?;
+            println!(
+                "Created checkpoint '{}' of {} file(s)",
+                checkpoint.name,
+                checkpoint.files.len()
+            );
+        }
+        CheckpointCommands::List => {
+            display_manager.print_checkpoints(&checkpoint::list(output_directory).await?);
+        }
+        CheckpointCommands::Diff { name } => {
+            let checkpoint = checkpoint::load(output_directory, &name).await?;
+            let changes = checkpoint::diff(output_directory, &checkpoint).await?;
+            display_manager.print_checkpoint_changes(&changes);
+            for change in &changes {
+                let old = change
+                    .checkpoint_content
+                    .as_deref()
+                    .map(std::str::from_utf8);
+                let new = change.current_content.as_deref().map(std::str::from_utf8);
+                let (old_label, new_label) = match change.change {
+                    Change::Added => ("/dev/null", change.path.as_str()),
+                    Change::Deleted => (change.path.as_str(), "/dev/null"),
+                    Change::Modified => (change.path.as_str(), change.path.as_str()),
+                };
+                match (old.transpose(), new.transpose()) {
+                    (Ok(old), Ok(new)) => display_manager.print_diff(&unified_diff(
+                        old_label,
+                        new_label,
+                        old.unwrap_or_default(),
+                        new.unwrap_or_default(),
+                    )),
+                    _ => println!("Binary file {} differs\n", change.path),
+                }
+            }
+        }
+        CheckpointCommands::Restore { name, force } => {
+            let checkpoint = checkpoint::load(output_directory, &name).await?;
+            checkpoint::restore(output_directory, &checkpoint, force).await?;
+            println!("Restored checkpoint '{}'", checkpoint.name);
+        }
+        CheckpointCommands::Delete { name } => {
+            checkpoint::delete(output_directory, &name).await?;
+            println!("Deleted checkpoint '{}'", name);
+        }
     }
 
     Ok(())
 }
 
-///  Filters out parts of `FileChunks` that are not specified in `parts_to_edit_hashmap`.
-///
-///  Args:
-///     output_file_text: A vector of `FileChunks` containing file paths and their parts.
-///     parts_to_edit_hashmap: A hashmap where the key is the file path and the value is a vector of part IDs to keep.
-///
-///  Returns:
-///     A vector of `FileChunks` containing only the parts specified in `parts_to_edit_hashmap`.
-///
-fn filter_out_unused_parts(
-    output_file_text: &Vec<FileChunks>,
-    parts_to_edit_hashmap: &std::collections::HashMap<String, Vec<usize>>,
-) -> Vec<FileChunks> {
-    let mut filtered_output_file_text: Vec<FileChunks> = Vec::new();
-
-    for file_chunk in output_file_text {
-        let file_path = &file_chunk.file_path;
-
-        // Check if the file path exists in the hashmap
-        if let Some(parts_to_edit) = parts_to_edit_hashmap.get(file_path) {
-            // Filter the parts to keep only those specified in parts_to_edit
-            let filtered_parts: Vec<FilePart> = file_chunk
-                .parts
+/// Handles the recipe subcommand.
+async fn handle_recipe_subcommand(
+    config_args: &ConfigArgs,
+    command: RecipeCommands,
+) -> Result<(), AppError> {
+    match command {
+        RecipeCommands::Run {
+            name,
+            vars,
+            mut run,
+        } => {
+            let recipe = find_recipe(&name)?;
+            let vars = vars
                 .iter()
-                .filter(|part| parts_to_edit.contains(&part.part_id))
-                .cloned()
-                .collect();
-
-            // If there are parts to keep, add the file to the result
-            if !filtered_parts.is_empty() {
-                filtered_output_file_text.push(FileChunks {
-                    file_path: file_path.clone(),
-                    parts: filtered_parts,
-                });
+                .map(|var| {
+                    var.split_once('=')
+                        .map(|(name, value)| (name.trim().to_string(), value.to_string()))
+                        .ok_or_else(|| {
+                            AppError::InvalidInput(format!("Expected NAME=VALUE, got '{}'", var))
+                        })
+                })
+                .collect::<Result<BTreeMap<_, _>, _>>()?;
+
+            // The recipe's system prompt sits just below --system-prompt
+            let mut config_args = config_args.clone();
+            if config_args.system_prompt.is_none() {
+                config_args.system_prompt = recipe.system_prompt.clone();
             }
+            let config = config_args.resolve()?.config;
+
+            // Paths and include globs on the command line replace the
+            // recipe's, ignores and exclude globs add to them
+            if run.paths.is_empty() {
+                run.paths = recipe.paths.clone();
+            }
+            if run.include.is_empty() {
+                run.include = recipe.include.clone();
+            }
+            run.ignore.extend(recipe.ignore.iter().cloned());
+            run.exclude.extend(recipe.exclude.iter().cloned());
+
+            let files = reader::get_files_to_press(
+                &run.paths,
+                &run.ignore,
+                &run.include,
+                &run.exclude,
+                &config.files,
+            )?
+            .files;
+            let prompt = recipe.render(&files, &vars)?;
+            run_pipeline(prompt, run, config).await?;
+        }
+        RecipeCommands::List => {
+            CliDisplayManager::new().print_recipes(&list_recipes()?);
         }
     }
 
-    filtered_output_file_text
+    Ok(())
 }
@@ -8,10 +8,22 @@ pub struct CodeAssistantResponse {
     pub response: String,
 }
 
+/// Changes to an existing file, either as whole parts or as search/replace
+/// blocks depending on the configured edit format.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct UpdatedFile {
     pub file_path: String,
+    #[serde(default)]
     pub parts: Vec<FilePart>,
+    #[serde(default)]
+    pub edits: Vec<SearchReplace>,
+}
+
+/// Replaces the only occurrence of `search` in a file with `replace`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchReplace {
+    pub search: String,
+    pub replace: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
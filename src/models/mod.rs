@@ -1,7 +1,9 @@
 pub mod code_assistant_response;
+pub mod parsing;
 pub mod preprocessor_response;
 
-use serde::{Deserialize, Serialize};
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FileChunks {
@@ -11,6 +13,7 @@ pub struct FileChunks {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FilePart {
+    #[serde(deserialize_with = "deserialize_part_id")]
     pub part_id: usize,
     pub content: String,
 }
@@ -18,5 +21,41 @@ pub struct FilePart {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct FilePartIds {
     pub file_path: String,
+    #[serde(deserialize_with = "deserialize_part_ids")]
     pub parts: Vec<usize>,
 }
+
+/// Accepts a part ID sent as an integer or as a string such as `"3"` or `"partId3"`.
+fn deserialize_part_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    let value = Value::deserialize(deserializer)?;
+    part_id_from_value(&value).map_err(D::Error::custom)
+}
+
+/// Accepts a list of part IDs, each sent as an integer or a string.
+fn deserialize_part_ids<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<usize>, D::Error> {
+    let values = Vec::<Value>::deserialize(deserializer)?;
+    values
+        .iter()
+        .map(|value| part_id_from_value(value).map_err(D::Error::custom))
+        .collect()
+}
+
+fn part_id_from_value(value: &Value) -> Result<usize, String> {
+    match value {
+        Value::Number(number) => number
+            .as_u64()
+            .map(|id| id as usize)
+            .ok_or_else(|| format!("invalid part ID: {}", number)),
+        Value::String(text) => {
+            let text = text.trim();
+            // Keep the trailing digits, so "partId12" and "part_12" both become 12
+            let digits_start = text.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+            text[digits_start..]
+                .parse()
+                .map_err(|_| format!("invalid part ID: {:?}", text))
+        }
+        other => Err(format!("invalid part ID: {}", other)),
+    }
+}
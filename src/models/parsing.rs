@@ -0,0 +1,180 @@
+use serde::de::DeserializeOwned;
+
+/// Parses a model reply into `T`, tolerating the usual quirks of LLM output:
+/// markdown code fences, text around the JSON object, single-quoted strings
+/// and trailing commas.
+///
+/// The reply is parsed as-is first and only repaired if that fails. The error
+/// of the repaired attempt is returned when every attempt fails.
+pub fn parse_model_response<T: DeserializeOwned>(raw: &str) -> Result<T, serde_json::Error> {
+    let raw = raw.trim();
+    if let Ok(parsed) = serde_json::from_str(raw) {
+        return Ok(parsed);
+    }
+
+    let body = extract_json_body(raw);
+    match serde_json::from_str(body) {
+        Ok(parsed) => Ok(parsed),
+        Err(_) => serde_json::from_str(&repair_json(body)),
+    }
+}
+
+/// Strips markdown fences and any text before the first `{` or after the last `}`.
+/// Fences are only looked for when the reply doesn't already start with `{`,
+/// since edited code may legitimately contain them.
+fn extract_json_body(raw: &str) -> &str {
+    let mut body = raw;
+
+    if let Some(fence_start) = body.find("```").filter(|_| !body.starts_with('{')) {
+        let after_fence = &body[fence_start + 3..];
+        // Skip the language tag of the opening fence, e.g. ```json
+        let content_start = after_fence.find('\n').map_or(0, |newline| newline + 1);
+        let content = &after_fence[content_start..];
+        body = match content.rfind("```") {
+            Some(fence_end) => &content[..fence_end],
+            None => content,
+        };
+    }
+
+    match (body.find('{'), body.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &body[start..=end],
+        _ => body.trim(),
+    }
+}
+
+/// Rewrites single-quoted strings as double-quoted ones and drops trailing
+/// commas. Double-quoted strings are copied untouched.
+///
+/// A single quote only closes a string when it is followed by `,` `:` `}` `]`
+/// or the end of input, so apostrophes inside text (`'don't'`) survive.
+fn repair_json(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut repaired = String::with_capacity(text.len());
+    let mut index = 0;
+
+    while index < chars.len() {
+        let c = chars[index];
+        match c {
+            '"' => {
+                repaired.push(c);
+                index += 1;
+                while index < chars.len() {
+                    let c = chars[index];
+                    repaired.push(c);
+                    index += 1;
+                    if c == '\\' && index < chars.len() {
+                        repaired.push(chars[index]);
+                        index += 1;
+                    } else if c == '"' {
+                        break;
+                    }
+                }
+                continue;
+            }
+            '\'' => {
+                repaired.push('"');
+                index += 1;
+                while index < chars.len() {
+                    let c = chars[index];
+                    index += 1;
+                    match c {
+                        '\\' if index < chars.len() => {
+                            let escaped = chars[index];
+                            index += 1;
+                            if escaped == '\'' {
+                                repaired.push('\'');
+                            } else {
+                                repaired.push('\\');
+                                repaired.push(escaped);
+                            }
+                        }
+                        '"' => repaired.push_str("\\\""),
+                        '\'' if closes_string(&chars[index..]) => {
+                            repaired.push('"');
+                            break;
+                        }
+                        '\n' => repaired.push_str("\\n"),
+                        _ => repaired.push(c),
+                    }
+                }
+                continue;
+            }
+            ',' => {
+                let next = chars[index + 1..].iter().find(|c| !c.is_whitespace());
+                if !matches!(next, Some('}') | Some(']')) {
+                    repaired.push(c);
+                }
+            }
+            _ => repaired.push(c),
+        }
+        index += 1;
+    }
+
+    repaired
+}
+
+/// Whether the text following a single quote makes it a closing quote.
+fn closes_string(rest: &[char]) -> bool {
+    matches!(
+        rest.iter().find(|c| !c.is_whitespace()),
+        None | Some(',') | Some(':') | Some('}') | Some(']')
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn parse(raw: &str) -> Value {
+        parse_model_response(raw).unwrap()
+    }
+
+    #[test]
+    fn extracts_the_body_of_a_code_fence() {
+        let raw = "Here you go:\n```json\n{\"a\": 1}\n```\nDone.";
+        assert_eq!(extract_json_body(raw), "{\"a\": 1}");
+        assert_eq!(parse(raw), json!({"a": 1}));
+    }
+
+    #[test]
+    fn keeps_fences_inside_a_reply_that_starts_with_json() {
+        let raw = "{\"content\": \"```rust\\nfn main() {}\\n```\"} trailing";
+        assert_eq!(parse(raw), json!({"content": "```rust\nfn main() {}\n```"}));
+    }
+
+    #[test]
+    fn strips_text_around_the_object() {
+        assert_eq!(
+            extract_json_body("Sure! {\"a\": [1]} Hope it helps"),
+            "{\"a\": [1]}"
+        );
+    }
+
+    #[test]
+    fn rewrites_single_quoted_strings_keeping_apostrophes() {
+        let raw = "{'text': 'don't stop', 'quote': 'say \"hi\"'}";
+        assert_eq!(
+            parse(raw),
+            json!({"text": "don't stop", "quote": "say \"hi\""})
+        );
+    }
+
+    #[test]
+    fn leaves_apostrophes_in_double_quoted_strings_alone() {
+        let raw = "{\"text\": \"it's fine\",}";
+        assert_eq!(repair_json(raw), "{\"text\": \"it's fine\"}");
+    }
+
+    #[test]
+    fn drops_trailing_commas() {
+        let raw = "{\"a\": [1, 2, ], \"b\": {\"c\": 3,\n},\n}";
+        assert_eq!(parse(raw), json!({"a": [1, 2], "b": {"c": 3}}));
+    }
+
+    #[test]
+    fn keeps_commas_inside_strings() {
+        let raw = "{'a': 'x, }', 'b': 1,}";
+        assert_eq!(parse(raw), json!({"a": "x, }", "b": 1}));
+    }
+}
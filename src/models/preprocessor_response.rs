@@ -6,3 +6,87 @@ pub struct PreprocessorResponse {
     pub parts_to_edit: Vec<FilePartIds>,
     pub preprocessor_prompt: String,
 }
+
+impl PreprocessorResponse {
+    /// Merges the responses of several preprocessor calls made over batches
+    /// of the same files. Part IDs are unioned per file, keeping the order in
+    /// which files first appear, and the prompts are joined.
+    pub fn merge(responses: Vec<PreprocessorResponse>) -> PreprocessorResponse {
+        let mut parts_to_edit: Vec<FilePartIds> = Vec::new();
+        let mut prompts = Vec::new();
+
+        for response in responses {
+            for file in response.parts_to_edit {
+                match parts_to_edit
+                    .iter_mut()
+                    .find(|existing| existing.file_path == file.file_path)
+                {
+                    Some(existing) => existing.parts.extend(file.parts),
+                    None => parts_to_edit.push(file),
+                }
+            }
+            if !response.preprocessor_prompt.trim().is_empty() {
+                prompts.push(response.preprocessor_prompt);
+            }
+        }
+
+        for file in &mut parts_to_edit {
+            file.parts.sort_unstable();
+            file.parts.dedup();
+        }
+
+        PreprocessorResponse {
+            parts_to_edit,
+            preprocessor_prompt: prompts.join("\n\n"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(files: &[(&str, &[usize])], prompt: &str) -> PreprocessorResponse {
+        PreprocessorResponse {
+            parts_to_edit: files
+                .iter()
+                .map(|(file_path, parts)| FilePartIds {
+                    file_path: file_path.to_string(),
+                    parts: parts.to_vec(),
+                })
+                .collect(),
+            preprocessor_prompt: prompt.to_string(),
+        }
+    }
+
+    #[test]
+    fn merges_overlapping_parts_of_a_file_split_over_batches() {
+        let merged = PreprocessorResponse::merge(vec![
+            response(&[("big.rs", &[5, 2, 3]), ("a.rs", &[1])], "first"),
+            response(&[("b.rs", &[2]), ("big.rs", &[3, 7, 2])], "second"),
+        ]);
+        let files: Vec<(&str, &[usize])> = merged
+            .parts_to_edit
+            .iter()
+            .map(|file| (file.file_path.as_str(), file.parts.as_slice()))
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("big.rs", &[2, 3, 5, 7][..]),
+                ("a.rs", &[1][..]),
+                ("b.rs", &[2][..]),
+            ]
+        );
+        assert_eq!(merged.preprocessor_prompt, "first\n\nsecond");
+    }
+
+    #[test]
+    fn skips_empty_prompts() {
+        let merged = PreprocessorResponse::merge(vec![
+            response(&[], "  "),
+            response(&[("a.rs", &[1])], "only"),
+        ]);
+        assert_eq!(merged.preprocessor_prompt, "only");
+    }
+}
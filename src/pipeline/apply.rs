@@ -0,0 +1,279 @@
+use super::observer::ChangeReview;
+use super::FileDiff;
+use crate::errors::AppError;
+use crate::file_processing::history::RunRecorder;
+use crate::file_processing::{chunker, edits};
+use crate::models::code_assistant_response::{CodeAssistantResponse, NewFile};
+use crate::utils::config::ChunkStrategy;
+use similar::TextDiff;
+use std::path::{Path, PathBuf};
+
+/// The new content of an existing file, ready to be written.
+pub(crate) struct FileUpdate {
+    /// Path as named by the model.
+    pub file_path: String,
+    pub original_path: PathBuf,
+    pub content: String,
+}
+
+/// Files written by [`apply_response`].
+pub(crate) struct AppliedChanges {
+    pub saved_files: Vec<PathBuf>,
+    pub new_files: Vec<PathBuf>,
+    pub diffs: Vec<FileDiff>,
+}
+
+///
+/// Processes the `CodeAssistantResponse` to save updated files, create new files,
+/// and write the response text. Every file is recorded with `recorder` before
+/// it is overwritten or created, so the run can be rolled back later.
+/// The diff of the written changes goes to `output_directory/diff.patch`.
+///
+/// With `in_place` the original files are overwritten, otherwise the updated
+/// files are put in `output_directory/code/`. With `review`, only the
+/// accepted changes are written.
+///
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn apply_response(
+    response: &CodeAssistantResponse,
+    original_paths: &[PathBuf],
+    output_directory: &Path,
+    in_place: bool,
+    review: Option<&mut Box<dyn ChangeReview>>,
+    recorder: &mut RunRecorder,
+    chunk_size: usize,
+    chunk_strategy: ChunkStrategy,
+) -> Result<AppliedChanges, AppError> {
+    // Updated files: build every new file content up front, so an edit that
+    // doesn't apply stops the run before anything is written
+    let mut file_updates =
+        build_updated_contents(response, original_paths, chunk_size, chunk_strategy).await?;
+    let mut new_files_to_create: Vec<&NewFile> = response.new_files.iter().collect();
+
+    // When reviewing, only the accepted hunks and new files are kept
+    if let Some(reviewer) = review {
+        let mut accepted = Vec::new();
+        for mut update in file_updates {
+            let original_content = tokio::fs::read_to_string(&update.original_path).await?;
+            if let Some(content) =
+                reviewer.review_file(&update.file_path, &original_content, &update.content)?
+            {
+                update.content = content;
+                accepted.push(update);
+            }
+        }
+        file_updates = accepted;
+
+        let mut accepted = Vec::new();
+        for new_file in new_files_to_create {
+            if reviewer.review_new_file(&new_file.file_path, &new_file.content)? {
+                accepted.push(new_file);
+            }
+        }
+        new_files_to_create = accepted;
+    }
+
+    let diffs = diff_changes(&file_updates, &new_files_to_create).await?;
+
+    // Now, proceed with overwriting (updated) and creating (new) files.
+    let mut saved_files = Vec::new();
+    let mut new_files = Vec::new();
+
+    // Process updated files
+    for update in &file_updates {
+        // In place, overwrite the original file directly
+        // otherwise, put the updated file in output_directory/code/<file_path>
+        let output_file_path = if in_place {
+            update.original_path.clone()
+        } else {
+            output_directory.join("code").join(&update.file_path)
+        };
+
+        if let Some(parent) = output_file_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Only files overwritten in place need to be restored on rollback
+        if in_place {
+            recorder
+                .record_modified_file(&update.original_path, &update.content)
+                .await?;
+        }
+
+        tokio::fs::write(&output_file_path, update.content.as_bytes()).await?;
+        saved_files.push(output_file_path);
+    }
+
+    // Process new files. A "new" file that already exists is overwritten
+    // like an updated one, after its content is backed up.
+    for new_file in &new_files_to_create {
+        let file_path = PathBuf::from(&new_file.file_path);
+        if let Some(parent) = file_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let existed = tokio::fs::try_exists(&file_path).await?;
+        recorder
+            .record_new_file(&file_path, &new_file.content)
+            .await?;
+        tokio::fs::write(&file_path, new_file.content.as_bytes()).await?;
+        if existed {
+            saved_files.push(file_path);
+        } else {
+            new_files.push(file_path);
+        }
+    }
+
+    // Write the response text if present
+    if !response.response.is_empty() {
+        let response_txt_path = output_directory.join("response.txt");
+        tokio::fs::create_dir_all(output_directory).await?;
+        tokio::fs::write(&response_txt_path, response.response.as_bytes()).await?;
+    }
+
+    if !diffs.is_empty() {
+        tokio::fs::create_dir_all(output_directory).await?;
+        tokio::fs::write(output_directory.join("diff.patch"), join_diffs(&diffs)).await?;
+    }
+
+    Ok(AppliedChanges {
+        saved_files,
+        new_files,
+        diffs,
+    })
+}
+
+/// Returns the diffs `response` would make, without writing anything.
+pub(crate) async fn preview_response(
+    response: &CodeAssistantResponse,
+    original_paths: &[PathBuf],
+    chunk_size: usize,
+    chunk_strategy: ChunkStrategy,
+) -> Result<Vec<FileDiff>, AppError> {
+    let file_updates =
+        build_updated_contents(response, original_paths, chunk_size, chunk_strategy).await?;
+    let new_files: Vec<&NewFile> = response.new_files.iter().collect();
+    diff_changes(&file_updates, &new_files).await
+}
+
+/// Diffs every update against the file on disk, and every new file against nothing.
+async fn diff_changes(
+    file_updates: &[FileUpdate],
+    new_files: &[&NewFile],
+) -> Result<Vec<FileDiff>, AppError> {
+    let mut diffs = Vec::new();
+    for update in file_updates {
+        let original_content = tokio::fs::read_to_string(&update.original_path).await?;
+        let path = update.original_path.to_string_lossy();
+        diffs.push(FileDiff {
+            path: update.original_path.clone(),
+            new_file: false,
+            diff: unified_diff(&path, &path, &original_content, &update.content),
+        });
+    }
+    for new_file in new_files {
+        // The model may name a file that already exists, which is then overwritten
+        let existing_content = match tokio::fs::read_to_string(&new_file.file_path).await {
+            Ok(content) => Some(content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let diff = match &existing_content {
+            Some(content) => unified_diff(
+                &new_file.file_path,
+                &new_file.file_path,
+                content,
+                &new_file.content,
+            ),
+            None => unified_diff("/dev/null", &new_file.file_path, "", &new_file.content),
+        };
+        diffs.push(FileDiff {
+            path: PathBuf::from(&new_file.file_path),
+            new_file: existing_content.is_none(),
+            diff,
+        });
+    }
+    Ok(diffs)
+}
+
+/// Joins the diffs of several files into one patch.
+pub(crate) fn join_diffs(diffs: &[FileDiff]) -> String {
+    diffs.iter().map(|diff| diff.diff.as_str()).collect()
+}
+
+/// Applies the updated parts and edits of `response` to the original files
+/// in memory and returns the new content of each file.
+pub(crate) async fn build_updated_contents(
+    response: &CodeAssistantResponse,
+    original_paths: &[PathBuf],
+    chunk_size: usize,
+    chunk_strategy: ChunkStrategy,
+) -> Result<Vec<FileUpdate>, AppError> {
+    let mut updated_contents: Vec<FileUpdate> = Vec::new();
+    for updated_file in &response.updated_files {
+        // Match whole path components, so `a.rs` doesn't pick up `ba.rs`
+        let fallback = PathBuf::from(&updated_file.file_path);
+        let response_path = Path::new(&updated_file.file_path);
+        let original_file_path = original_paths
+            .iter()
+            .find(|p| p.as_path() == response_path)
+            .or_else(|| original_paths.iter().find(|p| p.ends_with(response_path)))
+            .unwrap_or(&fallback);
+
+        let original_content = tokio::fs::read_to_string(&original_file_path).await?;
+
+        // Search/replace edits alone apply to the file as it is on disk, which
+        // keeps its line endings and final newline
+        let mut new_content = if updated_file.parts.is_empty() {
+            original_content
+        } else {
+            // Re-split exactly like the reader did, so part IDs line up
+            let mut parts = chunker::split_into_parts(
+                original_file_path,
+                &original_content,
+                chunk_size,
+                chunk_strategy,
+            );
+
+            for part in &updated_file.parts {
+                // Parse `part_id` into `usize`
+                let part_id: usize = part.part_id;
+
+                // Compare `part_id` with `parts.len()`
+                if part_id > 0 && part_id <= parts.len() {
+                    parts[part_id - 1] = part.content.clone();
+                }
+            }
+
+            parts.join("\n")
+        };
+        if !updated_file.edits.is_empty() {
+            new_content = edits::apply_search_replace(
+                &updated_file.file_path,
+                &new_content,
+                &updated_file.edits,
+            )?;
+        }
+
+        updated_contents.push(FileUpdate {
+            file_path: updated_file.file_path.clone(),
+            original_path: original_file_path.to_path_buf(),
+            content: new_content,
+        });
+    }
+
+    Ok(updated_contents)
+}
+
+/// Renders the unified diff between `old_content` and `new_content`.
+pub fn unified_diff(
+    old_label: &str,
+    new_label: &str,
+    old_content: &str,
+    new_content: &str,
+) -> String {
+    TextDiff::from_lines(old_content, new_content)
+        .unified_diff()
+        .context_radius(3)
+        .header(old_label, new_label)
+        .to_string()
+}
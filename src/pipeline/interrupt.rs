@@ -0,0 +1,120 @@
+use crate::errors::AppError;
+use crate::utils::config::Stage;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use tokio::sync::watch;
+
+/// Exit code of a process stopped by Ctrl-C, like the shell reports it.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// The process-wide Ctrl-C handler. Once tokio listens for Ctrl-C, the
+/// default handler is gone for good, so it is installed once and covers
+/// everything that follows.
+static HANDLER: OnceLock<Arc<Handler>> = OnceLock::new();
+
+struct Handler {
+    /// Number of Ctrl-C presses so far.
+    presses: watch::Sender<u64>,
+    /// Number of places currently handling Ctrl-C themselves.
+    catching: AtomicUsize,
+}
+
+/// Ctrl-C handling for one pipeline run.
+///
+/// While a model request or the verification command runs, Ctrl-C is caught:
+/// the request is cancelled, and the verify loop stops before the next fix
+/// round. Anywhere else (writing files, review prompts) Ctrl-C exits the
+/// process like it would without a handler.
+#[derive(Clone)]
+pub(crate) struct Interrupt {
+    /// The handler and the number of presses when the run started, `None`
+    /// when Ctrl-C is left alone.
+    handler: Option<(Arc<Handler>, u64)>,
+}
+
+impl Interrupt {
+    /// Leaves Ctrl-C alone: requests always run to completion.
+    pub fn disabled() -> Self {
+        Self { handler: None }
+    }
+
+    /// Installs the handler if it isn't yet. Must be called within a tokio runtime.
+    pub fn install() -> Self {
+        let handler = HANDLER
+            .get_or_init(|| {
+                let handler = Arc::new(Handler {
+                    presses: watch::Sender::new(0),
+                    catching: AtomicUsize::new(0),
+                });
+                let listener = Arc::clone(&handler);
+                tokio::spawn(async move {
+                    while tokio::signal::ctrl_c().await.is_ok() {
+                        listener.presses.send_modify(|presses| *presses += 1);
+                        if listener.catching.load(Ordering::SeqCst) == 0 {
+                            eprintln!("Interrupted by Ctrl-C");
+                            std::process::exit(INTERRUPTED_EXIT_CODE);
+                        }
+                    }
+                });
+                handler
+            })
+            .clone();
+        let presses = *handler.presses.borrow();
+        Self {
+            handler: Some((handler, presses)),
+        }
+    }
+
+    /// Whether Ctrl-C was pressed since the run started.
+    pub fn pressed(&self) -> bool {
+        self.handler
+            .as_ref()
+            .is_some_and(|(handler, start)| *handler.presses.borrow() > *start)
+    }
+
+    /// Runs the request of `stage` to completion unless Ctrl-C is pressed
+    /// first. Dropping the future cancels the in-flight request before
+    /// anything is written.
+    pub async fn until<T>(
+        &self,
+        stage: Stage,
+        future: impl Future<Output = T>,
+    ) -> Result<T, AppError> {
+        let Some((handler, start)) = &self.handler else {
+            return Ok(future.await);
+        };
+        let _catching = Catching::new(handler);
+        let mut presses = handler.presses.subscribe();
+        tokio::select! {
+            result = future => Ok(result),
+            _ = presses.wait_for(|presses| presses > start) => Err(AppError::Interrupted(stage)),
+        }
+    }
+
+    /// Runs `f`, typically a child process that gets Ctrl-C too, without
+    /// exiting on Ctrl-C; check [`Interrupt::pressed`] afterwards.
+    pub fn catch<T>(&self, f: impl FnOnce() -> T) -> T {
+        let _catching = self
+            .handler
+            .as_ref()
+            .map(|(handler, _)| Catching::new(handler));
+        f()
+    }
+}
+
+/// Keeps the handler from exiting the process while it lives.
+struct Catching<'a>(&'a Handler);
+
+impl<'a> Catching<'a> {
+    fn new(handler: &'a Handler) -> Self {
+        handler.catching.fetch_add(1, Ordering::SeqCst);
+        Self(handler)
+    }
+}
+
+impl Drop for Catching<'_> {
+    fn drop(&mut self) {
+        self.0.catching.fetch_sub(1, Ordering::SeqCst);
+    }
+}
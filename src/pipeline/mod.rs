@@ -0,0 +1,721 @@
+//! The press pipeline: discover the files, ask the preprocessor which parts
+//! need editing, have the code assistant rewrite them and apply the result.
+
+mod apply;
+mod interrupt;
+pub mod observer;
+mod query;
+
+pub use apply::unified_diff;
+pub use observer::{ChangeReview, PipelineEvent, PipelineObserver};
+
+use crate::api::provider::{build_provider, LlmProvider};
+use crate::errors::AppError;
+use crate::file_processing::history::{self, GitRecord, RunRecorder};
+use crate::file_processing::reader::{self, SkippedFile};
+use crate::models::code_assistant_response::CodeAssistantResponse;
+use crate::models::preprocessor_response::PreprocessorResponse;
+use crate::utils::api_key::resolve_api_key;
+use crate::utils::config::{ChunkStrategy, Config, EditFormat, Stage};
+use crate::utils::{git, verify};
+use interrupt::Interrupt;
+use query::{query_models, Models};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Where the changes of a run go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ApplyStrategy {
+    /// Write the updated files to `press.output/code/`, leaving the originals
+    /// alone. New files are always created in place.
+    #[default]
+    OutputDirectory,
+    /// Overwrite the original files.
+    InPlace,
+    /// Only compute the diffs; nothing is written, not even the exchange logs.
+    DryRun,
+}
+
+/// The diff of one file changed by a run.
+#[derive(Debug, Clone)]
+pub struct FileDiff {
+    pub path: PathBuf,
+    /// Whether the file didn't exist before the run.
+    pub new_file: bool,
+    /// Unified diff of the change.
+    pub diff: String,
+}
+
+/// The commit a git mode run made.
+#[derive(Debug, Clone)]
+pub struct GitCommit {
+    pub branch: String,
+    pub commit: String,
+}
+
+/// Rolls back a run recorded in the history, like `press rollback <id>`.
+#[derive(Debug, Clone)]
+pub struct RollbackHandle {
+    run_id: String,
+    output_directory: PathBuf,
+}
+
+impl RollbackHandle {
+    /// ID of the run in the history.
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Restores the files the run changed and deletes the ones it created.
+    /// Without `force`, asks on stdin before overwriting files changed since.
+    pub async fn rollback(&self, force: bool) -> Result<(), AppError> {
+        history::rollback_run(&self.output_directory, Some(&self.run_id), force).await
+    }
+}
+
+/// Everything a pipeline run produced.
+#[derive(Debug)]
+pub struct PipelineOutcome {
+    pub preprocessor_response: PreprocessorResponse,
+    pub code_assistant_response: CodeAssistantResponse,
+    /// Responses of the fix rounds of `--verify`, in order.
+    pub fix_responses: Vec<CodeAssistantResponse>,
+    /// Files that were found but not sent to the model.
+    pub skipped_files: Vec<SkippedFile>,
+    /// Existing files that were written, at their new location.
+    pub saved_files: Vec<PathBuf>,
+    pub new_files: Vec<PathBuf>,
+    /// The changes of the run, or the changes it would make for a dry run.
+    pub diffs: Vec<FileDiff>,
+    pub git_commit: Option<GitCommit>,
+    /// `None` for a dry run, which isn't recorded.
+    pub rollback: Option<RollbackHandle>,
+}
+
+/// Builds and runs one press request.
+///
+/// Everything not set on the builder comes from the [`Config`] it was
+/// created with.
+pub struct Pipeline {
+    config: Config,
+    paths: Vec<String>,
+    ignore: Vec<String>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    prompt: Option<String>,
+    context: Vec<String>,
+    preprocessor: Option<Arc<dyn LlmProvider>>,
+    code_assistant: Option<Arc<dyn LlmProvider>>,
+    apply: ApplyStrategy,
+    review: Option<Box<dyn ChangeReview>>,
+    git: bool,
+    verify: Option<(String, u32)>,
+    interrupt_on_ctrl_c: bool,
+}
+
+impl Pipeline {
+    /// Creates a pipeline with the settings of `config`.
+    pub fn new(config: Config) -> Self {
+        Self {
+            git: config.git.enabled,
+            config,
+            paths: Vec::new(),
+            ignore: Vec::new(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            prompt: None,
+            context: Vec::new(),
+            preprocessor: None,
+            code_assistant: None,
+            apply: ApplyStrategy::default(),
+            review: None,
+            verify: None,
+            interrupt_on_ctrl_c: false,
+        }
+    }
+
+    /// Files and directories to process.
+    pub fn paths(mut self, paths: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.paths = paths.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Files and directories to leave out.
+    pub fn ignore(mut self, paths: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.ignore = paths.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Only process the files in directories that match one of these globs.
+    pub fn include(mut self, globs: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.include = globs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Skip the files in directories that match any of these globs.
+    pub fn exclude(mut self, globs: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.exclude = globs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// What to ask the model for.
+    pub fn prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = Some(prompt.into());
+        self
+    }
+
+    /// Adds a section of context (console output, documentation, ...) after the prompt.
+    pub fn context(mut self, section: impl Into<String>) -> Self {
+        self.context.push(section.into());
+        self
+    }
+
+    /// Uses `provider` for both stages instead of the configured one.
+    pub fn provider(self, provider: Arc<dyn LlmProvider>) -> Self {
+        self.stage_provider(Stage::Preprocessor, Arc::clone(&provider))
+            .stage_provider(Stage::CodeAssistant, provider)
+    }
+
+    /// Uses `provider` for `stage` instead of the configured one.
+    pub fn stage_provider(mut self, stage: Stage, provider: Arc<dyn LlmProvider>) -> Self {
+        match stage {
+            Stage::Preprocessor => self.preprocessor = Some(provider),
+            Stage::CodeAssistant => self.code_assistant = Some(provider),
+        }
+        self
+    }
+
+    /// How files are split into parts.
+    pub fn chunker(mut self, chunk_size: usize, chunk_strategy: ChunkStrategy) -> Self {
+        self.config.chunk_size = chunk_size;
+        self.config.chunk_strategy = chunk_strategy;
+        self
+    }
+
+    /// How the code assistant describes its changes.
+    pub fn edit_format(mut self, edit_format: EditFormat) -> Self {
+        self.config.edit_format = edit_format;
+        self
+    }
+
+    /// Where the changes go. Git mode and verification always apply in place.
+    pub fn apply(mut self, apply: ApplyStrategy) -> Self {
+        self.apply = apply;
+        self
+    }
+
+    /// Lets `review` decide which changes are written.
+    pub fn review(mut self, review: Box<dyn ChangeReview>) -> Self {
+        self.review = Some(review);
+        self
+    }
+
+    /// Commits the changes on a new branch.
+    pub fn git(mut self, enabled: bool) -> Self {
+        self.git = enabled;
+        self
+    }
+
+    /// Runs `command` after the changes are written and sends its failures
+    /// back to the model, up to `max_rounds` times. The run is rolled back if
+    /// the command never passes.
+    pub fn verify(mut self, command: impl Into<String>, max_rounds: u32) -> Self {
+        self.verify = Some((command.into(), max_rounds));
+        self
+    }
+
+    /// Cancels the request in flight when Ctrl-C is pressed, returning
+    /// [`AppError::Interrupted`], and stops `--verify` before its next fix
+    /// round. This installs a process-wide Ctrl-C handler, which exits the
+    /// process when Ctrl-C is pressed anywhere else.
+    pub fn interrupt_on_ctrl_c(mut self, enabled: bool) -> Self {
+        self.interrupt_on_ctrl_c = enabled;
+        self
+    }
+
+    /// Runs the pipeline, reporting its progress to `observer`.
+    pub async fn run(
+        mut self,
+        observer: &mut dyn PipelineObserver,
+    ) -> Result<PipelineOutcome, AppError> {
+        let config = &self.config;
+        let prompt = self.prompt.take().ok_or(AppError::MissingPrompt)?;
+        let dry_run = self.apply == ApplyStrategy::DryRun;
+
+        // Build the provider of each stage first (fails early if an API key is required but missing)
+        // The key (which may run `api_key_command`) is only needed for the configured providers
+        let api_key = if self.preprocessor.is_none() || self.code_assistant.is_none() {
+            resolve_api_key(config)?
+        } else {
+            None
+        };
+        let preprocessor_stage = config.resolve_stage(Stage::Preprocessor);
+        let code_assistant_stage = config.resolve_stage(Stage::CodeAssistant);
+        let preprocessor = match self.preprocessor.take() {
+            Some(provider) => provider,
+            None => build_provider(
+                &preprocessor_stage.provider,
+                api_key.clone(),
+                config.timeouts(),
+            )?
+            .into(),
+        };
+        let code_assistant = match self.code_assistant.take() {
+            Some(provider) => provider,
+            None => {
+                build_provider(&code_assistant_stage.provider, api_key, config.timeouts())?.into()
+            }
+        };
+
+        // Git mode commits the run on a new branch. The tree is checked (and stashed) before the
+        // files are read, and put back if the run fails. A dry run never touches git.
+        let git_run = if self.git && !dry_run {
+            let git_run = git::prepare(&config.git, &prompt)?;
+            if git_run.stashed {
+                observer.on_event(PipelineEvent::GitStashed);
+            }
+            Some(git_run)
+        } else {
+            None
+        };
+
+        // A failed run puts the tree back the way git mode found it
+        let mut started_run: Option<String> = None;
+        let outcome = async {
+            let discovered_files = reader::get_files_to_press(
+                &self.paths,
+                &self.ignore,
+                &self.include,
+                &self.exclude,
+                &config.files,
+            )?;
+            let directory_files = discovered_files.files;
+            let skipped_files = discovered_files.skipped;
+            observer.on_event(PipelineEvent::FilesDiscovered {
+                count: directory_files.len(),
+                skipped: &skipped_files,
+            });
+
+            let mut combined_prompt = prompt.clone();
+            for context_section in &self.context {
+                combined_prompt.push_str("\n\n");
+                combined_prompt.push_str(context_section.trim_end());
+            }
+
+            let models = Models {
+                preprocessor,
+                preprocessor_stage,
+                code_assistant,
+                code_assistant_stage,
+                // A dry run doesn't even write the exchange logs
+                log_directory: (!dry_run).then_some(config.output_directory.as_str()),
+                interrupt: if self.interrupt_on_ctrl_c {
+                    Interrupt::install()
+                } else {
+                    Interrupt::disabled()
+                },
+            };
+            let (preprocessor_response, code_assistant_response) = query_models(
+                config,
+                &models,
+                observer,
+                &combined_prompt,
+                &directory_files,
+            )
+            .await?;
+
+            if dry_run {
+                let diffs = apply::preview_response(
+                    &code_assistant_response,
+                    &directory_files,
+                    config.chunk_size,
+                    config.chunk_strategy,
+                )
+                .await?;
+                return Ok(PipelineOutcome {
+                    preprocessor_response,
+                    code_assistant_response,
+                    fix_responses: Vec::new(),
+                    skipped_files,
+                    saved_files: Vec::new(),
+                    new_files: Vec::new(),
+                    diffs,
+                    git_commit: None,
+                    rollback: None,
+                });
+            }
+
+            observer.on_event(PipelineEvent::SavingStarted);
+
+            let output_directory = Path::new(&config.output_directory);
+            let press_output_dir = output_directory.join("press.output");
+            tokio::fs::create_dir_all(&press_output_dir).await?;
+
+            // Every run is recorded in the history so it can be rolled back later.
+            // Git mode needs the backups too: a run that fails verification or its
+            // commit is rolled back before the stash is popped.
+            let mut recorder =
+                RunRecorder::start(output_directory, &prompt, &self.paths, config).await?;
+            started_run = Some(recorder.id().to_string());
+
+            // Git mode and verification always edit the files in place
+            let in_place =
+                self.apply == ApplyStrategy::InPlace || git_run.is_some() || self.verify.is_some();
+            let applied = apply::apply_response(
+                &code_assistant_response,
+                &directory_files,
+                &press_output_dir,
+                in_place,
+                self.review.as_mut(),
+                &mut recorder,
+                config.chunk_size,
+                config.chunk_strategy,
+            )
+            .await?;
+            let mut saved_files = applied.saved_files;
+            let mut new_files = applied.new_files;
+            let mut diffs = applied.diffs;
+            let mut fix_responses: Vec<CodeAssistantResponse> = Vec::new();
+
+            observer.on_event(PipelineEvent::SavingFinished { in_place });
+
+            // Run the verification command and send its failures back to the model
+            // until it passes or the rounds run out. The files are already written
+            // by then, so a run that never passes, or whose fix round fails, is
+            // recorded and rolled back.
+            if let Some((command, max_rounds)) = &self.verify {
+                let max_rounds = *max_rounds;
+                let mut round = 0;
+                // Tells a failed fix round apart from a command that couldn't run
+                let mut fix_round_running = false;
+                let verification = async {
+                    loop {
+                        observer.on_event(PipelineEvent::VerifyStarted { command });
+                        // The command gets Ctrl-C too, so it only stops the loop below
+                        let outcome =
+                            models.interrupt.catch(|| verify::run_verify_command(command))?;
+                        if outcome.success {
+                            observer.on_event(PipelineEvent::VerifyPassed { fix_rounds: round });
+                            return Ok::<_, AppError>(None);
+                        }
+                        if round == max_rounds {
+                            return Ok(Some(outcome.output));
+                        }
+                        if models.interrupt.pressed() {
+                            return Err(AppError::VerificationFailed(format!(
+                                "`{}` was interrupted by Ctrl-C",
+                                command
+                            )));
+                        }
+                        round += 1;
+                        fix_round_running = true;
+                        observer.on_event(PipelineEvent::VerifyFailed {
+                            output: &outcome.output,
+                            round,
+                            max_rounds,
+                        });
+
+                        // The next round sees the files as they are now, including the new ones
+                        let mut files = directory_files.clone();
+                        for new_file in &new_files {
+                            if !files.contains(new_file) {
+                                files.push(new_file.clone());
+                            }
+                        }
+
+                        let feedback =
+                            verify::feedback_prompt(&combined_prompt, command, &outcome.output);
+                        let (_, fix_response) =
+                            query_models(config, &models, observer, &feedback, &files).await?;
+
+                        let fix = apply::apply_response(
+                            &fix_response,
+                            &files,
+                            &press_output_dir,
+                            in_place,
+                            self.review.as_mut(),
+                            &mut recorder,
+                            config.chunk_size,
+                            config.chunk_strategy,
+                        )
+                        .await?;
+                        for path in fix.saved_files {
+                            if !saved_files.contains(&path) && !new_files.contains(&path) {
+                                saved_files.push(path);
+                            }
+                        }
+                        for path in fix.new_files {
+                            if !new_files.contains(&path) {
+                                new_files.push(path);
+                            }
+                        }
+                        diffs.extend(fix.diffs);
+                        fix_responses.push(fix_response);
+                        fix_round_running = false;
+                    }
+                }
+                .await;
+
+                // `Ok` holds the output of the last failed verification
+                if let Some(failure) = verification.transpose() {
+                    let response = joined_responses(&code_assistant_response, &fix_responses);
+                    let run_id = recorder
+                        .finish(&apply::join_diffs(&diffs), &response)
+                        .await?;
+                    let error = match failure {
+                        Ok(output) => {
+                            observer.on_event(PipelineEvent::VerifyRollingBack {
+                                output: &output,
+                                run_id: &run_id,
+                            });
+                            AppError::VerificationFailed(format!(
+                                "`{}` still failed after {} fix round(s), the changes were rolled back",
+                                command, max_rounds
+                            ))
+                        }
+                        Err(e) if fix_round_running => {
+                            observer.on_event(PipelineEvent::FixRoundFailed {
+                                round,
+                                run_id: &run_id,
+                            });
+                            AppError::VerificationFailed(format!(
+                                "fix round {} failed, the changes were rolled back: {}",
+                                round, e
+                            ))
+                        }
+                        Err(e) => {
+                            observer.on_event(PipelineEvent::VerifyCommandFailed { run_id: &run_id });
+                            let reason = match e {
+                                AppError::VerificationFailed(reason) => reason,
+                                e => e.to_string(),
+                            };
+                            AppError::VerificationFailed(format!(
+                                "{}, the changes were rolled back",
+                                reason
+                            ))
+                        }
+                    };
+                    history::rollback_run(output_directory, Some(&run_id), true).await?;
+                    return Err(error);
+                }
+            }
+            let response = joined_responses(&code_assistant_response, &fix_responses);
+
+            let mut git_commit = None;
+            if let Some(git_run) = &git_run {
+                let changed_paths: Vec<PathBuf> =
+                    saved_files.iter().chain(&new_files).cloned().collect();
+                if changed_paths.is_empty() {
+                    observer.on_event(PipelineEvent::GitNothingToCommit);
+                } else {
+                    let commit = git::commit_on_branch(git_run, &changed_paths, &prompt, &response)?;
+                    recorder.set_git(GitRecord {
+                        branch: git_run.branch.clone(),
+                        base_commit: git_run.base_commit.clone(),
+                    });
+                    observer.on_event(PipelineEvent::GitCommitted {
+                        branch: &git_run.branch,
+                        commit: &commit,
+                    });
+                    git_commit = Some(GitCommit {
+                        branch: git_run.branch.clone(),
+                        commit,
+                    });
+                }
+            }
+
+            let run_id = recorder
+                .finish(&apply::join_diffs(&diffs), &response)
+                .await?;
+            observer.on_event(PipelineEvent::RunRecorded { run_id: &run_id });
+
+            Ok(PipelineOutcome {
+                preprocessor_response,
+                code_assistant_response,
+                fix_responses,
+                skipped_files,
+                saved_files,
+                new_files,
+                diffs,
+                git_commit,
+                rollback: Some(RollbackHandle {
+                    run_id,
+                    output_directory: output_directory.to_path_buf(),
+                }),
+            })
+        }
+        .await;
+        if let (Err(_), Some(git_run)) = (&outcome, &git_run) {
+            // Put back the files the run wrote first, so the stash pops onto a clean tree
+            if let Some(run_id) = &started_run {
+                let output_directory = Path::new(&config.output_directory);
+                if let Err(error) = history::rollback_unfinished_run(output_directory, run_id).await
+                {
+                    observer.on_event(PipelineEvent::GitRestoreFailed { error: &error });
+                }
+            }
+            match git::restore(git_run) {
+                Ok(checked_out) => {
+                    if checked_out || git_run.stashed {
+                        observer.on_event(PipelineEvent::GitRestored {
+                            checked_out: checked_out.then_some(git_run.start.as_str()),
+                            unstashed: git_run.stashed,
+                        })
+                    }
+                }
+                Err(error) => observer.on_event(PipelineEvent::GitRestoreFailed { error: &error }),
+            }
+        }
+        outcome
+    }
+}
+
+/// The explanation of the first response and of every fix round.
+fn joined_responses(first: &CodeAssistantResponse, fixes: &[CodeAssistantResponse]) -> String {
+    std::iter::once(first)
+        .chain(fixes)
+        .map(|response| response.response.as_str())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::errors::DeepSeekError;
+    use crate::api::provider::{CompletionOptions, ProgressFn};
+    use crate::models::code_assistant_response::{NewFile, SearchReplace, UpdatedFile};
+    use crate::models::{FileChunks, FilePartIds};
+    use crate::utils::config::DirtyTreePolicy;
+    use async_trait::async_trait;
+    use serde_json::Value;
+    use std::fs;
+    use std::process::Command;
+
+    /// The pipeline and git work in the current directory, which tests share.
+    static CURRENT_DIR: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    /// Selects the first part of the first file and greets louder in it.
+    struct GreetingProvider;
+
+    #[async_trait]
+    impl LlmProvider for GreetingProvider {
+        fn name(&self) -> &str {
+            "greeting"
+        }
+
+        async fn complete(
+            &self,
+            _messages: &[Value],
+            _options: CompletionOptions,
+            _on_progress: Option<&ProgressFn>,
+        ) -> Result<String, DeepSeekError> {
+            unreachable!("both stages are answered directly")
+        }
+
+        async fn call_preprocessor(
+            &self,
+            _user_system_prompt: &str,
+            _user_prompt: &str,
+            file_chunks: &[FileChunks],
+            _options: CompletionOptions,
+            _log_directory: Option<&str>,
+            _on_progress: Option<&ProgressFn>,
+        ) -> Result<PreprocessorResponse, DeepSeekError> {
+            Ok(PreprocessorResponse {
+                parts_to_edit: vec![FilePartIds {
+                    file_path: file_chunks[0].file_path.clone(),
+                    parts: vec![file_chunks[0].parts[0].part_id],
+                }],
+                preprocessor_prompt: String::new(),
+            })
+        }
+
+        async fn call_code_assistant(
+            &self,
+            _user_system_prompt: &str,
+            _user_prompt: &str,
+            _preprocessor_plan: &str,
+            _outline: &str,
+            file_chunks: &[FileChunks],
+            _edit_format: EditFormat,
+            _options: CompletionOptions,
+            _log_directory: Option<&str>,
+            _on_progress: Option<&ProgressFn>,
+        ) -> Result<CodeAssistantResponse, DeepSeekError> {
+            Ok(CodeAssistantResponse {
+                updated_files: vec![UpdatedFile {
+                    file_path: file_chunks[0].file_path.clone(),
+                    parts: Vec::new(),
+                    edits: vec![SearchReplace {
+                        search: "\"hi\"".to_string(),
+                        replace: "\"hello\"".to_string(),
+                    }],
+                }],
+                new_files: vec![NewFile {
+                    file_path: "NOTES.md".to_string(),
+                    content: "note\n".to_string(),
+                }],
+                response: "Greeted louder.".to_string(),
+            })
+        }
+    }
+
+    fn git(args: &[&str]) -> String {
+        let output = Command::new("git").args(args).output().unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    #[tokio::test]
+    async fn failed_git_commit_puts_back_the_files_before_popping_the_stash() {
+        let _current_dir = CURRENT_DIR.lock().await;
+        let dir = tempfile::tempdir().unwrap();
+        let previous_dir = std::env::current_dir().unwrap();
+        std::env::set_current_dir(dir.path()).unwrap();
+
+        git(&["init", "--quiet", "--initial-branch=main"]);
+        git(&["config", "user.email", "press@example.com"]);
+        git(&["config", "user.name", "press"]);
+        let original = "fn main() {\n    println!(\"hi\");\n}\n";
+        fs::write("main.rs", original).unwrap();
+        git(&["add", "main.rs"]);
+        git(&["commit", "--quiet", "-m", "initial"]);
+        // The run's edits touch the stashed file, so popping onto them would conflict
+        let uncommitted = format!("{}// uncommitted\n", original);
+        fs::write("main.rs", &uncommitted).unwrap();
+        // Rejects every commit once the run has written its files
+        fs::write(".git/hooks/pre-commit", "#!/bin/sh\nexit 1\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(".git/hooks/pre-commit", fs::Permissions::from_mode(0o755))
+                .unwrap();
+        }
+
+        let mut config = Config::default();
+        config.git.dirty = DirtyTreePolicy::Stash;
+        config.edit_format = EditFormat::SearchReplace;
+        let result = Pipeline::new(config)
+            .provider(Arc::new(GreetingProvider))
+            .paths(["main.rs"])
+            .prompt("greet louder")
+            .git(true)
+            .run(&mut ())
+            .await;
+
+        let main_rs = fs::read_to_string("main.rs");
+        let notes_exist = Path::new("NOTES.md").exists();
+        let branch = git(&["rev-parse", "--abbrev-ref", "HEAD"]);
+        let stashes = git(&["stash", "list"]);
+        let staged = git(&["diff", "--cached", "--name-only"]);
+        std::env::set_current_dir(previous_dir).unwrap();
+
+        assert!(matches!(result, Err(AppError::GitError(_))));
+        assert_eq!(main_rs.unwrap(), uncommitted);
+        assert!(!notes_exist);
+        assert_eq!(branch, "main");
+        assert_eq!(stashes, "");
+        assert_eq!(staged, "");
+    }
+}
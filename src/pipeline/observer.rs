@@ -0,0 +1,121 @@
+use crate::api::provider::ProgressFn;
+use crate::errors::AppError;
+use crate::file_processing::reader::SkippedFile;
+use crate::utils::config::Stage;
+
+/// Something that happened while a [`Pipeline`](super::Pipeline) ran, in the
+/// order it happened.
+#[derive(Debug)]
+pub enum PipelineEvent<'a> {
+    /// Uncommitted changes were stashed before a git mode run.
+    GitStashed,
+    /// The files to send were found.
+    FilesDiscovered {
+        count: usize,
+        skipped: &'a [SkippedFile],
+    },
+    /// The files were read and split into parts.
+    FilesCombined,
+    /// The models are about to be queried; `provider` names the provider of
+    /// each stage.
+    QueryStarted {
+        provider: &'a str,
+    },
+    PreprocessorStarted,
+    /// The files don't fit in one preprocessor call and are sent in batches.
+    PreprocessorBatches(usize),
+    /// The prompt of `stage` is estimated to exceed its context budget.
+    BudgetExceeded {
+        stage: Stage,
+        estimated: usize,
+        available: usize,
+    },
+    /// A request to the model of `stage` was sent and press is waiting for it.
+    WaitingForModel(Stage),
+    PreprocessorFinished,
+    CodeAssistantStarted,
+    CodeAssistantFinished,
+    SavingStarted,
+    /// The changes were written, to the original files when `in_place` is set.
+    SavingFinished {
+        in_place: bool,
+    },
+    VerifyStarted {
+        command: &'a str,
+    },
+    VerifyPassed {
+        fix_rounds: u32,
+    },
+    /// The verification command failed and fix round `round` is starting.
+    VerifyFailed {
+        output: &'a str,
+        round: u32,
+        max_rounds: u32,
+    },
+    /// The verification command failed after the last fix round.
+    VerifyRollingBack {
+        output: &'a str,
+        run_id: &'a str,
+    },
+    /// Fix round `round` failed (interrupted, API error, edit that doesn't
+    /// apply, ...) and the run is rolled back.
+    FixRoundFailed {
+        round: u32,
+        run_id: &'a str,
+    },
+    /// The verification command couldn't be run or was interrupted by
+    /// Ctrl-C, and the run is rolled back.
+    VerifyCommandFailed {
+        run_id: &'a str,
+    },
+    GitCommitted {
+        branch: &'a str,
+        commit: &'a str,
+    },
+    GitNothingToCommit,
+    /// A git mode run failed, and the branch it started on was checked out
+    /// again and/or the stashed changes were popped.
+    GitRestored {
+        checked_out: Option<&'a str>,
+        unstashed: bool,
+    },
+    /// A git mode run failed and the tree couldn't be put back.
+    GitRestoreFailed {
+        error: &'a AppError,
+    },
+    /// The run was recorded in the history.
+    RunRecorded {
+        run_id: &'a str,
+    },
+}
+
+/// Receives the progress of a pipeline, e.g. to show it in a terminal.
+/// `()` ignores everything.
+pub trait PipelineObserver: Send {
+    fn on_event(&mut self, event: PipelineEvent<'_>);
+
+    /// Returns a callback for the number of tokens streamed so far, asked for
+    /// right after each [`PipelineEvent::WaitingForModel`].
+    fn progress(&self) -> Option<Box<ProgressFn>> {
+        None
+    }
+}
+
+impl PipelineObserver for () {
+    fn on_event(&mut self, _event: PipelineEvent<'_>) {}
+}
+
+/// Decides which of the proposed changes are written, e.g. by asking the user.
+pub trait ChangeReview: Send {
+    /// Reviews the changes from `old` to `new` and returns the content to
+    /// write, or `None` to leave the file alone.
+    fn review_file(
+        &mut self,
+        file_path: &str,
+        old: &str,
+        new: &str,
+    ) -> Result<Option<String>, AppError>;
+
+    /// Returns whether the new file at `file_path` should be created.
+    fn review_new_file(&mut self, file_path: &str, content: &str) -> Result<bool, AppError>;
+}
@@ -0,0 +1,237 @@
+use super::interrupt::Interrupt;
+use super::observer::{PipelineEvent, PipelineObserver};
+use crate::api::budget;
+use crate::api::provider::{CompletionOptions, LlmProvider};
+use crate::api::retry::RetryPolicy;
+use crate::errors::AppError;
+use crate::file_processing::{outline, reader};
+use crate::models::code_assistant_response::CodeAssistantResponse;
+use crate::models::preprocessor_response::PreprocessorResponse;
+use crate::models::{FileChunks, FilePart};
+use crate::utils::config::{BudgetPolicy, Config, ResolvedStage, Stage};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The provider and settings of each stage, shared by every round of a run.
+pub(crate) struct Models<'a> {
+    pub preprocessor: Arc<dyn LlmProvider>,
+    pub preprocessor_stage: ResolvedStage,
+    pub code_assistant: Arc<dyn LlmProvider>,
+    pub code_assistant_stage: ResolvedStage,
+    /// Where the exchanges are logged, `None` for a dry run.
+    pub log_directory: Option<&'a str>,
+    /// Cancels the request in flight on Ctrl-C, when enabled.
+    pub interrupt: Interrupt,
+}
+
+/// Sends `prompt` with the current content of `files` through the
+/// preprocessor and then the code assistant.
+pub(crate) async fn query_models(
+    config: &Config,
+    models: &Models<'_>,
+    observer: &mut dyn PipelineObserver,
+    prompt: &str,
+    files: &[PathBuf],
+) -> Result<(PreprocessorResponse, CodeAssistantResponse), AppError> {
+    let output_file_text =
+        reader::combine_text_files(files.to_vec(), config.chunk_size, config.chunk_strategy)
+            .await?;
+    observer.on_event(PipelineEvent::FilesCombined);
+
+    let provider = if models.preprocessor.name() == models.code_assistant.name() {
+        models.preprocessor.name().to_string()
+    } else {
+        format!(
+            "{} & {}",
+            models.preprocessor.name(),
+            models.code_assistant.name()
+        )
+    };
+    observer.on_event(PipelineEvent::QueryStarted {
+        provider: &provider,
+    });
+
+    observer.on_event(PipelineEvent::PreprocessorStarted);
+
+    // Split the files into batches that each fit in the preprocessor's context budget
+    let available = budget::available_file_tokens(
+        Stage::Preprocessor,
+        config.edit_format,
+        &models.preprocessor_stage,
+        &config.system_prompt,
+        prompt,
+    );
+    let estimated = budget::estimate_file_tokens(&output_file_text);
+    let batches = budget::plan_batches(&output_file_text, available, config.budget_policy)?;
+    if batches.len() > 1 {
+        observer.on_event(PipelineEvent::PreprocessorBatches(batches.len()));
+    } else if estimated > available {
+        observer.on_event(PipelineEvent::BudgetExceeded {
+            stage: Stage::Preprocessor,
+            estimated,
+            available,
+        });
+    }
+
+    observer.on_event(PipelineEvent::WaitingForModel(Stage::Preprocessor));
+    let on_progress = observer.progress();
+
+    let retry_policy = RetryPolicy::new(models.preprocessor_stage.retries, &config.retry);
+    let mut responses = Vec::with_capacity(batches.len());
+    for batch in &batches {
+        let request = retry_policy.run("Preprocessor", || {
+            models.preprocessor.call_preprocessor(
+                &config.system_prompt,
+                prompt,
+                batch,
+                CompletionOptions {
+                    temperature: models.preprocessor_stage.temperature,
+                    max_tokens: models.preprocessor_stage.max_tokens,
+                    stream: config.stream,
+                    repair_attempts: config.repair_attempts,
+                },
+                models.log_directory,
+                on_progress.as_deref(),
+            )
+        });
+
+        responses.push(
+            models
+                .interrupt
+                .until(Stage::Preprocessor, request)
+                .await??,
+        );
+    }
+    let preprocessor_response = PreprocessorResponse::merge(responses);
+
+    log::debug!(
+        "Preprocessor Response - Parts to Edit: {:?}",
+        preprocessor_response.parts_to_edit
+    );
+    log::debug!(
+        "Preprocessor Response - Prompt: {}",
+        preprocessor_response.preprocessor_prompt
+    );
+
+    // Create a hashmap of parts to edit
+    let mut parts_to_edit_hashmap: HashMap<String, Vec<usize>> = HashMap::new();
+
+    for file in &preprocessor_response.parts_to_edit {
+        parts_to_edit_hashmap.insert(file.file_path.clone(), file.parts.clone());
+    }
+
+    // Use the parsed response to filter the preprocessed prompt
+    let filtered_prompt = filter_out_unused_parts(&output_file_text, &parts_to_edit_hashmap);
+
+    log::debug!("Filtered Preprocessed Prompt:\n{:?}", filtered_prompt);
+
+    // The code assistant also gets the preprocessor's reasoning and an outline
+    // of the parts it isn't sent, so its edits match the code around them
+    let preprocessor_plan = preprocessor_response.preprocessor_prompt.trim();
+    let mut outline = outline::outline_unselected_parts(&output_file_text, &parts_to_edit_hashmap);
+
+    observer.on_event(PipelineEvent::PreprocessorFinished);
+
+    observer.on_event(PipelineEvent::CodeAssistantStarted);
+
+    // The code assistant needs every selected part at once, so it can't be split
+    let available = budget::available_file_tokens(
+        Stage::CodeAssistant,
+        config.edit_format,
+        &models.code_assistant_stage,
+        &config.system_prompt,
+        prompt,
+    )
+    .saturating_sub(budget::estimate_tokens(preprocessor_plan));
+    let estimated = budget::estimate_file_tokens(&filtered_prompt);
+    // The outline is the first thing to go when the budget is tight
+    if estimated + budget::estimate_tokens(&outline) > available {
+        log::debug!("Leaving the outline out of the code assistant request to fit the budget");
+        outline.clear();
+    }
+    if estimated > available {
+        if config.budget_policy == BudgetPolicy::Fail {
+            return Err(budget::budget_exceeded(estimated, available));
+        }
+        observer.on_event(PipelineEvent::BudgetExceeded {
+            stage: Stage::CodeAssistant,
+            estimated,
+            available,
+        });
+    }
+
+    observer.on_event(PipelineEvent::WaitingForModel(Stage::CodeAssistant));
+    let on_progress = observer.progress();
+
+    // Get code assistant response from the provider
+    let retry_policy = RetryPolicy::new(models.code_assistant_stage.retries, &config.retry);
+    let request = retry_policy.run("Code Assistant", || {
+        models.code_assistant.call_code_assistant(
+            &config.system_prompt,
+            prompt,
+            preprocessor_plan,
+            &outline,
+            &filtered_prompt,
+            config.edit_format,
+            CompletionOptions {
+                temperature: models.code_assistant_stage.temperature,
+                max_tokens: models.code_assistant_stage.max_tokens,
+                stream: config.stream,
+                repair_attempts: config.repair_attempts,
+            },
+            models.log_directory,
+            on_progress.as_deref(),
+        )
+    });
+
+    let code_assistant_response = models
+        .interrupt
+        .until(Stage::CodeAssistant, request)
+        .await??;
+
+    observer.on_event(PipelineEvent::CodeAssistantFinished);
+
+    Ok((preprocessor_response, code_assistant_response))
+}
+
+///  Filters out parts of `FileChunks` that are not specified in `parts_to_edit_hashmap`.
+///
+///  Args:
+///     output_file_text: A vector of `FileChunks` containing file paths and their parts.
+///     parts_to_edit_hashmap: A hashmap where the key is the file path and the value is a vector of part IDs to keep.
+///
+///  Returns:
+///     A vector of `FileChunks` containing only the parts specified in `parts_to_edit_hashmap`.
+///
+fn filter_out_unused_parts(
+    output_file_text: &Vec<FileChunks>,
+    parts_to_edit_hashmap: &HashMap<String, Vec<usize>>,
+) -> Vec<FileChunks> {
+    let mut filtered_output_file_text: Vec<FileChunks> = Vec::new();
+
+    for file_chunk in output_file_text {
+        let file_path = &file_chunk.file_path;
+
+        // Check if the file path exists in the hashmap
+        if let Some(parts_to_edit) = parts_to_edit_hashmap.get(file_path) {
+            // Filter the parts to keep only those specified in parts_to_edit
+            let filtered_parts: Vec<FilePart> = file_chunk
+                .parts
+                .iter()
+                .filter(|part| parts_to_edit.contains(&part.part_id))
+                .cloned()
+                .collect();
+
+            // If there are parts to keep, add the file to the result
+            if !filtered_parts.is_empty() {
+                filtered_output_file_text.push(FileChunks {
+                    file_path: file_path.clone(),
+                    parts: filtered_parts,
+                });
+            }
+        }
+    }
+
+    filtered_output_file_text
+}
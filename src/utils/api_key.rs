@@ -0,0 +1,266 @@
+use crate::errors::AppError;
+use crate::utils::config::{user_config_path, Config};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Finds the API key, trying in order:
+///
+/// 1. `api_key`, which `--api-key`, `PRESS_API_KEY`, `DEEPSEEK_API_KEY` or a
+///    config file may set
+/// 2. the output of `api_key_command`
+/// 3. the file at `api_key_file`, or the key file written by
+///    `press model-config --set-api-key`
+///
+/// Returns `None` when none of them is set.
+pub fn resolve_api_key(config: &Config) -> Result<Option<String>, AppError> {
+    resolve_with_key_file(config, default_key_file())
+}
+
+/// [`resolve_api_key`] with `default_key_file` as the key file of the last resort.
+fn resolve_with_key_file(
+    config: &Config,
+    default_key_file: Option<PathBuf>,
+) -> Result<Option<String>, AppError> {
+    if let Some(api_key) = &config.api_key {
+        return Ok(Some(api_key.clone()));
+    }
+
+    if let Some(command) = &config.api_key_command {
+        return run_api_key_command(command).map(Some);
+    }
+
+    match &config.api_key_file {
+        Some(path) => read_key_file(Path::new(path)).map(Some),
+        None => match default_key_file.filter(|path| path.is_file()) {
+            Some(path) => read_key_file(&path).map(Some),
+            None => Ok(None),
+        },
+    }
+}
+
+/// `api_key` next to the user config, where `--set-api-key` stores the key.
+pub fn default_key_file() -> Option<PathBuf> {
+    Some(user_config_path()?.with_file_name("api_key"))
+}
+
+/// Writes `api_key` to the default key file, readable only by the current user.
+pub fn store_api_key(api_key: &str) -> Result<PathBuf, AppError> {
+    let path = default_key_file().ok_or_else(|| {
+        AppError::InvalidInput(
+            "Can't find the user config directory, set HOME or XDG_CONFIG_HOME".to_string(),
+        )
+    })?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // The mode only applies to new files, so an existing one is tightened too
+        if path.exists() {
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    let mut file = options.open(&path)?;
+    writeln!(file, "{}", api_key.trim())?;
+    Ok(path)
+}
+
+/// Runs `command` through the platform's shell and returns its trimmed stdout.
+fn run_api_key_command(command: &str) -> Result<String, AppError> {
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).output()
+    } else {
+        Command::new("sh").args(["-c", command]).output()
+    }
+    .map_err(|e| AppError::ApiKeyError(format!("could not run `{}`: {}", command, e)))?;
+
+    if !output.status.success() {
+        return Err(AppError::ApiKeyError(format!(
+            "`{}` failed ({}) {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let api_key = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if api_key.is_empty() {
+        return Err(AppError::ApiKeyError(format!(
+            "`{}` printed nothing",
+            command
+        )));
+    }
+    Ok(api_key)
+}
+
+/// Reads the key from `path`, refusing files other users can read.
+fn read_key_file(path: &Path) -> Result<String, AppError> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path)
+            .map_err(|e| AppError::ApiKeyError(format!("{}: {}", path.display(), e)))?
+            .permissions()
+            .mode();
+        if mode & 0o077 != 0 {
+            return Err(AppError::ApiKeyError(format!(
+                "{} can be read by other users (mode {:o}), run `chmod 600 {}`",
+                path.display(),
+                mode & 0o777,
+                path.display()
+            )));
+        }
+    }
+
+    let api_key = fs::read_to_string(path)
+        .map_err(|e| AppError::ApiKeyError(format!("{}: {}", path.display(), e)))?
+        .trim()
+        .to_string();
+    if api_key.is_empty() {
+        return Err(AppError::ApiKeyError(format!(
+            "{} is empty",
+            path.display()
+        )));
+    }
+    Ok(api_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a key file readable only by the current user, or with `mode`.
+    fn write_key(path: &Path, content: &str, mode: u32) {
+        fs::write(path, content).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+    }
+
+    fn resolve(config: &Config, default_key_file: &Path) -> Result<Option<String>, AppError> {
+        resolve_with_key_file(config, Some(default_key_file.to_path_buf()))
+    }
+
+    #[test]
+    fn sources_are_tried_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_file = dir.path().join("key");
+        let default_key_file = dir.path().join("api_key");
+        write_key(&key_file, "file-key\n", 0o600);
+        write_key(&default_key_file, "default-key\n", 0o600);
+
+        let mut config = Config {
+            api_key: Some("config-key".to_string()),
+            api_key_command: Some("echo command-key".to_string()),
+            api_key_file: Some(key_file.to_string_lossy().to_string()),
+            ..Config::default()
+        };
+        let mut expected = ["config-key", "command-key", "file-key", "default-key"].into_iter();
+        assert_eq!(
+            resolve(&config, &default_key_file).unwrap().as_deref(),
+            expected.next()
+        );
+        config.api_key = None;
+        assert_eq!(
+            resolve(&config, &default_key_file).unwrap().as_deref(),
+            expected.next()
+        );
+        config.api_key_command = None;
+        assert_eq!(
+            resolve(&config, &default_key_file).unwrap().as_deref(),
+            expected.next()
+        );
+        config.api_key_file = None;
+        assert_eq!(
+            resolve(&config, &default_key_file).unwrap().as_deref(),
+            expected.next()
+        );
+
+        fs::remove_file(&default_key_file).unwrap();
+        assert_eq!(resolve(&config, &default_key_file).unwrap(), None);
+    }
+
+    #[test]
+    fn a_failing_or_silent_command_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        // The key file isn't tried once a command is set
+        let default_key_file = dir.path().join("api_key");
+        write_key(&default_key_file, "default-key", 0o600);
+        for command in ["exit 3", "exit 0"] {
+            let config = Config {
+                api_key_command: Some(command.to_string()),
+                ..Config::default()
+            };
+            assert!(
+                matches!(
+                    resolve(&config, &default_key_file),
+                    Err(AppError::ApiKeyError(_))
+                ),
+                "{}",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn an_empty_or_missing_key_file_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_file = dir.path().join("key");
+        write_key(&key_file, "\n", 0o600);
+        let config = Config {
+            api_key_file: Some(key_file.to_string_lossy().to_string()),
+            ..Config::default()
+        };
+        assert!(matches!(
+            resolve(&config, &dir.path().join("api_key")),
+            Err(AppError::ApiKeyError(_))
+        ));
+
+        fs::remove_file(&key_file).unwrap();
+        assert!(matches!(
+            resolve(&config, &dir.path().join("api_key")),
+            Err(AppError::ApiKeyError(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn key_files_other_users_can_read_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_file = dir.path().join("key");
+        let config = Config {
+            api_key_file: Some(key_file.to_string_lossy().to_string()),
+            ..Config::default()
+        };
+        for mode in [0o640, 0o604, 0o644, 0o660] {
+            write_key(&key_file, "file-key", mode);
+            assert!(
+                matches!(
+                    resolve(&config, &dir.path().join("api_key")),
+                    Err(AppError::ApiKeyError(_))
+                ),
+                "{:o}",
+                mode
+            );
+        }
+
+        // The default key file is checked the same way
+        write_key(&key_file, "file-key", 0o644);
+        assert!(resolve(&Config::default(), &key_file).is_err());
+        write_key(&key_file, "file-key", 0o400);
+        assert_eq!(
+            resolve(&Config::default(), &key_file).unwrap().as_deref(),
+            Some("file-key")
+        );
+    }
+}
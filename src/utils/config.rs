@@ -1,25 +1,430 @@
 // src/config.rs
 
+use crate::api::client::Timeouts;
+use crate::api::config::DEFAULT_MAX_TOKENS;
 use crate::errors::AppError;
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::{env, fs};
+use toml::{Table, Value};
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     pub chunk_size: usize,
+    #[serde(default)]
+    pub chunk_strategy: ChunkStrategy,
+    #[serde(default)]
+    pub edit_format: EditFormat,
     pub api_key: Option<String>,
+    /// Command whose output is the API key, e.g. a password manager's CLI.
+    pub api_key_command: Option<String>,
+    /// File holding the API key, which other users must not be able to read.
+    pub api_key_file: Option<String>,
     pub log_level: String,
     pub output_directory: String,
     pub system_prompt: String,
     pub temperature: f32,
     pub retries: u32,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default = "default_repair_attempts")]
+    pub repair_attempts: u32,
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_context_budget")]
+    pub context_budget: usize,
+    #[serde(default)]
+    pub budget_policy: BudgetPolicy,
+    #[serde(default = "default_verify_iterations")]
+    pub verify_iterations: u32,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub provider: ProviderConfig,
+    #[serde(default)]
+    pub models: ModelsConfig,
+    #[serde(default)]
+    pub git: GitConfig,
+    #[serde(default)]
+    pub files: FilesConfig,
 }
 
-pub fn get_config_path() -> PathBuf {
-    let mut path = get_executable_dir();
-    path.push("config.toml");
-    path
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            chunk_size: 50,
+            chunk_strategy: ChunkStrategy::default(),
+            edit_format: EditFormat::default(),
+            api_key: None,
+            api_key_command: None,
+            api_key_file: None,
+            log_level: "off".to_string(),
+            output_directory: "./".to_string(),
+            system_prompt: "You are a helpful assistant".to_string(),
+            temperature: 0.0,
+            retries: 3,
+            stream: false,
+            retry: RetryConfig::default(),
+            repair_attempts: default_repair_attempts(),
+            connect_timeout_secs: default_connect_timeout_secs(),
+            timeout_secs: default_timeout_secs(),
+            context_budget: default_context_budget(),
+            budget_policy: BudgetPolicy::default(),
+            verify_iterations: default_verify_iterations(),
+            provider: ProviderConfig::default(),
+            models: ModelsConfig::default(),
+            git: GitConfig::default(),
+            files: FilesConfig::default(),
+        }
+    }
+}
+
+/// Backoff settings from the `[retry]` section of `config.toml`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RetryConfig {
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            base_delay_ms: 500,
+            max_delay_ms: 30_000,
+        }
+    }
+}
+
+/// Which files are sent to the model, from the `[files]` section of `config.toml`.
+/// Files are otherwise detected as text by their content.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct FilesConfig {
+    /// When not empty, only files with one of these extensions are processed.
+    #[serde(default)]
+    pub allow_extensions: Vec<String>,
+    /// Files with one of these extensions are never processed.
+    #[serde(default)]
+    pub deny_extensions: Vec<String>,
+}
+
+/// Git integration from the `[git]` section of `config.toml`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GitConfig {
+    /// Commit every run on a new branch.
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub dirty: DirtyTreePolicy,
+    #[serde(default = "default_branch_prefix")]
+    pub branch_prefix: String,
+}
+
+impl Default for GitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dirty: DirtyTreePolicy::default(),
+            branch_prefix: default_branch_prefix(),
+        }
+    }
+}
+
+/// What git mode does when the working tree has uncommitted changes.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum DirtyTreePolicy {
+    /// Stop before doing anything.
+    #[default]
+    #[serde(rename = "refuse")]
+    Refuse,
+    /// Stash the changes and carry on.
+    #[serde(rename = "stash")]
+    Stash,
+}
+
+impl std::str::FromStr for DirtyTreePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "refuse" => Ok(DirtyTreePolicy::Refuse),
+            "stash" => Ok(DirtyTreePolicy::Stash),
+            _ => Err(format!(
+                "unknown dirty tree policy '{}' (expected 'refuse' or 'stash')",
+                s
+            )),
+        }
+    }
+}
+
+/// Which LLM backend to talk to, from the `[provider]` section of `config.toml`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ProviderConfig {
+    #[serde(default)]
+    pub kind: ProviderKind,
+    pub base_url: Option<String>,
+    pub model: Option<String>,
+    pub auth_header: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ProviderKind {
+    #[default]
+    #[serde(rename = "deepseek")]
+    DeepSeek,
+    #[serde(rename = "openai-compatible")]
+    OpenAiCompatible,
+}
+
+impl std::str::FromStr for ProviderKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deepseek" => Ok(ProviderKind::DeepSeek),
+            "openai-compatible" => Ok(ProviderKind::OpenAiCompatible),
+            _ => Err(format!(
+                "unknown provider '{}' (expected 'deepseek' or 'openai-compatible')",
+                s
+            )),
+        }
+    }
+}
+
+/// How files are split into parts before being sent to the model.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ChunkStrategy {
+    /// Fixed windows of `chunk_size` lines.
+    #[default]
+    #[serde(rename = "lines")]
+    Lines,
+    /// Split on top-level items (functions, impls, classes), packed up to
+    /// `chunk_size` lines, falling back to blank-line boundaries.
+    #[serde(rename = "syntax")]
+    Syntax,
+}
+
+impl std::str::FromStr for ChunkStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lines" => Ok(ChunkStrategy::Lines),
+            "syntax" => Ok(ChunkStrategy::Syntax),
+            _ => Err(format!(
+                "unknown chunk strategy '{}' (expected 'lines' or 'syntax')",
+                s
+            )),
+        }
+    }
+}
+
+/// How the code assistant describes its edits.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum EditFormat {
+    /// Every changed part is sent back in full and replaces the original part.
+    #[default]
+    #[serde(rename = "parts")]
+    Parts,
+    /// Search/replace blocks, each applied only if its search text matches
+    /// the file exactly once.
+    #[serde(rename = "search-replace")]
+    SearchReplace,
+}
+
+impl std::str::FromStr for EditFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "parts" => Ok(EditFormat::Parts),
+            "search-replace" => Ok(EditFormat::SearchReplace),
+            _ => Err(format!(
+                "unknown edit format '{}' (expected 'parts' or 'search-replace')",
+                s
+            )),
+        }
+    }
+}
+
+/// What to do when the files don't fit in a stage's context budget.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum BudgetPolicy {
+    /// Send the prompt anyway and print a warning.
+    #[serde(rename = "warn")]
+    Warn,
+    /// Refuse to send the prompt.
+    #[serde(rename = "fail")]
+    Fail,
+    /// Split the preprocessor stage into several calls that each fit.
+    #[default]
+    #[serde(rename = "split")]
+    Split,
+}
+
+impl std::str::FromStr for BudgetPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(BudgetPolicy::Warn),
+            "fail" => Ok(BudgetPolicy::Fail),
+            "split" => Ok(BudgetPolicy::Split),
+            _ => Err(format!(
+                "unknown budget policy '{}' (expected 'warn', 'fail' or 'split')",
+                s
+            )),
+        }
+    }
+}
+
+/// Per-stage overrides from the `[models.preprocessor]` and
+/// `[models.code_assistant]` sections of `config.toml`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ModelsConfig {
+    #[serde(default)]
+    pub preprocessor: StageConfig,
+    #[serde(default)]
+    pub code_assistant: StageConfig,
+}
+
+/// Model settings for a single stage. Unset values fall back to the
+/// top-level `[provider]` section, `temperature`, `retries` and `context_budget`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct StageConfig {
+    pub provider: Option<ProviderKind>,
+    pub base_url: Option<String>,
+    pub model: Option<String>,
+    pub auth_header: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub retries: Option<u32>,
+    pub context_budget: Option<usize>,
+}
+
+/// The two stages of the press pipeline.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    Preprocessor,
+    CodeAssistant,
+}
+
+impl std::fmt::Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stage::Preprocessor => write!(f, "Preprocessor"),
+            Stage::CodeAssistant => write!(f, "Code Assistant"),
+        }
+    }
+}
+
+impl std::str::FromStr for Stage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "preprocessor" => Ok(Stage::Preprocessor),
+            "code-assistant" => Ok(Stage::CodeAssistant),
+            _ => Err(format!(
+                "unknown stage '{}' (expected 'preprocessor' or 'code-assistant')",
+                s
+            )),
+        }
+    }
+}
+
+/// Effective model settings for a stage after applying its overrides.
+pub struct ResolvedStage {
+    pub provider: ProviderConfig,
+    pub temperature: f32,
+    pub max_tokens: u32,
+    pub retries: u32,
+    pub context_budget: usize,
+}
+
+impl Config {
+    /// Connect and total request timeouts from the config.
+    pub fn timeouts(&self) -> Timeouts {
+        Timeouts {
+            connect: Duration::from_secs(self.connect_timeout_secs),
+            total: Duration::from_secs(self.timeout_secs),
+        }
+    }
+
+    /// Returns the `[models.*]` section for `stage`.
+    pub fn stage_config(&self, stage: Stage) -> &StageConfig {
+        match stage {
+            Stage::Preprocessor => &self.models.preprocessor,
+            Stage::CodeAssistant => &self.models.code_assistant,
+        }
+    }
+
+    /// Returns the `[models.*]` section for `stage`, for editing.
+    pub fn stage_config_mut(&mut self, stage: Stage) -> &mut StageConfig {
+        match stage {
+            Stage::Preprocessor => &mut self.models.preprocessor,
+            Stage::CodeAssistant => &mut self.models.code_assistant,
+        }
+    }
+
+    /// Merges the overrides of `stage` over the global provider settings.
+    ///
+    /// When a stage switches to a different provider kind, the global
+    /// base URL, model and auth header are not inherited.
+    pub fn resolve_stage(&self, stage: Stage) -> ResolvedStage {
+        let overrides = self.stage_config(stage);
+
+        let mut provider = match overrides.provider {
+            Some(kind) if kind != self.provider.kind => ProviderConfig {
+                kind,
+                ..ProviderConfig::default()
+            },
+            _ => self.provider.clone(),
+        };
+        if let Some(base_url) = &overrides.base_url {
+            provider.base_url = Some(base_url.clone());
+        }
+        if let Some(model) = &overrides.model {
+            provider.model = Some(model.clone());
+        }
+        if let Some(auth_header) = &overrides.auth_header {
+            provider.auth_header = Some(auth_header.clone());
+        }
+
+        ResolvedStage {
+            provider,
+            temperature: overrides.temperature.unwrap_or(self.temperature),
+            max_tokens: overrides.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            retries: overrides.retries.unwrap_or(self.retries),
+            context_budget: overrides.context_budget.unwrap_or(self.context_budget),
+        }
+    }
+}
+
+fn default_repair_attempts() -> u32 {
+    2
+}
+
+fn default_branch_prefix() -> String {
+    "press/".to_string()
+}
+
+fn default_verify_iterations() -> u32 {
+    3
+}
+
+fn default_context_budget() -> usize {
+    64_000
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_timeout_secs() -> u64 {
+    300
 }
 
 /// Validate config to prevent obviously wrong or missing values.
@@ -34,6 +439,44 @@ pub fn validate_config(config: &Config) -> Result<(), AppError> {
             "Temperature must be between 0.0 and 2.0".to_string(),
         ));
     }
+    if config.connect_timeout_secs == 0 || config.timeout_secs == 0 {
+        return Err(AppError::InvalidInput(
+            "Timeouts cannot be zero".to_string(),
+        ));
+    }
+    if config.context_budget == 0 {
+        return Err(AppError::InvalidInput(
+            "Context budget cannot be zero".to_string(),
+        ));
+    }
+    if config.retry.base_delay_ms > config.retry.max_delay_ms {
+        return Err(AppError::InvalidInput(
+            "Retry base delay cannot exceed the max delay".to_string(),
+        ));
+    }
+    for stage in [Stage::Preprocessor, Stage::CodeAssistant] {
+        let stage_config = config.stage_config(stage);
+        if let Some(temperature) = stage_config.temperature {
+            if !(0.0..=2.0).contains(&temperature) {
+                return Err(AppError::InvalidInput(format!(
+                    "{} temperature must be between 0.0 and 2.0",
+                    stage
+                )));
+            }
+        }
+        if stage_config.max_tokens == Some(0) {
+            return Err(AppError::InvalidInput(format!(
+                "{} max tokens cannot be zero",
+                stage
+            )));
+        }
+        if stage_config.context_budget == Some(0) {
+            return Err(AppError::InvalidInput(format!(
+                "{} context budget cannot be zero",
+                stage
+            )));
+        }
+    }
     if !Path::new(&config.output_directory).is_dir() {
         return Err(AppError::InvalidInput(format!(
             "Output directory does not exist: {}",
@@ -43,38 +486,551 @@ pub fn validate_config(config: &Config) -> Result<(), AppError> {
     Ok(())
 }
 
-/// Read config from file, and create a default config if none exists.
+/// Where a config value came from, lowest precedence first.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigSource {
+    Default,
+    /// `config.toml` next to the executable, where older versions kept it.
+    Legacy(PathBuf),
+    /// `~/.config/press/config.toml`, or `$XDG_CONFIG_HOME/press/config.toml`.
+    User(PathBuf),
+    /// The nearest `.press.toml` in the current directory or one of its parents.
+    Project(PathBuf),
+    /// A `PRESS_*` environment variable.
+    Env(String),
+    /// A flag of the current command.
+    Cli,
+}
+
+impl std::fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::Legacy(path) => write!(f, "legacy config {}", path.display()),
+            ConfigSource::User(path) => write!(f, "user config {}", path.display()),
+            ConfigSource::Project(path) => write!(f, "project config {}", path.display()),
+            ConfigSource::Env(var) => write!(f, "environment variable {}", var),
+            ConfigSource::Cli => write!(f, "command line"),
+        }
+    }
+}
+
+/// The type of a config value, used to parse it from environment variables and flags.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValueKind {
+    String,
+    Integer,
+    Float,
+    Bool,
+    /// A comma-separated list of strings.
+    List,
+}
+
+/// Every config key, with nested sections joined by dots.
+pub const CONFIG_KEYS: &[(&str, ValueKind)] = &[
+    ("chunk_size", ValueKind::Integer),
+    ("chunk_strategy", ValueKind::String),
+    ("edit_format", ValueKind::String),
+    ("api_key", ValueKind::String),
+    ("api_key_command", ValueKind::String),
+    ("api_key_file", ValueKind::String),
+    ("log_level", ValueKind::String),
+    ("output_directory", ValueKind::String),
+    ("system_prompt", ValueKind::String),
+    ("temperature", ValueKind::Float),
+    ("retries", ValueKind::Integer),
+    ("stream", ValueKind::Bool),
+    ("repair_attempts", ValueKind::Integer),
+    ("connect_timeout_secs", ValueKind::Integer),
+    ("timeout_secs", ValueKind::Integer),
+    ("context_budget", ValueKind::Integer),
+    ("budget_policy", ValueKind::String),
+    ("verify_iterations", ValueKind::Integer),
+    ("retry.base_delay_ms", ValueKind::Integer),
+    ("retry.max_delay_ms", ValueKind::Integer),
+    ("provider.kind", ValueKind::String),
+    ("provider.base_url", ValueKind::String),
+    ("provider.model", ValueKind::String),
+    ("provider.auth_header", ValueKind::String),
+    ("models.preprocessor.provider", ValueKind::String),
+    ("models.preprocessor.base_url", ValueKind::String),
+    ("models.preprocessor.model", ValueKind::String),
+    ("models.preprocessor.auth_header", ValueKind::String),
+    ("models.preprocessor.temperature", ValueKind::Float),
+    ("models.preprocessor.max_tokens", ValueKind::Integer),
+    ("models.preprocessor.retries", ValueKind::Integer),
+    ("models.preprocessor.context_budget", ValueKind::Integer),
+    ("models.code_assistant.provider", ValueKind::String),
+    ("models.code_assistant.base_url", ValueKind::String),
+    ("models.code_assistant.model", ValueKind::String),
+    ("models.code_assistant.auth_header", ValueKind::String),
+    ("models.code_assistant.temperature", ValueKind::Float),
+    ("models.code_assistant.max_tokens", ValueKind::Integer),
+    ("models.code_assistant.retries", ValueKind::Integer),
+    ("models.code_assistant.context_budget", ValueKind::Integer),
+    ("git.enabled", ValueKind::Bool),
+    ("git.dirty", ValueKind::String),
+    ("git.branch_prefix", ValueKind::String),
+    ("files.allow_extensions", ValueKind::List),
+    ("files.deny_extensions", ValueKind::List),
+];
+
+/// Parses `raw` as the value of `key`.
+pub fn parse_config_value(key: &str, raw: &str) -> Result<Value, AppError> {
+    let kind = CONFIG_KEYS
+        .iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, kind)| *kind)
+        .ok_or_else(|| AppError::InvalidInput(format!("Unknown config key '{}'", key)))?;
+    let invalid = |expected: &str| {
+        AppError::InvalidInput(format!(
+            "Invalid value '{}' for {}: expected {}",
+            raw, key, expected
+        ))
+    };
+
+    Ok(match kind {
+        ValueKind::String => Value::String(raw.to_string()),
+        ValueKind::Integer => {
+            Value::Integer(raw.trim().parse().map_err(|_| invalid("an integer"))?)
+        }
+        ValueKind::Float => Value::Float(raw.trim().parse().map_err(|_| invalid("a number"))?),
+        ValueKind::Bool => {
+            Value::Boolean(raw.trim().parse().map_err(|_| invalid("true or false"))?)
+        }
+        ValueKind::List => Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| Value::String(item.to_string()))
+                .collect(),
+        ),
+    })
+}
+
+/// Values set for a single command, the last layer of the config.
+#[derive(Default)]
+pub struct ConfigOverrides {
+    table: Table,
+}
+
+impl ConfigOverrides {
+    /// Sets `key` (e.g. `git.enabled`) to `value`.
+    pub fn set(&mut self, key: &str, value: impl Into<Value>) {
+        insert_key(&mut self.table, key, value.into());
+    }
+
+    /// The value set for `key`, if any.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        lookup_key(&self.table, key)
+    }
+}
+
+/// A value of the effective config and the layer it came from.
+pub struct ConfigEntry {
+    pub key: String,
+    /// The value in TOML syntax.
+    pub value: String,
+    pub source: ConfigSource,
+}
+
+/// The effective config and where each of its values came from.
+pub struct LoadedConfig {
+    pub config: Config,
+    pub entries: Vec<ConfigEntry>,
+}
+
+/// Loads the config by merging, from lowest to highest precedence: the
+/// built-in defaults, the legacy config next to the executable, the user
+/// config, the project's `.press.toml`, `PRESS_*` environment variables
+/// (e.g. `PRESS_CHUNK_SIZE`, `PRESS_GIT_ENABLED`) and `overrides`.
+pub fn load_config(overrides: &ConfigOverrides) -> Result<LoadedConfig, AppError> {
+    let mut layers = file_layers(true)?;
+    layers.extend(env_layers(|var| env::var(var).ok())?);
+    layers.push((ConfigSource::Cli, overrides.table.clone()));
+    resolve_layers(&layers)
+}
+
+/// Merges `layers`, lowest precedence first, into a validated config.
+fn resolve_layers(layers: &[(ConfigSource, Table)]) -> Result<LoadedConfig, AppError> {
+    let merged = merge_layers(layers);
+    let config = config_from_table(merged.clone())?;
+    validate_config(&config)?;
+
+    // Values are shown as written, and keys press doesn't know are left out
+    let mut effective = Vec::new();
+    flatten(&merged, "", &mut effective);
+    let entries = effective
+        .into_iter()
+        .filter(|(key, _)| CONFIG_KEYS.iter().any(|(name, _)| name == key))
+        .map(|(key, value)| {
+            let source = layers
+                .iter()
+                .rev()
+                .find(|(_, table)| lookup_key(table, &key).is_some())
+                .map_or(ConfigSource::Default, |(source, _)| source.clone());
+            // Never print the API key itself
+            let value = if key == "api_key" {
+                "\"********\"".to_string()
+            } else {
+                value.to_string()
+            };
+            ConfigEntry { key, value, source }
+        })
+        .collect();
+
+    Ok(LoadedConfig { config, entries })
+}
+
+/// Reads the effective config for the current directory.
 pub fn read_config() -> Result<Config, AppError> {
-    let config_path = get_config_path();
-    if !config_path.exists() {
-        // Create default config if it doesn't exist
-        let default_config = Config {
-            chunk_size: 50,
-            api_key: None,
-            log_level: "off".to_string(),
-            output_directory: "./".to_string(),
-            system_prompt: "You are a helpful assistant".to_string(),
-            temperature: 0.0,
-            retries: 3,
+    Ok(load_config(&ConfigOverrides::default())?.config)
+}
+
+/// Reads the user config on its own (over the defaults and the legacy
+/// config), which is what the `config` and `model-config` setters edit.
+/// It isn't validated, so a setter can still fix an invalid value.
+pub fn read_user_config() -> Result<Config, AppError> {
+    config_from_table(merge_layers(&file_layers(false)?))
+}
+
+/// Validates `config` and writes it to the user config. Only the values that
+/// differ from the defaults and the legacy config are kept, so the layers
+/// below still apply.
+pub fn write_config(config: &Config) -> Result<(), AppError> {
+    validate_config(config)?;
+    let config_path = user_config_path().ok_or_else(|| {
+        AppError::InvalidInput(
+            "Can't find the user config directory, set HOME or XDG_CONFIG_HOME".to_string(),
+        )
+    })?;
+
+    let base_layers: Vec<_> = file_layers(false)?
+        .into_iter()
+        .filter(|(source, _)| !matches!(source, ConfigSource::User(_)))
+        .collect();
+    let base = merge_layers(&base_layers);
+
+    let mut table = match Value::try_from(config).map_err(invalid_config)? {
+        Value::Table(table) => table,
+        _ => unreachable!("the config serializes to a table"),
+    };
+    strip_unchanged(&mut table, &base);
+
+    if let Some(parent) = config_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let config_str = toml::to_string(&table).map_err(invalid_config)?;
+    fs::write(config_path, config_str)?;
+    Ok(())
+}
+
+/// `~/.config/press/config.toml`, or `$XDG_CONFIG_HOME/press/config.toml`.
+pub fn user_config_path() -> Option<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?)
+            .join(".config"),
+    };
+    Some(config_dir.join("press").join("config.toml"))
+}
+
+/// The nearest `.press.toml` in the current directory or one of its parents.
+pub fn project_config_path() -> Option<PathBuf> {
+    let current_dir = env::current_dir().ok()?;
+    current_dir
+        .ancestors()
+        .map(|dir| dir.join(".press.toml"))
+        .find(|path| path.is_file())
+}
+
+/// `config.toml` next to the executable, read for compatibility with older versions.
+pub fn legacy_config_path() -> Option<PathBuf> {
+    Some(env::current_exe().ok()?.parent()?.join("config.toml"))
+}
+
+/// The environment variable that sets `key`, e.g. `PRESS_GIT_BRANCH_PREFIX`.
+pub fn env_var_name(key: &str) -> String {
+    format!("PRESS_{}", key.replace('.', "_").to_uppercase())
+}
+
+/// The defaults and every config file that exists, lowest precedence first.
+fn file_layers(include_project: bool) -> Result<Vec<(ConfigSource, Table)>, AppError> {
+    let mut layers = vec![(ConfigSource::Default, default_table()?)];
+
+    let mut files = Vec::new();
+    files.extend(legacy_config_path().map(ConfigSource::Legacy));
+    files.extend(user_config_path().map(ConfigSource::User));
+    if include_project {
+        files.extend(project_config_path().map(ConfigSource::Project));
+    }
+    for source in files {
+        let (ConfigSource::Legacy(path) | ConfigSource::User(path) | ConfigSource::Project(path)) =
+            &source
+        else {
+            continue;
         };
-        write_config(&default_config)?;
+        if !path.is_file() {
+            continue;
+        }
+        let config_str = fs::read_to_string(path)?;
+        let table: Table = toml::from_str(&config_str).map_err(|e| {
+            AppError::InvalidInput(format!("Invalid config file {}: {}", path.display(), e))
+        })?;
+        layers.push((source, table));
+    }
+    Ok(layers)
+}
+
+/// One layer per config variable `var` returns a value for.
+fn env_layers(
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<(ConfigSource, Table)>, AppError> {
+    let mut layers = Vec::new();
+    // DEEPSEEK_API_KEY is read too, below PRESS_API_KEY
+    if let Some(api_key) = var("DEEPSEEK_API_KEY") {
+        let mut table = Table::new();
+        table.insert("api_key".to_string(), Value::String(api_key));
+        layers.push((ConfigSource::Env("DEEPSEEK_API_KEY".to_string()), table));
+    }
+    for (key, _) in CONFIG_KEYS {
+        let name = env_var_name(key);
+        if let Some(raw) = var(&name) {
+            let mut table = Table::new();
+            insert_key(&mut table, key, parse_config_value(key, &raw)?);
+            layers.push((ConfigSource::Env(name), table));
+        }
+    }
+    Ok(layers)
+}
+
+fn default_table() -> Result<Table, AppError> {
+    match Value::try_from(Config::default()).map_err(invalid_config)? {
+        Value::Table(table) => Ok(table),
+        _ => unreachable!("the config serializes to a table"),
+    }
+}
+
+fn config_from_table(table: Table) -> Result<Config, AppError> {
+    Value::Table(table).try_into().map_err(invalid_config)
+}
+
+fn merge_layers(layers: &[(ConfigSource, Table)]) -> Table {
+    let mut merged = Table::new();
+    for (_, table) in layers {
+        merge_tables(&mut merged, table);
+    }
+    merged
+}
+
+/// Copies every value of `layer` into `base`, merging nested tables.
+fn merge_tables(base: &mut Table, layer: &Table) {
+    for (key, value) in layer {
+        match (base.get_mut(key), value) {
+            (Some(Value::Table(base_table)), Value::Table(layer_table)) => {
+                merge_tables(base_table, layer_table)
+            }
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Removes the values of `table` that are the same in `base`, and the tables left empty.
+fn strip_unchanged(table: &mut Table, base: &Table) {
+    table.retain(|key, value| match (value, base.get(key)) {
+        (Value::Table(table), Some(Value::Table(base))) => {
+            strip_unchanged(table, base);
+            !table.is_empty()
+        }
+        (value, Some(base_value)) => value != base_value,
+        (_, None) => true,
+    });
+}
+
+fn insert_key(table: &mut Table, key: &str, value: Value) {
+    match key.split_once('.') {
+        Some((section, rest)) => {
+            let entry = table
+                .entry(section.to_string())
+                .or_insert_with(|| Value::Table(Table::new()));
+            if !entry.is_table() {
+                *entry = Value::Table(Table::new());
+            }
+            if let Value::Table(section) = entry {
+                insert_key(section, rest, value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+fn lookup_key<'a>(table: &'a Table, key: &str) -> Option<&'a Value> {
+    match key.split_once('.') {
+        Some((section, rest)) => lookup_key(table.get(section)?.as_table()?, rest),
+        None => table.get(key),
+    }
+}
+
+/// Lists every value of `table` that isn't a table under its dotted key.
+fn flatten<'a>(table: &'a Table, prefix: &str, out: &mut Vec<(String, &'a Value)>) {
+    for (key, value) in table {
+        let key = format!("{}{}", prefix, key);
+        match value {
+            Value::Table(section) => flatten(section, &format!("{}.", key), out),
+            value => out.push((key, value)),
+        }
     }
-    let config_str = fs::read_to_string(config_path)?;
-    let config: Config = toml::from_str(&config_str)?;
-    validate_config(&config)?;
-    Ok(config)
 }
 
-pub fn write_config(config: &Config) -> std::io::Result<()> {
-    let config_path = get_config_path();
-    let config_str = toml::to_string(config).expect("Failed to serialize config");
-    fs::write(config_path, config_str)
+fn invalid_config(error: impl std::fmt::Display) -> AppError {
+    AppError::InvalidInput(format!("Invalid config: {}", error))
 }
 
-fn get_executable_dir() -> PathBuf {
-    env::current_exe()
-        .expect("Failed to get the executable path")
-        .parent()
-        .expect("Failed to get the executable directory")
-        .to_path_buf()
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn layer(source: ConfigSource, toml: &str) -> (ConfigSource, Table) {
+        (source, toml::from_str(toml).unwrap())
+    }
+
+    /// One layer of every kind, each overriding `chunk_size` and the key
+    /// named after it, so the result shows which layer won where.
+    fn every_layer() -> Vec<(ConfigSource, Table)> {
+        vec![
+            (ConfigSource::Default, default_table().unwrap()),
+            layer(
+                ConfigSource::Legacy(PathBuf::from("config.toml")),
+                "chunk_size = 1\nretries = 1\nsystem_prompt = \"legacy\"",
+            ),
+            layer(
+                ConfigSource::User(PathBuf::from("user.toml")),
+                "chunk_size = 2\nretries = 2\n[git]\nenabled = true",
+            ),
+            layer(
+                ConfigSource::Project(PathBuf::from(".press.toml")),
+                "chunk_size = 3\n[git]\nbranch_prefix = \"project/\"",
+            ),
+            layer(
+                ConfigSource::Env("PRESS_CHUNK_SIZE".to_string()),
+                "chunk_size = 4",
+            ),
+            layer(ConfigSource::Cli, "chunk_size = 5"),
+        ]
+    }
+
+    fn source_of<'a>(loaded: &'a LoadedConfig, key: &str) -> &'a ConfigSource {
+        &loaded
+            .entries
+            .iter()
+            .find(|entry| entry.key == key)
+            .unwrap()
+            .source
+    }
+
+    #[test]
+    fn later_layers_take_precedence() {
+        let mut layers = every_layer();
+        let loaded = resolve_layers(&layers).unwrap();
+        assert_eq!(loaded.config.chunk_size, 5);
+        assert_eq!(source_of(&loaded, "chunk_size"), &ConfigSource::Cli);
+
+        // Each layer wins once the ones above it are gone
+        for expected in (1..=4).rev() {
+            layers.pop();
+            let loaded = resolve_layers(&layers).unwrap();
+            assert_eq!(loaded.config.chunk_size, expected);
+            assert_eq!(source_of(&loaded, "chunk_size"), &layers.last().unwrap().0);
+        }
+        layers.pop();
+        assert_eq!(
+            resolve_layers(&layers).unwrap().config.chunk_size,
+            Config::default().chunk_size
+        );
+    }
+
+    #[test]
+    fn untouched_keys_keep_the_value_of_lower_layers() {
+        let loaded = resolve_layers(&every_layer()).unwrap();
+        assert_eq!(loaded.config.retries, 2);
+        assert!(matches!(
+            source_of(&loaded, "retries"),
+            ConfigSource::User(_)
+        ));
+        assert_eq!(loaded.config.system_prompt, "legacy");
+        assert!(matches!(
+            source_of(&loaded, "system_prompt"),
+            ConfigSource::Legacy(_)
+        ));
+        assert_eq!(loaded.config.temperature, Config::default().temperature);
+        assert_eq!(source_of(&loaded, "temperature"), &ConfigSource::Default);
+
+        // Sections are merged key by key
+        assert!(loaded.config.git.enabled);
+        assert_eq!(loaded.config.git.branch_prefix, "project/");
+        assert!(matches!(
+            source_of(&loaded, "git.branch_prefix"),
+            ConfigSource::Project(_)
+        ));
+    }
+
+    #[test]
+    fn env_variables_are_parsed_by_key_type() {
+        let vars = HashMap::from([
+            ("PRESS_CHUNK_SIZE", "42"),
+            ("PRESS_GIT_ENABLED", "true"),
+            ("PRESS_FILES_DENY_EXTENSIONS", "lock, min.js,"),
+            ("PRESS_API_KEY", "press-key"),
+            ("DEEPSEEK_API_KEY", "deepseek-key"),
+        ]);
+        let mut layers = vec![(ConfigSource::Default, default_table().unwrap())];
+        layers.extend(env_layers(|var| vars.get(var).map(|value| value.to_string())).unwrap());
+        let loaded = resolve_layers(&layers).unwrap();
+
+        assert_eq!(loaded.config.chunk_size, 42);
+        assert!(loaded.config.git.enabled);
+        assert_eq!(loaded.config.files.deny_extensions, ["lock", "min.js"]);
+        // PRESS_API_KEY wins over DEEPSEEK_API_KEY
+        assert_eq!(loaded.config.api_key.as_deref(), Some("press-key"));
+        assert_eq!(
+            source_of(&loaded, "api_key"),
+            &ConfigSource::Env("PRESS_API_KEY".to_string())
+        );
+
+        let invalid = env_layers(|var| (var == "PRESS_CHUNK_SIZE").then(|| "many".to_string()));
+        assert!(matches!(invalid, Err(AppError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn invalid_values_are_refused_whichever_layer_sets_them() {
+        let mut layers = every_layer();
+        layers.push(layer(ConfigSource::Cli, "temperature = 3.0"));
+        assert!(matches!(
+            resolve_layers(&layers),
+            Err(AppError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn an_invalid_user_config_can_still_be_fixed_by_a_setter() {
+        let layers = vec![
+            (ConfigSource::Default, default_table().unwrap()),
+            layer(
+                ConfigSource::User(PathBuf::from("user.toml")),
+                "chunk_size = 0",
+            ),
+        ];
+        assert!(resolve_layers(&layers).is_err());
+
+        // What `read_user_config` hands to the setters, before `write_config` validates
+        let mut config = config_from_table(merge_layers(&layers)).unwrap();
+        assert!(validate_config(&config).is_err());
+        config.chunk_size = 100;
+        validate_config(&config).unwrap();
+    }
+}
@@ -1,78 +1,230 @@
 // src/console_capture.rs
 
-use winapi::shared::minwindef::{DWORD, FALSE};
-use winapi::shared::ntdef::NULL;
-use winapi::um::processenv::GetStdHandle;
-use winapi::um::winbase::STD_OUTPUT_HANDLE;
-use winapi::um::wincon::{
-    GetConsoleScreenBufferInfo, ReadConsoleOutputCharacterW, CONSOLE_SCREEN_BUFFER_INFO,
-};
-use winapi::um::winnt::HANDLE;
-
-/// Converts a slice of wide characters (UTF-16) to a Rust `String`.
-fn wide_to_string(wide: &[u16]) -> String {
-    String::from_utf16_lossy(wide)
+use crate::errors::AppError;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+/// Output of the last command run through `press run`.
+#[derive(Serialize, Deserialize)]
+pub struct CapturedOutput {
+    pub command: String,
+    pub exit_code: Option<i32>,
+    pub output: String,
 }
 
-/// Captures the last few lines of the Windows console output.
-///
-/// This function reads the last `lines_to_capture` lines from the console's screen buffer.
-/// It is tailored for Windows systems.
-///
-/// # Returns
-///
-/// A `String` containing the captured console output.
-pub fn get_last_console_output(num_to_capture: usize) -> String {
-    unsafe {
-        // Get the handle to the standard output
-        let handle: HANDLE = GetStdHandle(STD_OUTPUT_HANDLE);
-        if handle == NULL {
-            log::error!("Failed to get standard output handle.");
-            return String::new();
-        }
+/// Runs `command`, showing its output as usual while keeping its last
+/// `lines_to_keep` lines for `--pipe-output`. Returns the command's exit code.
+pub fn run_and_capture(
+    command: &[String],
+    lines_to_keep: usize,
+    output_directory: &Path,
+) -> Result<Option<i32>, AppError> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| AppError::InvalidInput("No command to run".to_string()))?;
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| AppError::InvalidInput(format!("Could not run '{}': {}", program, e)))?;
+
+    // Both streams are echoed as they come and collected in arrival order
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let stdout = child.stdout.take().map(|stdout| {
+        let lines = Arc::clone(&lines);
+        std::thread::spawn(move || echo_lines(stdout, &lines, std::io::stdout()))
+    });
+    let stderr = child.stderr.take().map(|stderr| {
+        let lines = Arc::clone(&lines);
+        std::thread::spawn(move || echo_lines(stderr, &lines, std::io::stderr()))
+    });
+    for reader in [stdout, stderr].into_iter().flatten() {
+        let _ = reader.join();
+    }
+    let status = child.wait()?;
+
+    let lines = lines.lock().map(|lines| lines.clone()).unwrap_or_default();
+    let captured = CapturedOutput {
+        command: command.join(" "),
+        exit_code: status.code(),
+        output: lines[lines.len().saturating_sub(lines_to_keep)..].join("\n"),
+    };
+    let captured_str =
+        toml::to_string(&captured).map_err(|e| AppError::InvalidInput(e.to_string()))?;
+    let capture_path = capture_path(output_directory);
+    if let Some(parent) = capture_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(capture_path, captured_str)?;
 
-        // Retrieve console screen buffer info
-        let mut csbi: CONSOLE_SCREEN_BUFFER_INFO = std::mem::zeroed();
-        if GetConsoleScreenBufferInfo(handle, &mut csbi) == FALSE {
-            log::error!("Failed to get console screen buffer info.");
-            return String::new();
+    Ok(status.code())
+}
+
+fn echo_lines(stream: impl Read, lines: &Mutex<Vec<String>>, mut echo: impl Write) {
+    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+        let _ = writeln!(echo, "{}", line);
+        if let Ok(mut lines) = lines.lock() {
+            lines.push(line);
         }
+    }
+}
+
+/// Returns the last `num_to_capture` lines of console output, labelled for the prompt.
+///
+/// The output captured by the last `press run` is used when there is one.
+/// Otherwise, on Windows, the lines are read from the console's screen buffer;
+/// other terminals can't be read back.
+pub fn get_last_console_output(
+    num_to_capture: usize,
+    output_directory: &Path,
+) -> Result<String, AppError> {
+    if let Ok(captured_str) = std::fs::read_to_string(capture_path(output_directory)) {
+        let captured: CapturedOutput =
+            toml::from_str(&captured_str).map_err(|e| AppError::InvalidInput(e.to_string()))?;
+        let lines: Vec<&str> = captured.output.lines().collect();
+        let exit_code = captured
+            .exit_code
+            .map_or("killed by a signal".to_string(), |code| {
+                format!("exit code {}", code)
+            });
+        return Ok(format!(
+            "Output of `{}` ({}):\n{}",
+            captured.command,
+            exit_code,
+            lines[lines.len().saturating_sub(num_to_capture)..].join("\n")
+        ));
+    }
+
+    #[cfg(windows)]
+    {
+        Ok(format!(
+            "Console output:\n{}",
+            windows::get_last_console_output(num_to_capture)
+        ))
+    }
+    #[cfg(not(windows))]
+    Err(AppError::InvalidInput(
+        "--pipe-output needs a command run with `press run -- <command>` first \
+         (or pipe the output instead: `<command> 2>&1 | press --stdin ...`)"
+            .to_string(),
+    ))
+}
+
+/// Deletes the output captured by the last `press run` once a run has written
+/// changes based on it, so later runs don't send it again and, on Windows,
+/// read the live console instead.
+pub fn consume_console_capture(output_directory: &Path) -> Result<(), AppError> {
+    match std::fs::remove_file(capture_path(output_directory)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Reads everything piped into press, for `--stdin`. Fails when stdin is a
+/// terminal or nothing was piped.
+pub fn read_piped_stdin() -> Result<String, AppError> {
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        return Err(AppError::InvalidInput(
+            "--stdin needs input piped into press, e.g. `cargo build 2>&1 | press --stdin ...`"
+                .to_string(),
+        ));
+    }
+    let mut input = String::new();
+    stdin.lock().read_to_string(&mut input)?;
+    if input.trim().is_empty() {
+        return Err(AppError::InvalidInput(
+            "--stdin was given but nothing was piped into press".to_string(),
+        ));
+    }
+    Ok(input)
+}
 
-        let buffer_width = csbi.dwSize.X as usize;
-        let buffer_height = csbi.dwSize.Y as usize;
+fn capture_path(output_directory: &Path) -> PathBuf {
+    output_directory.join("press.output").join("last_run.toml")
+}
 
-        // Define how many lines you want to capture
-        let lines_to_capture = num_to_capture.min(buffer_height); // Capture last 10 lines or less
+#[cfg(windows)]
+mod windows {
+    use winapi::shared::minwindef::{DWORD, FALSE};
+    use winapi::shared::ntdef::NULL;
+    use winapi::um::processenv::GetStdHandle;
+    use winapi::um::winbase::STD_OUTPUT_HANDLE;
+    use winapi::um::wincon::{
+        GetConsoleScreenBufferInfo, ReadConsoleOutputCharacterW, CONSOLE_SCREEN_BUFFER_INFO,
+    };
+    use winapi::um::winnt::HANDLE;
 
-        let mut output = String::new();
+    /// Converts a slice of wide characters (UTF-16) to a Rust `String`.
+    fn wide_to_string(wide: &[u16]) -> String {
+        String::from_utf16_lossy(wide)
+    }
 
-        for i in 0..lines_to_capture {
-            let y = csbi.dwCursorPosition.Y.saturating_sub(i as i16 + 1);
-            if y < 0 {
-                break;
+    /// Captures the last few lines of the Windows console output.
+    ///
+    /// This function reads the last `lines_to_capture` lines from the console's screen buffer.
+    /// It is tailored for Windows systems.
+    ///
+    /// # Returns
+    ///
+    /// A `String` containing the captured console output.
+    pub fn get_last_console_output(num_to_capture: usize) -> String {
+        unsafe {
+            // Get the handle to the standard output
+            let handle: HANDLE = GetStdHandle(STD_OUTPUT_HANDLE);
+            if handle == NULL {
+                log::error!("Failed to get standard output handle.");
+                return String::new();
             }
 
-            let mut buffer: Vec<u16> = vec![0; buffer_width];
-            let mut chars_read: DWORD = 0;
-
-            // Read a single line from the console buffer
-            if ReadConsoleOutputCharacterW(
-                handle,
-                buffer.as_mut_ptr(),
-                buffer_width as DWORD,
-                winapi::um::wincon::COORD { X: 0, Y: y },
-                &mut chars_read,
-            ) == FALSE
-            {
-                log::error!("Failed to read console output.");
-                continue;
+            // Retrieve console screen buffer info
+            let mut csbi: CONSOLE_SCREEN_BUFFER_INFO = std::mem::zeroed();
+            if GetConsoleScreenBufferInfo(handle, &mut csbi) == FALSE {
+                log::error!("Failed to get console screen buffer info.");
+                return String::new();
             }
 
-            // Convert wide characters to String
-            let line = wide_to_string(&buffer[..chars_read as usize]);
-            output = format!("{}\n{}", line, output);
-        }
+            let buffer_width = csbi.dwSize.X as usize;
+            let buffer_height = csbi.dwSize.Y as usize;
+
+            // Define how many lines you want to capture
+            let lines_to_capture = num_to_capture.min(buffer_height); // Capture last 10 lines or less
+
+            let mut output = String::new();
+
+            for i in 0..lines_to_capture {
+                let y = csbi.dwCursorPosition.Y.saturating_sub(i as i16 + 1);
+                if y < 0 {
+                    break;
+                }
+
+                let mut buffer: Vec<u16> = vec![0; buffer_width];
+                let mut chars_read: DWORD = 0;
 
-        output.trim_start().to_string()
+                // Read a single line from the console buffer
+                if ReadConsoleOutputCharacterW(
+                    handle,
+                    buffer.as_mut_ptr(),
+                    buffer_width as DWORD,
+                    winapi::um::wincon::COORD { X: 0, Y: y },
+                    &mut chars_read,
+                ) == FALSE
+                {
+                    log::error!("Failed to read console output.");
+                    continue;
+                }
+
+                // Convert wide characters to String
+                let line = wide_to_string(&buffer[..chars_read as usize]);
+                output = format!("{}\n{}", line, output);
+            }
+
+            output.trim_start().to_string()
+        }
     }
 }
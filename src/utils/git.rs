@@ -0,0 +1,209 @@
+use crate::errors::AppError;
+use crate::utils::config::{DirtyTreePolicy, GitConfig};
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Longest slug taken from the prompt when naming a branch.
+const MAX_SLUG_LENGTH: usize = 48;
+
+/// Longest commit subject before it gets truncated.
+const MAX_SUBJECT_LENGTH: usize = 72;
+
+/// State recorded before a git mode run touches the working tree.
+pub struct GitRun {
+    /// Commit checked out before the run, which `press rollback` resets to.
+    pub base_commit: String,
+    /// Branch checked out before the run, or the commit if HEAD was detached.
+    pub start: String,
+    /// Branch the changes will be committed on.
+    pub branch: String,
+    /// Whether uncommitted changes were stashed to get a clean tree.
+    pub stashed: bool,
+}
+
+/// Checks that the working tree can be used for a git mode run, stashing
+/// uncommitted changes if `git_config` allows it, and picks the branch name.
+///
+/// Untracked files don't count as uncommitted changes, so press's own
+/// output directory never makes the tree dirty.
+pub fn prepare(git_config: &GitConfig, prompt: &str) -> Result<GitRun, AppError> {
+    git(&["rev-parse", "--show-toplevel"]).map_err(|_| {
+        AppError::GitError("git mode needs to run inside a git repository".to_string())
+    })?;
+
+    let dirty = !uncommitted_changes()?.is_empty();
+    let stashed = match (dirty, git_config.dirty) {
+        (false, _) => false,
+        (true, DirtyTreePolicy::Refuse) => {
+            return Err(AppError::GitError(
+                "the working tree has uncommitted changes; commit or stash them first, \
+                 or set `dirty = \"stash\"` in the [git] section"
+                    .to_string(),
+            ))
+        }
+        (true, DirtyTreePolicy::Stash) => {
+            git(&[
+                "stash",
+                "push",
+                "-m",
+                &format!("press: before {}", subject(prompt)),
+            ])?;
+            true
+        }
+    };
+
+    let base_commit = git(&["rev-parse", "HEAD"])?;
+    let start = match git(&["rev-parse", "--abbrev-ref", "HEAD"])? {
+        head if head == "HEAD" => base_commit.clone(),
+        branch => branch,
+    };
+    Ok(GitRun {
+        base_commit,
+        start,
+        branch: unique_branch_name(&git_config.branch_prefix, prompt),
+        stashed,
+    })
+}
+
+/// Undoes [`prepare`] after a failed run: checks out the starting branch
+/// again if the run's branch was created, and pops the stash if one was made.
+///
+/// A commit that failed leaves the run's changes staged, so the index is
+/// reset first. Changes the run left in the working tree stay there: the
+/// caller puts the files back before, otherwise the pop fails if they touch
+/// the stashed files and the stash is kept. Returns whether the starting
+/// branch had to be checked out.
+pub fn restore(run: &GitRun) -> Result<bool, AppError> {
+    let on_run_branch = git(&["rev-parse", "--abbrev-ref", "HEAD"])? == run.branch;
+    if on_run_branch {
+        git(&["reset", "--quiet"])?;
+        git(&["checkout", &run.start])?;
+    }
+    if run.stashed {
+        git(&["stash", "pop"]).map_err(|e| {
+            AppError::GitError(format!(
+                "{}; the changes from before the run are still stashed, \
+                 restore them with 'git stash pop'",
+                e
+            ))
+        })?;
+    }
+    Ok(on_run_branch)
+}
+
+/// The tracked files with uncommitted changes, as `git status --porcelain`
+/// lines. Untracked files aren't listed.
+pub fn uncommitted_changes() -> Result<Vec<String>, AppError> {
+    Ok(git(&["status", "--porcelain", "--untracked-files=no"])?
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+/// Switches to a new branch carrying the current changes, then stages
+/// `paths` and commits them with the prompt as the subject and the model's
+/// `response` as the body. Returns the new commit's short hash.
+pub fn commit_on_branch(
+    run: &GitRun,
+    paths: &[PathBuf],
+    prompt: &str,
+    response: &str,
+) -> Result<String, AppError> {
+    git(&["checkout", "-b", &run.branch])?;
+
+    let mut add = vec!["add".to_string(), "--".to_string()];
+    add.extend(paths.iter().map(|path| path.to_string_lossy().to_string()));
+    git(&add.iter().map(String::as_str).collect::<Vec<_>>())?;
+
+    let subject = format!("press: {}", subject(prompt));
+    let mut commit = vec!["commit", "-m", subject.as_str()];
+    if !response.trim().is_empty() {
+        commit.extend(["-m", response.trim()]);
+    }
+    git(&commit)?;
+
+    git(&["rev-parse", "--short", "HEAD"])
+}
+
+/// Resets `branch` to `base_commit`, discarding the commit made by a git mode run.
+/// Refuses to touch any other branch in case the user has moved on. The reset
+/// also discards uncommitted changes, so callers check [`uncommitted_changes`] first.
+pub fn reset_to(branch: &str, base_commit: &str) -> Result<(), AppError> {
+    let current = git(&["rev-parse", "--abbrev-ref", "HEAD"])?;
+    if current != branch {
+        return Err(AppError::RollbackError(format!(
+            "the last run committed on '{}' but '{}' is checked out",
+            branch, current
+        )));
+    }
+    git(&["reset", "--hard", base_commit])?;
+    Ok(())
+}
+
+/// Builds a branch name from the prompt that doesn't exist yet, adding a
+/// numeric suffix when needed.
+fn unique_branch_name(prefix: &str, prompt: &str) -> String {
+    let mut slug = String::new();
+    for c in prompt.to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= MAX_SLUG_LENGTH {
+            break;
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    let base = format!(
+        "{}{}",
+        prefix,
+        if slug.is_empty() { "changes" } else { slug }
+    );
+
+    let mut name = base.clone();
+    let mut suffix = 2;
+    while branch_exists(&name) {
+        name = format!("{}-{}", base, suffix);
+        suffix += 1;
+    }
+    name
+}
+
+fn branch_exists(name: &str) -> bool {
+    git(&[
+        "rev-parse",
+        "--verify",
+        "--quiet",
+        &format!("refs/heads/{}", name),
+    ])
+    .is_ok()
+}
+
+/// First line of the prompt, truncated to fit a commit subject.
+fn subject(prompt: &str) -> String {
+    let line = prompt.lines().next().unwrap_or_default().trim();
+    if line.chars().count() <= MAX_SUBJECT_LENGTH {
+        return line.to_string();
+    }
+    let truncated: String = line.chars().take(MAX_SUBJECT_LENGTH - 3).collect();
+    format!("{}...", truncated.trim_end())
+}
+
+/// Runs git with `args` and returns its trimmed stdout.
+fn git(args: &[&str]) -> Result<String, AppError> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|e| AppError::GitError(format!("failed to run git: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(AppError::GitError(format!(
+            "`git {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -1,3 +1,7 @@
+pub mod api_key;
 pub mod config;
 pub mod console_capture;
+pub mod git;
 pub mod logger;
+pub mod recipe;
+pub mod verify;
@@ -0,0 +1,443 @@
+use crate::errors::AppError;
+use crate::utils::config::user_config_path;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{env, fmt, fs};
+
+/// Where a recipe was found. Project recipes shadow user recipes of the same name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecipeScope {
+    Project,
+    User,
+}
+
+impl fmt::Display for RecipeScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecipeScope::Project => write!(f, "project"),
+            RecipeScope::User => write!(f, "user"),
+        }
+    }
+}
+
+/// A named prompt template with the defaults of its runs.
+///
+/// A recipe is either `<name>.toml`, with the template in `prompt`, or
+/// `<name>.md`, whose body is the template and whose other settings go in
+/// an optional TOML front matter between `+++` lines.
+#[derive(Debug, Clone)]
+pub struct Recipe {
+    pub name: String,
+    pub path: PathBuf,
+    pub scope: RecipeScope,
+    pub description: Option<String>,
+    pub prompt: String,
+    /// Replaces the config's system prompt unless `--system-prompt` is given.
+    pub system_prompt: Option<String>,
+    /// Used when no paths are given on the command line.
+    pub paths: Vec<String>,
+    /// Added to the ones given on the command line.
+    pub ignore: Vec<String>,
+    /// Used when no include globs are given on the command line.
+    pub include: Vec<String>,
+    /// Added to the ones given on the command line.
+    pub exclude: Vec<String>,
+    /// Default values of the template's variables, overridden by `--var`.
+    pub vars: BTreeMap<String, String>,
+}
+
+/// The settings of a recipe file; `prompt` is the body in Markdown recipes.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RecipeFile {
+    description: Option<String>,
+    prompt: Option<String>,
+    system_prompt: Option<String>,
+    #[serde(default)]
+    paths: Vec<String>,
+    #[serde(default)]
+    ignore: Vec<String>,
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    vars: BTreeMap<String, String>,
+}
+
+impl Recipe {
+    /// Renders the template for `files`. `{{file_list}}` and `{{language}}`
+    /// are filled in from the files; `vars` override them and the recipe's
+    /// defaults.
+    pub fn render(
+        &self,
+        files: &[PathBuf],
+        vars: &BTreeMap<String, String>,
+    ) -> Result<String, AppError> {
+        let mut values = self.vars.clone();
+        values.insert("file_list".to_string(), file_list(files));
+        values.insert(
+            "language".to_string(),
+            detect_language(files).unwrap_or("text").to_string(),
+        );
+        values.extend(vars.iter().map(|(k, v)| (k.clone(), v.clone())));
+        render_template(&self.prompt, &values)
+            .map_err(|e| AppError::RecipeError(format!("{}: {}", self.path.display(), e)))
+    }
+}
+
+/// Every recipe in the project and user recipe directories, sorted by name.
+/// Project recipes hide user recipes with the same name.
+pub fn list_recipes() -> Result<Vec<Recipe>, AppError> {
+    list_recipes_in(&recipe_dirs())
+}
+
+/// Finds the recipe `name`, looking in the project recipes first.
+pub fn find_recipe(name: &str) -> Result<Recipe, AppError> {
+    find_recipe_in(name, &recipe_dirs())
+}
+
+/// [`list_recipes`] over `dirs`, highest precedence first.
+fn list_recipes_in(dirs: &[(RecipeScope, PathBuf)]) -> Result<Vec<Recipe>, AppError> {
+    let mut recipes: BTreeMap<String, Recipe> = BTreeMap::new();
+    for (scope, dir) in dirs.iter().rev() {
+        for recipe in read_recipe_dir(dir, *scope)? {
+            recipes.insert(recipe.name.clone(), recipe);
+        }
+    }
+    Ok(recipes.into_values().collect())
+}
+
+/// [`find_recipe`] in `dirs`, highest precedence first.
+fn find_recipe_in(name: &str, dirs: &[(RecipeScope, PathBuf)]) -> Result<Recipe, AppError> {
+    for (scope, dir) in dirs {
+        for extension in ["toml", "md"] {
+            let path = dir.join(format!("{}.{}", name, extension));
+            if path.is_file() {
+                return read_recipe(&path, *scope);
+            }
+        }
+    }
+    Err(AppError::RecipeError(format!(
+        "No recipe named '{}' (see `press recipe list`)",
+        name
+    )))
+}
+
+/// The nearest `.press/recipes` in the current directory or one of its
+/// parents, and `recipes` next to the user config, in that order.
+pub fn recipe_dirs() -> Vec<(RecipeScope, PathBuf)> {
+    let mut dirs = Vec::new();
+    if let Some(dir) = env::current_dir().ok().and_then(|current_dir| {
+        current_dir
+            .ancestors()
+            .map(|dir| dir.join(".press").join("recipes"))
+            .find(|dir| dir.is_dir())
+    }) {
+        dirs.push((RecipeScope::Project, dir));
+    }
+    if let Some(path) = user_config_path() {
+        dirs.push((RecipeScope::User, path.with_file_name("recipes")));
+    }
+    dirs
+}
+
+/// Reads every `.toml` and `.md` recipe in `dir`, which may not exist.
+fn read_recipe_dir(dir: &Path, scope: RecipeScope) -> Result<Vec<Recipe>, AppError> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut recipes = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|ext| ext == "toml" || ext == "md")
+        {
+            recipes.push(read_recipe(&path, scope)?);
+        }
+    }
+    Ok(recipes)
+}
+
+fn read_recipe(path: &Path, scope: RecipeScope) -> Result<Recipe, AppError> {
+    let invalid = |e: String| AppError::RecipeError(format!("{}: {}", path.display(), e));
+    let contents = fs::read_to_string(path)?;
+    let recipe_file = if path.extension().is_some_and(|ext| ext == "md") {
+        let (front_matter, body) = split_front_matter(&contents).map_err(invalid)?;
+        let mut recipe_file: RecipeFile = match front_matter {
+            Some(front_matter) => {
+                toml::from_str(front_matter).map_err(|e| invalid(e.to_string()))?
+            }
+            None => RecipeFile::default(),
+        };
+        if recipe_file.prompt.is_some() {
+            return Err(invalid(
+                "the prompt of a Markdown recipe is its body, not a front matter key".to_string(),
+            ));
+        }
+        recipe_file.prompt = Some(body.trim().to_string());
+        recipe_file
+    } else {
+        toml::from_str(&contents).map_err(|e| invalid(e.to_string()))?
+    };
+
+    let prompt = recipe_file
+        .prompt
+        .filter(|prompt| !prompt.trim().is_empty())
+        .ok_or_else(|| invalid("the recipe has no prompt".to_string()))?;
+    Ok(Recipe {
+        name: path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        path: path.to_path_buf(),
+        scope,
+        description: recipe_file.description,
+        prompt,
+        system_prompt: recipe_file.system_prompt,
+        paths: recipe_file.paths,
+        ignore: recipe_file.ignore,
+        include: recipe_file.include,
+        exclude: recipe_file.exclude,
+        vars: recipe_file.vars,
+    })
+}
+
+/// Splits a `+++`-delimited TOML front matter off the start of a Markdown
+/// file. A front matter that is never closed is an error, rather than
+/// becoming part of the prompt.
+fn split_front_matter(contents: &str) -> Result<(Option<&str>, &str), String> {
+    let Some(rest) = contents
+        .strip_prefix("+++\n")
+        .or_else(|| contents.strip_prefix("+++\r\n"))
+    else {
+        return Ok((None, contents));
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "+++" {
+            return Ok((Some(&rest[..offset]), &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    Err("the front matter is missing its closing '+++' line".to_string())
+}
+
+/// Replaces every `{{name}}` in `template` with its value. Unknown variables
+/// are an error, so a typo doesn't reach the model.
+pub fn render_template(
+    template: &str,
+    values: &BTreeMap<String, String>,
+) -> Result<String, String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| "unclosed '{{' in the prompt".to_string())?;
+        let name = after[..end].trim();
+        let value = values.get(name).ok_or_else(|| {
+            format!(
+                "unknown variable '{}', pass it with --var {}=VALUE",
+                name, name
+            )
+        })?;
+        rendered.push_str(value);
+        rest = &after[end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// The paths of `files`, comma-separated.
+fn file_list(files: &[PathBuf]) -> String {
+    files
+        .iter()
+        .map(|file| file.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The most common language among `files`, by extension.
+pub fn detect_language(files: &[PathBuf]) -> Option<&'static str> {
+    let mut counts: BTreeMap<&'static str, usize> = BTreeMap::new();
+    for file in files {
+        if let Some(language) = file
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(language_of)
+        {
+            *counts.entry(language).or_default() += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .map(|(language, _)| language)
+}
+
+fn language_of(extension: &str) -> Option<&'static str> {
+    let language = match extension.to_lowercase().as_str() {
+        "rs" => "Rust",
+        "py" | "pyi" => "Python",
+        "js" | "jsx" | "mjs" | "cjs" => "JavaScript",
+        "ts" | "tsx" => "TypeScript",
+        "go" => "Go",
+        "java" => "Java",
+        "kt" | "kts" => "Kotlin",
+        "scala" => "Scala",
+        "swift" => "Swift",
+        "c" | "h" => "C",
+        "cc" | "cpp" | "hpp" => "C++",
+        "cs" => "C#",
+        "php" => "PHP",
+        "rb" => "Ruby",
+        "dart" => "Dart",
+        "lua" => "Lua",
+        "sh" | "bash" => "Shell",
+        _ => return None,
+    };
+    Some(language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn front_matter_is_split_off_with_either_line_ending() {
+        assert_eq!(
+            split_front_matter("+++\ndescription = \"d\"\n+++\nBody\n"),
+            Ok((Some("description = \"d\"\n"), "Body\n"))
+        );
+        assert_eq!(
+            split_front_matter("+++\r\ndescription = \"d\"\r\n+++\r\nBody\r\n"),
+            Ok((Some("description = \"d\"\r\n"), "Body\r\n"))
+        );
+        assert_eq!(split_front_matter("+++\n+++"), Ok((Some(""), "")));
+        // Only a front matter at the very start counts
+        assert_eq!(
+            split_front_matter("Body\n+++\nx = 1\n+++\n"),
+            Ok((None, "Body\n+++\nx = 1\n+++\n"))
+        );
+    }
+
+    #[test]
+    fn an_unclosed_front_matter_is_an_error() {
+        assert!(split_front_matter("+++\ndescription = \"d\"\nBody\n").is_err());
+        assert!(split_front_matter("+++\r\n").is_err());
+    }
+
+    #[test]
+    fn templates_are_rendered_with_their_variables() {
+        let values = values(&[("name", "press"), ("braces", "{{name}}")]);
+        assert_eq!(
+            render_template("Hi {{name}}, {{ name }}!", &values).unwrap(),
+            "Hi press, press!"
+        );
+        // Values aren't rendered again
+        assert_eq!(render_template("{{braces}}", &values).unwrap(), "{{name}}");
+        assert_eq!(
+            render_template("no variables", &values).unwrap(),
+            "no variables"
+        );
+    }
+
+    #[test]
+    fn unknown_and_unclosed_variables_are_errors() {
+        let values = values(&[("name", "press")]);
+        let unknown = render_template("Hi {{nmae}}", &values).unwrap_err();
+        assert!(unknown.contains("nmae"), "{}", unknown);
+        assert!(render_template("Hi {{name", &values).is_err());
+        assert!(render_template("Hi {{name}} {{", &values).is_err());
+    }
+
+    #[test]
+    fn project_recipes_shadow_user_recipes() {
+        let project = tempfile::tempdir().unwrap();
+        let user = tempfile::tempdir().unwrap();
+        fs::write(project.path().join("docs.md"), "Project docs").unwrap();
+        fs::write(
+            user.path().join("docs.toml"),
+            "prompt = \"User docs\"\ndescription = \"d\"",
+        )
+        .unwrap();
+        fs::write(
+            user.path().join("tests.md"),
+            "+++\npaths = [\"src\"]\n+++\nWrite tests for {{language}}\n",
+        )
+        .unwrap();
+        fs::write(user.path().join("notes.txt"), "not a recipe").unwrap();
+        let dirs = [
+            (RecipeScope::Project, project.path().to_path_buf()),
+            (RecipeScope::User, user.path().to_path_buf()),
+        ];
+
+        let recipes: Vec<(String, RecipeScope, String)> = list_recipes_in(&dirs)
+            .unwrap()
+            .into_iter()
+            .map(|recipe| (recipe.name, recipe.scope, recipe.prompt))
+            .collect();
+        assert_eq!(
+            recipes,
+            [
+                (
+                    "docs".to_string(),
+                    RecipeScope::Project,
+                    "Project docs".to_string()
+                ),
+                (
+                    "tests".to_string(),
+                    RecipeScope::User,
+                    "Write tests for {{language}}".to_string()
+                ),
+            ]
+        );
+
+        let docs = find_recipe_in("docs", &dirs).unwrap();
+        assert_eq!(docs.scope, RecipeScope::Project);
+        let tests = find_recipe_in("tests", &dirs).unwrap();
+        assert_eq!(tests.paths, ["src"]);
+        assert_eq!(
+            tests
+                .render(&[PathBuf::from("src/lib.rs")], &BTreeMap::new())
+                .unwrap(),
+            "Write tests for Rust"
+        );
+        assert!(matches!(
+            find_recipe_in("missing", &dirs),
+            Err(AppError::RecipeError(_))
+        ));
+    }
+
+    #[test]
+    fn broken_recipes_are_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let dirs = [(RecipeScope::User, dir.path().to_path_buf())];
+        for (file, contents) in [
+            ("unclosed.md", "+++\ndescription = \"d\"\nBody\n"),
+            ("prompt_key.md", "+++\nprompt = \"p\"\n+++\nBody\n"),
+            ("empty.md", "+++\ndescription = \"d\"\n+++\n\n"),
+            ("unknown_key.toml", "prompt = \"p\"\nmodel = \"m\"\n"),
+        ] {
+            fs::write(dir.path().join(file), contents).unwrap();
+            let name = file.split('.').next().unwrap();
+            assert!(
+                matches!(find_recipe_in(name, &dirs), Err(AppError::RecipeError(_))),
+                "{}",
+                file
+            );
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use crate::errors::AppError;
+use std::process::Command;
+
+/// Most characters of command output sent back to the model. Build and test
+/// failures usually show up at the end, so the start is dropped.
+const MAX_OUTPUT_CHARS: usize = 8_000;
+
+/// Result of running the verification command.
+pub struct VerifyOutcome {
+    pub success: bool,
+    /// Combined stdout and stderr, truncated to the last `MAX_OUTPUT_CHARS`.
+    pub output: String,
+}
+
+/// Runs `command` through the platform's shell in the current directory.
+pub fn run_verify_command(command: &str) -> Result<VerifyOutcome, AppError> {
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).output()
+    } else {
+        Command::new("sh").args(["-c", command]).output()
+    }
+    .map_err(|e| AppError::VerificationFailed(format!("could not run `{}`: {}", command, e)))?;
+
+    let mut combined = String::from_utf8_lossy(&output.stdout).to_string();
+    combined.push_str(&String::from_utf8_lossy(&output.stderr));
+
+    Ok(VerifyOutcome {
+        success: output.status.success(),
+        output: tail(&combined, MAX_OUTPUT_CHARS),
+    })
+}
+
+/// Builds the prompt of a fix round from the original prompt and the output
+/// of the failed command.
+pub fn feedback_prompt(prompt: &str, command: &str, output: &str) -> String {
+    format!(
+        "{}\n\nThe changes for this request have been applied, but running `{}` \
+         failed with the output below. The files show their current state. \
+         Fix the problems so the command succeeds.\n\n{}",
+        prompt,
+        command,
+        output.trim_end()
+    )
+}
+
+/// The last `max_chars` characters of `text`.
+fn tail(text: &str, max_chars: usize) -> String {
+    let char_count = text.chars().count();
+    if char_count <= max_chars {
+        return text.to_string();
+    }
+    let truncated: String = text.chars().skip(char_count - max_chars).collect();
+    format!("[... output truncated ...]\n{}", truncated)
+}